@@ -0,0 +1,108 @@
+//! Compares [`NonblockingRead::read_until_block()`], which allocates on every call, with
+//! [`NonblockingRead::read_until_block_into()`], which reuses its buffers, for a writer that
+//! emits many small writes.
+//!
+//! Allocations per iteration are counted with a wrapping global allocator, and printed
+//! alongside the usual timing results with `cargo bench -- --nocapture`.
+
+#![feature(test)]
+
+extern crate test;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use nix::fcntl::OFlag;
+use test::Bencher;
+
+use floatty::poller::{NonblockingRead, DEFAULT_BUFFER_SIZE};
+use floatty::{DataBuf, DataBufExt};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc
+{
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8
+	{
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		unsafe { System.alloc(layout) }
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout)
+	{
+		unsafe { System.dealloc(ptr, layout) }
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8
+	{
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		unsafe { System.realloc(ptr, layout, new_size) }
+	}
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// How many small writes each "event" consists of.
+const WRITES_PER_EVENT: usize = 64;
+const WRITE: &[u8] = b"a small line of output\n";
+
+fn nonblocking_pipe() -> (File, File)
+{
+	let (read_end, write_end) = nix::unistd::pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC)
+		.expect("creating pipe for benchmark");
+
+	(File::from(read_end), File::from(write_end))
+}
+
+fn emit_small_writes(writer: &mut File)
+{
+	for _ in 0..WRITES_PER_EVENT {
+		writer.write_all(WRITE).unwrap();
+	}
+}
+
+fn report_allocations(name: &str, iterations: usize, before: usize)
+{
+	let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+	eprintln!("{name}: {:.2} allocations per event", allocations as f64 / iterations as f64);
+}
+
+#[bench]
+fn allocating(b: &mut Bencher)
+{
+	let (mut reader, mut writer) = nonblocking_pipe();
+
+	let mut iterations = 0;
+	let before = ALLOCATIONS.load(Ordering::Relaxed);
+	b.iter(|| {
+		emit_small_writes(&mut writer);
+		let data = reader.read_until_block().unwrap();
+		assert_eq!(data.len(), WRITE.len() * WRITES_PER_EVENT);
+		iterations += 1;
+	});
+	report_allocations("allocating", iterations, before);
+}
+
+#[bench]
+fn reused(b: &mut Bencher)
+{
+	let (mut reader, mut writer) = nonblocking_pipe();
+	let mut buffer = DataBuf::zeroed(DEFAULT_BUFFER_SIZE);
+	let mut data = DataBuf::new();
+
+	let mut iterations = 0;
+	let before = ALLOCATIONS.load(Ordering::Relaxed);
+	b.iter(|| {
+		emit_small_writes(&mut writer);
+		data.clear();
+		let count = reader.read_until_block_into(&mut buffer, &mut data).unwrap();
+		assert_eq!(count, WRITE.len() * WRITES_PER_EVENT);
+		iterations += 1;
+	});
+	report_allocations("reused", iterations, before);
+}
@@ -0,0 +1,30 @@
+//! Build information for `floatty --version`.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+fn main()
+{
+	// Only build scripts get told the target, so pass it along.
+	let target = env::var("TARGET").expect("cargo always sets TARGET for build scripts");
+	println!("cargo:rustc-env=FLOATTY_BUILD_TARGET={target}");
+
+	// Not every build has a git checkout (like from a tarball or in Nix), so this is optional.
+	if !Path::new(".git").exists() {
+		return;
+	}
+	println!("cargo:rerun-if-changed=.git/HEAD");
+	println!("cargo:rerun-if-changed=.git/refs");
+
+	let output = Command::new("git")
+		.args(["rev-parse", "--short", "HEAD"])
+		.output();
+	match output {
+		Ok(output) if output.status.success() => {
+			let hash = String::from_utf8_lossy(&output.stdout);
+			println!("cargo:rustc-env=FLOATTY_GIT_HASH={}", hash.trim());
+		},
+		_ => (),
+	}
+}
@@ -0,0 +1,61 @@
+//! Runs programs under a PTY without showing their output, and checks what they printed, colors
+//! and all, the way a test of a program's terminal output would, including one that prints far
+//! more than is worth keeping.
+//!
+//! Run with `cargo run --example capture`; it panics on the first thing that doesn't work.
+
+use floatty::parent::{CaptureLimit, ChildOutcome, OverLimit};
+use floatty::Session;
+
+/// Much more than [`LIMIT`], as fast as the child can write it.
+const FLOOD: &str = "yes | head -c 10000000";
+const LIMIT: usize = 64 * 1024;
+
+fn main() -> miette::Result<()>
+{
+	let (outcome, captured) = Session::new("printf")
+		.arg(r"\033[31mred\033[0m\n")
+		.stream(false)
+		.spawn()?
+		.wait_and_capture()?;
+
+	assert_eq!(outcome, ChildOutcome::Exited(0));
+	// The line discipline turns the newline into CR LF, like a real terminal would.
+	assert_eq!(captured.output, b"\x1b[31mred\x1b[0m\r\n");
+	assert!(!captured.truncated);
+	println!("captured: {:?}", String::from_utf8_lossy(&captured.output));
+
+	let (outcome, captured) = Session::new("sh")
+		.args(["-c", "echo out; exit 3"])
+		.stream(false)
+		.spawn()?
+		.wait_and_capture()?;
+	assert_eq!(outcome, ChildOutcome::Exited(3));
+	assert_eq!(captured.output, b"out\r\n");
+	println!("captured: {:?}", String::from_utf8_lossy(&captured.output));
+
+	let (outcome, captured) = Session::new("sh")
+		.args(["-c", FLOOD])
+		.stream(false)
+		.capture_limit(CaptureLimit { bytes: LIMIT, over_limit: OverLimit::Truncate })
+		.spawn()?
+		.wait_and_capture()?;
+	assert_eq!(outcome, ChildOutcome::Exited(0));
+	assert_eq!(captured.output.len(), LIMIT);
+	assert!(captured.truncated);
+	assert!(captured.output.starts_with(b"y\r\ny\r\n"));
+	println!("truncated to {} bytes", captured.output.len());
+
+	let result = Session::new("sh")
+		.args(["-c", FLOOD])
+		.stream(false)
+		.capture_limit(CaptureLimit { bytes: LIMIT, over_limit: OverLimit::Fail })
+		.spawn()?
+		.wait_and_capture();
+	let error = result.expect_err("child went past the capture limit without failing");
+	println!("failed: {error}");
+
+	println!("ok");
+
+	Ok(())
+}
@@ -0,0 +1,71 @@
+//! Polls something other than a PTY with floatty's [`Poller`], as a quick check that it works
+//! as a general utility: a pipe registered straight from its `OwnedFd`, a socket read through its
+//! own `Read` impl, and the poller nested in another loop through its own file descriptor.
+//!
+//! Run with `cargo run --example poller`; it panics on the first thing that doesn't work.
+
+use std::ffi::c_int;
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use floatty::fdops::FdOps;
+use floatty::poller::{PollInterest, Poller};
+
+/// Whether `fd` becomes readable within `timeout_ms`, according to plain `poll()`.
+fn readable(fd: RawFd, timeout_ms: c_int) -> bool
+{
+	let mut pollfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+	// SAFETY: `pollfd` is valid for the one entry we say it has.
+	let ready = unsafe { libc::poll(&raw mut pollfd, 1, timeout_ms) };
+	assert!(ready >= 0, "poll() failed: {}", io::Error::last_os_error());
+
+	ready > 0
+}
+
+fn main() -> miette::Result<()>
+{
+	let (reader, mut writer) = io::pipe().expect("opening pipe");
+	reader.as_fd().set_nonblocking();
+	let reader_fd = reader.as_raw_fd();
+
+	let mut poller = Poller::with_sources([PollInterest::read(reader)])?;
+	assert_eq!(poller.source_fds().collect::<Vec<_>>(), [reader_fd]);
+
+	// Nothing's ready yet, so the poller's own file descriptor shouldn't be readable either.
+	assert!(!readable(poller.as_raw_fd(), 0));
+	writer.write_all(b"through the pipe").unwrap();
+	assert!(readable(poller.as_raw_fd(), 100), "poller's file descriptor isn't readable");
+
+	let mut got = Vec::new();
+	poller.each(|event, data| {
+		assert_eq!(event.key, reader_fd);
+		got.extend_from_slice(data);
+		ControlFlow::Break(())
+	})?;
+	assert_eq!(got, b"through the pipe");
+	println!("pipe: {:?}", String::from_utf8_lossy(&got));
+
+	poller.remove_source(reader_fd)?;
+	assert_eq!(poller.source_fds().count(), 0);
+
+	// Sockets are read with their own `Read` impl, not as a `File`.
+	let (ours, mut theirs) = UnixStream::pair().expect("opening socket pair");
+	ours.set_nonblocking(true).expect("making socket nonblocking");
+	let ours_fd = ours.as_raw_fd();
+	poller.add_source(PollInterest::new(ours, true, false))?;
+	theirs.write_all(b"through the socket").unwrap();
+	let mut got = Vec::new();
+	poller.each(|event, data| {
+		assert_eq!(event.key, ours_fd);
+		got.extend_from_slice(data);
+		ControlFlow::Break(())
+	})?;
+	assert_eq!(got, b"through the socket");
+	println!("socket: {:?}", String::from_utf8_lossy(&got));
+
+	println!("ok");
+
+	Ok(())
+}
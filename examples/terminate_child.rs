@@ -0,0 +1,53 @@
+//! Checks that [`terminate_child()`] gets rid of children whether or not they listen to
+//! `SIGTERM`: one that exits when asked, and one that ignores it and has to be `SIGKILL`ed.
+//!
+//! Run with `cargo run --example terminate_child`; it panics on the first thing that doesn't work.
+
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+
+use floatty::parent::terminate_child;
+
+/// Start `script` in its own process group, like a session leader would be.
+///
+/// The [`Child`](std::process::Child) is dropped without waiting, since that's
+/// [`terminate_child()`]'s job.
+#[allow(clippy::zombie_processes)]
+fn spawn(script: &str) -> Pid
+{
+	let child = Command::new("sh")
+		.args(["-c", script])
+		.process_group(0)
+		.spawn()
+		.expect("spawning sh");
+
+	Pid::from_raw(child.id() as i32)
+}
+
+fn main()
+{
+	let grace = Duration::from_millis(500);
+
+	let polite = spawn("exec sleep 10");
+	let started = Instant::now();
+	let status = terminate_child(polite, grace).expect("terminating polite child");
+	assert_eq!(status, WaitStatus::Signaled(polite, Signal::SIGTERM, false));
+	assert!(started.elapsed() < grace, "waited out the grace period for a child that exited");
+	println!("polite child: {status:?} after {:?}", started.elapsed());
+
+	// Give the shell a moment to install the trap before we signal it.
+	let stubborn = spawn("trap '' TERM; while :; do sleep 0.05; done");
+	std::thread::sleep(Duration::from_millis(100));
+	let started = Instant::now();
+	let status = terminate_child(stubborn, grace).expect("terminating stubborn child");
+	assert_eq!(status, WaitStatus::Signaled(stubborn, Signal::SIGKILL, false));
+	assert!(started.elapsed() >= grace, "SIGKILLed before the grace period was up");
+	println!("stubborn child: {status:?} after {:?}", started.elapsed());
+
+	println!("ok");
+}
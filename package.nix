@@ -15,6 +15,7 @@
 			root = ./.;
 			fileset = lib.fileset.unions [
 				./src
+				./build.rs
 				./Cargo.toml
 				./Cargo.lock
 			];
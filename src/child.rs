@@ -1,5 +1,8 @@
-use std::ffi::OsStr;
-use std::path::Path;
+use std::env;
+use std::error::Error as StdError;
+use std::ffi::{c_uint, OsStr, OsString};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
 use std::process::Command;
 use std::io;
 use std::os::fd::{AsFd, AsRawFd, OwnedFd, RawFd};
@@ -17,24 +20,226 @@ use {
 
 use crate::pty::csctty;
 
-pub fn child_process(prog: Box<Path>, args: Box<[Box<OsStr>]>, our_pty: OwnedFd) -> miette::Result<()>
+/// What we set `TERM` to for the child if it wouldn't otherwise have one.
+///
+/// The child is connected to a real PTY, so advertising a capable terminal is correct.
+pub const DEFAULT_TERM: &str = "xterm-256color";
+
+/// What the child exits with if the program doesn't exist, like shells do.
+pub const NOT_FOUND_CODE: i32 = 127;
+/// What the child exits with if the program exists but can't be executed, like shells do.
+pub const NOT_EXECUTABLE_CODE: i32 = 126;
+/// What the child exits with if anything before `exec()` fails.
+pub const SETUP_FAILED_CODE: i32 = 1;
+
+/// `exec()`-ing the target program failed.
+#[derive(Debug, Diagnostic)]
+#[diagnostic(code(floatty::child::exec))]
+pub struct ExecError
+{
+	program: OsString,
+	source: io::Error,
+}
+
+impl ExecError
+{
+	pub fn new(program: impl Into<OsString>, source: io::Error) -> Self
+	{
+		Self {
+			program: program.into(),
+			source,
+		}
+	}
+
+	/// The exit code the child should report this with, so the parent can tell the cases apart.
+	pub fn exit_code(&self) -> i32
+	{
+		match self.source.raw_os_error() {
+			Some(libc::ENOENT) | Some(libc::ENOTDIR) => NOT_FOUND_CODE,
+			// Permission denied, a directory, not a valid executable...
+			_ => NOT_EXECUTABLE_CODE,
+		}
+	}
+}
+
+impl Display for ExecError
+{
+	fn fmt(&self, f: &mut Formatter) -> FmtResult
+	{
+		write!(f, "exec()-ing target process {}", self.program.display())
+	}
+}
+
+impl StdError for ExecError
+{
+	fn source(&self) -> Option<&(dyn StdError + 'static)>
+	{
+		Some(&self.source)
+	}
+}
+
+/// Whether `command` will be executed with a non-empty `TERM`, taking into account
+/// whether it inherits our environment at all.
+fn has_term(command: &Command, inherits_env: bool) -> bool
+{
+	let term = OsStr::new("TERM");
+	let explicit = command.get_envs().find(|&(key, _val)| key == term);
+
+	match explicit {
+		Some((_key, Some(val))) => !val.is_empty(),
+		// Explicitly removed.
+		Some((_key, None)) => false,
+		None if inherits_env => env::var_os(term).is_some_and(|val| !val.is_empty()),
+		None => false,
+	}
+}
+
+/// Which of the child's stdio file descriptors are connected to its PTY. Whichever aren't are
+/// left as the child inherited them from us.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct StdioConfig
+{
+	/// Whether the child's stdin is the PTY. Defaults to `true`.
+	///
+	/// Otherwise, it reads our stdin directly, for things like a program that takes data on
+	/// stdin while still writing to a terminal.
+	pub stdin: bool,
+	/// Whether the child's stderr is the PTY. Defaults to `true`.
+	///
+	/// Otherwise, it writes to our stderr directly, so its errors can be captured separately
+	/// from its output, which still goes through the PTY. Note that this means whether the
+	/// child sees a terminal on stderr depends on whether *our* stderr is one, whatever its
+	/// stdout is, and that its errors won't be in any [`Recording`](crate::parent::Recording).
+	pub stderr: bool,
+}
+
+impl Default for StdioConfig
+{
+	fn default() -> Self
+	{
+		Self {
+			stdin: true,
+			stderr: true,
+		}
+	}
+}
+
+/// The first file descriptor after stdio.
+const FIRST_UNNEEDED_FD: RawFd = 3;
+
+/// Mark every file descriptor after stdio close-on-exec, so nothing else we have open leaks into
+/// the program, whether we opened it ourselves before forking, or inherited it from whatever
+/// started us.
+fn cloexec_unneeded_fds()
+{
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	{
+		// `close_range()` only got a libc wrapper recently, and not in every libc.
+		// SAFETY: this only changes file descriptor flags, and doesn't touch memory.
+		let code = unsafe {
+			libc::syscall(
+				libc::SYS_close_range,
+				FIRST_UNNEEDED_FD as c_uint,
+				c_uint::MAX,
+				libc::CLOSE_RANGE_CLOEXEC,
+			)
+		};
+		if code == 0 {
+			trace!("marked fds {FIRST_UNNEEDED_FD} and up close-on-exec with close_range()");
+			return;
+		}
+		// Linux before 5.11 doesn't support `CLOSE_RANGE_CLOEXEC`, or `close_range()` at all.
+		debug!("close_range() failed: {}; going through /dev/fd instead", Errno::last());
+	}
+
+	// Collected first, since reading the directory opens a file descriptor of its own.
+	let fds: Vec<RawFd> = match fs::read_dir("/dev/fd") {
+		Ok(entries) => entries
+			.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+			.filter(|&fd| fd >= FIRST_UNNEEDED_FD)
+			.collect(),
+		Err(e) => {
+			warn!("couldn't list open file descriptors, so they may leak into the child: {e}");
+			return;
+		},
+	};
+
+	use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+	for fd in fds {
+		match fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)) {
+			Ok(_) => (),
+			// Like the one `read_dir()` had open.
+			Err(Errno::EBADF) => (),
+			Err(e) => warn!("couldn't mark fd {fd} close-on-exec: {e}"),
+		}
+	}
+}
+
+/// Give the program the signal state it would have had if we weren't in the way: nothing
+/// blocked, and `SIGPIPE` back to its default, which the Rust runtime ignores for us.
+///
+/// The parent loop blocks the signals it reads from its signalfd, and a library caller may have
+/// its own blocked, none of which the program should inherit. Anything
+/// else ignored is left ignored, since that was presumably whoever started us asking for it,
+/// like `nohup` does for `SIGHUP`.
+fn reset_signals() -> miette::Result<()>
+{
+	use nix::sys::signal::{signal, sigprocmask, SigHandler, SigSet, SigmaskHow, Signal};
+
+	sigprocmask(SigmaskHow::SIG_SETMASK, Some(&SigSet::empty()), None)
+		.into_diagnostic()
+		.context("unblocking all signals")?;
+
+	// SAFETY: `SIG_DFL` isn't a handler, so there's nothing to be async-signal-safe.
+	unsafe { signal(Signal::SIGPIPE, SigHandler::SigDfl) }
+		.into_diagnostic()
+		.context("resetting SIGPIPE to its default action")?;
+
+	Ok(())
+}
+
+/// Set up stdio for `command` to be `our_pty`, per `stdio`, and exec it. Only returns on error.
+///
+/// If `controlling` is set, `our_pty` also becomes the controlling terminal of a new session.
+/// `inherits_env` should be false if `command`'s environment was cleared, so we know
+/// whether it will get our `TERM`.
+pub fn child_process(
+	command: &mut Command,
+	our_pty: OwnedFd,
+	controlling: bool,
+	inherits_env: bool,
+	stdio: StdioConfig,
+) -> miette::Result<()>
 {
 	// Become a session leader...
 	let pgid = nix::unistd::setsid().into_diagnostic()?;
 	debug!("became session leader of new session {pgid}");
 
 	// ...and take our terminal as this session's terminal.
-	csctty(our_pty.as_fd())?;
+	if controlling {
+		csctty(our_pty.as_fd())?;
+	}
 
 	// Set stdio file descrptors for this child process to the pty.
-	// TODO: should this also set stdin?
 	let stdin_fileno = io::stdin().as_raw_fd();
 	let stdout_fileno = io::stdout().as_raw_fd();
 	let stderr_fileno = io::stderr().as_raw_fd();
 
 	let pty_raw: RawFd = our_pty.as_raw_fd();
 
-	for fileno in [stdin_fileno, stdout_fileno, stderr_fileno] {
+	let mut filenos = vec![stdout_fileno];
+	if stdio.stdin {
+		filenos.push(stdin_fileno);
+	} else {
+		debug!("leaving child's stdin as ours");
+	}
+	if stdio.stderr {
+		filenos.push(stderr_fileno);
+	} else {
+		debug!("leaving child's stderr as ours");
+	}
+
+	for fileno in filenos {
 		nix::unistd::dup2(pty_raw, fileno)
 			.into_diagnostic()
 			.with_context(|| format!("setting stdio fd {fileno} to pty fd {pty_raw}"))?;
@@ -43,11 +248,29 @@ pub fn child_process(prog: Box<Path>, args: Box<[Box<OsStr>]>, our_pty: OwnedFd)
 	// I totally don't get why this is here but all the PTY code we've found does this.
 	drop(our_pty);
 
-	let err = Command::new(prog.as_ref())
-		.args(args)
-		.exec();
+	// Only stdio is the program's business.
+	cloexec_unneeded_fds();
+	reset_signals()?;
 
-	Err(err)
-		.into_diagnostic()
-		.with_context(|| format!("exec()-ing target process {}", prog.display()))
+	if !has_term(command, inherits_env) {
+		debug!("child has no TERM; defaulting to {DEFAULT_TERM}");
+		command.env("TERM", DEFAULT_TERM);
+	}
+
+	// Command would do this for us, but wouldn't tell us which directory it failed on.
+	if let Some(dir) = command.get_current_dir() {
+		let dir = dir.to_owned();
+		nix::unistd::chdir(&dir)
+			.into_diagnostic()
+			.with_context(|| format!("changing to working directory {}", dir.display()))?;
+		// And now that we're there, don't let Command resolve a relative path a second time.
+		command.current_dir(".");
+	}
+
+	let source = command.exec();
+
+	Err(ExecError {
+		program: command.get_program().to_owned(),
+		source,
+	})?
 }
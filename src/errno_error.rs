@@ -0,0 +1,258 @@
+//! The shared shape of our errno-backed error types.
+
+use std::hash::{Hash, Hasher};
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use nix::errno::Errno;
+
+/// One past the largest errno value Linux defines (`EHWPOISON`).
+const ERRNO_COUNT: usize = 134;
+
+/// Every [`Errno`], indexed by its raw value, so we can hand out `&'static` references to them.
+static ERRNOS: [Errno; ERRNO_COUNT] = {
+	let mut errnos = [Errno::UnknownErrno; ERRNO_COUNT];
+	let mut raw = 0;
+	while raw < ERRNO_COUNT {
+		errnos[raw] = Errno::from_raw(raw as i32);
+		raw += 1;
+	}
+	errnos
+};
+
+/// An error code that a syscall wasn't documented to return, kept around as the `Other` variant
+/// of the error types here instead of being treated as impossible.
+///
+/// This only exists because [`Errno`] doesn't implement [`Hash`], or serde's traits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OtherErrno(pub Errno);
+
+impl OtherErrno
+{
+	pub const fn errno(self) -> Errno
+	{
+		self.0
+	}
+}
+
+impl Hash for OtherErrno
+{
+	fn hash<H: Hasher>(&self, state: &mut H)
+	{
+		(self.0 as i32).hash(state);
+	}
+}
+
+impl From<Errno> for OtherErrno
+{
+	fn from(other: Errno) -> OtherErrno
+	{
+		OtherErrno(other)
+	}
+}
+
+impl From<OtherErrno> for Errno
+{
+	fn from(other: OtherErrno) -> Errno
+	{
+		other.0
+	}
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for OtherErrno
+{
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	{
+		serializer.serialize_i32(self.0 as i32)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for OtherErrno
+{
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+	{
+		let raw = <i32 as serde::Deserialize>::deserialize(deserializer)?;
+
+		Ok(OtherErrno(Errno::from_raw(raw)))
+	}
+}
+
+/// Returns a `&'static` reference to the same error code as `errno`.
+pub(crate) const fn errno_ref(errno: Errno) -> &'static Errno
+{
+	let raw = errno as usize;
+	if raw < ERRNO_COUNT && ERRNOS[raw] as i32 == errno as i32 {
+		&ERRNOS[raw]
+	} else {
+		&Errno::UnknownErrno
+	}
+}
+
+/// An error type which is just a more descriptive view of the error codes a particular syscall
+/// can fail with.
+///
+/// Implementors provide the mapping to and from [`Errno`] and a description for each variant as
+/// inherent methods (`try_from_raw()` and `to_errno()` as `const fn`s, and `desc()`), plus an
+/// `Other(OtherErrno)` variant. `impl_errno_error!` then implements this trait in terms of
+/// those, along with `Display`, `Error`, the `From` conversions, and inherent `from_errno()` and
+/// `as_errno()` forwarders, so callers don't need this trait in scope.
+///
+/// `desc()` isn't a `const fn`, because `Other` errors use [`Errno::desc()`], which isn't either.
+/// `from_errno()` never panics; undocumented codes are logged and kept as `Other`.
+pub trait ErrnoError: Copy + Sized + 'static
+{
+	/// The syscall this error type is for, as it should appear in messages.
+	const SYSCALL: &'static str;
+
+	/// Returns `None` if `raw` is not an error code this syscall is documented to return.
+	fn try_from_raw(raw: Errno) -> Option<Self>;
+
+	/// Wrap an error code this syscall isn't documented to return.
+	fn other(raw: Errno) -> Self;
+
+	fn to_errno(self) -> Errno;
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	fn desc(self) -> &'static str;
+
+	/// Like [`ErrnoError::try_from_raw()`], but undocumented error codes are kept as the
+	/// `Other` variant.
+	fn from_errno(raw: Errno) -> Self
+	{
+		match Self::try_from_raw(raw) {
+			Some(err) => err,
+			None => {
+				warn!("{} gave undocumented error code {raw}", Self::SYSCALL);
+				Self::other(raw)
+			}
+		}
+	}
+
+	fn as_errno(self) -> &'static Errno
+	{
+		errno_ref(self.to_errno())
+	}
+}
+
+/// Implement [`ErrnoError`] for an error type with inherent `try_from_raw()`, `to_errno()`, and
+/// `desc()` methods and an `Other(OtherErrno)` variant, along with `Display`, `Error`, the `From`
+/// conversions to and from [`Errno`], and inherent `from_errno()` and `as_errno()`.
+macro_rules! impl_errno_error {
+	($error:ty, $syscall:literal) => {
+		impl $crate::ErrnoError for $error
+		{
+			const SYSCALL: &'static str = $syscall;
+
+			fn try_from_raw(raw: ::nix::errno::Errno) -> Option<Self>
+			{
+				<$error>::try_from_raw(raw)
+			}
+
+			fn other(raw: ::nix::errno::Errno) -> Self
+			{
+				Self::Other($crate::OtherErrno(raw))
+			}
+
+			fn to_errno(self) -> ::nix::errno::Errno
+			{
+				<$error>::to_errno(self)
+			}
+
+			fn desc(self) -> &'static str
+			{
+				<$error>::desc(self)
+			}
+		}
+
+		impl $error
+		{
+			/// Like `try_from_raw()`, but undocumented error codes are logged and kept as the
+			/// `Other` variant.
+			pub fn from_errno(raw: ::nix::errno::Errno) -> Self
+			{
+				<$error as $crate::ErrnoError>::from_errno(raw)
+			}
+
+			pub const fn as_errno(self) -> &'static ::nix::errno::Errno
+			{
+				$crate::errno_error::errno_ref(self.to_errno())
+			}
+		}
+
+		impl ::std::fmt::Display for $error
+		{
+			fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result
+			{
+				let description: &'static str = self.desc();
+				f.write_str(description)?;
+
+				Ok(())
+			}
+		}
+
+		impl ::std::error::Error for $error
+		{
+			fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)>
+			{
+				// We can actually reconstruct the source error trivially,
+				// so we don't even need to store it.
+				let nix_error: &'static ::nix::errno::Errno = self.as_errno();
+
+				Some(nix_error)
+			}
+		}
+
+		impl From<::nix::errno::Errno> for $error
+		{
+			fn from(other: ::nix::errno::Errno) -> $error
+			{
+				<$error>::from_errno(other)
+			}
+		}
+
+		impl From<$error> for ::nix::errno::Errno
+		{
+			fn from(other: $error) -> ::nix::errno::Errno
+			{
+				<$error>::to_errno(other)
+			}
+		}
+	};
+}
+pub(crate) use impl_errno_error;
+
+#[cfg(test)]
+mod tests
+{
+	use nix::errno::Errno;
+
+	use crate::pty::GrantptError;
+	use crate::OtherErrno;
+
+	// The forwarders are still usable in const contexts, like they were before the trait.
+	const NOT_A_PTY: Option<GrantptError> = GrantptError::try_from_raw(Errno::EINVAL);
+	const NOT_A_PTY_ERRNO: &Errno = GrantptError::NotAPty.as_errno();
+
+	#[test]
+	fn const_forwarders()
+	{
+		assert_eq!(NOT_A_PTY, Some(GrantptError::NotAPty));
+		assert_eq!(*NOT_A_PTY_ERRNO, Errno::EINVAL);
+		assert_eq!(GrantptError::NotAPty.to_errno(), Errno::EINVAL);
+	}
+
+	#[test]
+	fn from_errno()
+	{
+		assert_eq!(GrantptError::from_errno(Errno::EACCES), GrantptError::InaccessibleSlave);
+		let other = GrantptError::from_errno(Errno::EIO);
+		assert_eq!(other, GrantptError::Other(OtherErrno(Errno::EIO)));
+		assert_eq!(*other.as_errno(), Errno::EIO);
+		assert_eq!(other.desc(), Errno::EIO.desc());
+	}
+}
@@ -1,24 +1,50 @@
 //! Operations on file descriptors.
 
-use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
+use std::ffi::{c_char, OsString};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
 
 #[allow(unused_imports)]
 use {
     log::{trace, debug, info, warn, error},
 	tap::prelude::*,
 };
+use bstr::ByteSlice;
+use nix::errno::Errno;
 use nix::fcntl::{FcntlArg, OFlag};
 
-/// Set file descriptor status flags.
+use crate::pty::TTY_NAME_MAX;
+use crate::{DataBuf, DataBufExt, DataExt};
+
+/// Get file descriptor status flags.
 ///
 /// Per POSIX, this cannot fail if used on a valid file descriptor.
-pub fn set_fl(fd: &BorrowedFd, flags: OFlag)
+pub fn get_fl(fd: &BorrowedFd) -> OFlag
+{
+	let raw_fd: RawFd = fd.as_raw_fd();
+
+	match nix::fcntl::fcntl(raw_fd, FcntlArg::F_GETFL) {
+		Ok(ret) => {
+			trace!("fcntl(F_GETFL) returned {ret:#o}");
+			OFlag::from_bits_retain(ret)
+		},
+		Err(errno) => {
+			// Either the kernel violated POSIX, or someone used unsafe code to give us
+			// an invalid file descriptor.
+			unreachable!("POSIX fcntl F_GETFL cannot fail, but got errno: {}", errno);
+		},
+	}
+}
+
+/// Replace *all* file descriptor status flags with `flags`, which is what `F_SETFL` does.
+fn replace_fl(fd: &BorrowedFd, flags: OFlag)
 {
 	let raw_fd: RawFd = fd.as_raw_fd();
 
 	match nix::fcntl::fcntl(raw_fd, FcntlArg::F_SETFL(flags)) {
 		Ok(ret) => {
-			trace!("fcntl(F_SETFL, O_NONBLOCK) returned {ret}");
+			trace!("fcntl(F_SETFL, {flags:?}) returned {ret}");
 		},
 		Err(errno) => {
 			// Either the kernel violated POSIX, or someone used unsafe code to give us
@@ -28,26 +54,171 @@ pub fn set_fl(fd: &BorrowedFd, flags: OFlag)
 	}
 }
 
+/// Set file descriptor status flags, in addition to the ones already set.
+///
+/// Per POSIX, this cannot fail if used on a valid file descriptor.
+pub fn set_fl(fd: &BorrowedFd, flags: OFlag)
+{
+	// F_SETFL replaces the whole flag word, so we have to include the existing ones ourselves.
+	let existing = get_fl(fd);
+	replace_fl(fd, existing | flags);
+}
+
+/// Clear file descriptor status flags, leaving the others as they are.
+///
+/// Per POSIX, this cannot fail if used on a valid file descriptor.
+pub fn clear_fl(fd: &BorrowedFd, flags: OFlag)
+{
+	let existing = get_fl(fd);
+	replace_fl(fd, existing - flags);
+}
+
+/// Whether `fd` refers to a terminal, per `isatty(3p)`.
+pub fn is_tty(fd: &BorrowedFd) -> bool
+{
+	// SAFETY: `isatty()` is safe to call on any file descriptor, even invalid ones.
+	let code = unsafe { libc::isatty(fd.as_raw_fd()) };
+	code == 1
+}
+
+/// The path of the terminal `fd` refers to, per `ttyname_r(3p)`, or `None` if it isn't one.
+///
+/// For a PTY controller, Linux gives `/dev/ptmx` here, rather than anything about its terminal
+/// child; [`ptsname()`](crate::pty::ptsname) is what gets that.
+pub fn tty_name(fd: &BorrowedFd) -> Option<Box<Path>>
+{
+	// + 1 for the NUL terminator.
+	let mut buffer = DataBuf::zeroed(*TTY_NAME_MAX + 1);
+	let buf_ptr: *mut c_char = buffer.as_c_buf_mut();
+
+	// SAFETY: `buf_ptr` is non-null, and has already been zeroed with `buffer.len()` characters.
+	let code = unsafe { libc::ttyname_r(fd.as_raw_fd(), buf_ptr, buffer.len()) };
+	// `ttyname_r()` returns the error number itself, rather than setting `errno`.
+	if code != 0 {
+		let errno = Errno::from_raw(code);
+		if errno != Errno::ENOTTY {
+			debug!("ttyname_r() on fd {} failed: {errno}", fd.as_raw_fd());
+		}
+		return None;
+	}
+
+	let nul_pos = buffer.find_byte(b'\0').unwrap_or(buffer.len());
+	buffer.truncate(nul_pos);
+
+	let path: Box<Path> = buffer
+		.pipe(OsString::from_vec)
+		.pipe(<PathBuf as From<OsString>>::from)
+		.pipe(PathBuf::into_boxed_path);
+
+	Some(path)
+}
+
 pub trait FdOps
 {
-	/// Set file descriptor status flags.
+	/// Get file descriptor status flags.
 	///
 	/// Per POSIX, this cannot fail if used on a valid file descriptor.
-	fn set_fl(&mut self, flags: OFlag);
+	fn get_fl(&self) -> OFlag;
+
+	/// Set file descriptor status flags, in addition to the ones already set.
+	///
+	/// Per POSIX, this cannot fail if used on a valid file descriptor.
+	fn set_fl(&self, flags: OFlag);
+
+	/// Clear file descriptor status flags, leaving the others as they are.
+	///
+	/// Per POSIX, this cannot fail if used on a valid file descriptor.
+	fn clear_fl(&self, flags: OFlag);
 
 	/// Set the `O_NONBLOCK` file descriptor flag.
 	///
 	/// Per POSIX, this cannot fail if used on a valid file descriptor.
-	fn set_nonblocking(&mut self)
+	fn set_nonblocking(&self)
 	{
 		self.set_fl(OFlag::O_NONBLOCK)
 	}
+
+	/// Clear the `O_NONBLOCK` file descriptor flag, leaving the others as they are.
+	///
+	/// Per POSIX, this cannot fail if used on a valid file descriptor.
+	fn set_blocking(&self)
+	{
+		self.clear_fl(OFlag::O_NONBLOCK)
+	}
+
+	/// Whether this refers to a terminal, per `isatty(3p)`.
+	fn is_tty(&self) -> bool;
+
+	/// The path of the terminal this refers to, per `ttyname_r(3p)`, or `None` if it isn't one.
+	fn tty_name(&self) -> Option<Box<Path>>;
 }
 
-impl FdOps for BorrowedFd<'_>
+/// Setting flags doesn't need exclusive access in Rust's sense, since the kernel owns them,
+/// so this works on a shared reference to anything with a file descriptor, like
+/// [`File`](std::fs::File) and [`OwnedFd`](std::os::fd::OwnedFd).
+impl<T: AsFd + ?Sized> FdOps for T
 {
-	fn set_fl(&mut self, flags: OFlag)
+	fn get_fl(&self) -> OFlag
+	{
+		get_fl(&self.as_fd())
+	}
+
+	fn set_fl(&self, flags: OFlag)
 	{
-		set_fl(&*self, flags)
+		set_fl(&self.as_fd(), flags)
+	}
+
+	fn clear_fl(&self, flags: OFlag)
+	{
+		clear_fl(&self.as_fd(), flags)
+	}
+
+	fn is_tty(&self) -> bool
+	{
+		is_tty(&self.as_fd())
+	}
+
+	fn tty_name(&self) -> Option<Box<Path>>
+	{
+		tty_name(&self.as_fd())
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::io;
+
+	use nix::fcntl::OFlag;
+
+	use super::FdOps;
+
+	#[test]
+	fn set_fl_keeps_existing()
+	{
+		let (reader, _writer) = io::pipe().unwrap();
+		reader.set_fl(OFlag::O_APPEND);
+		reader.set_nonblocking();
+		assert!(reader.get_fl().contains(OFlag::O_APPEND | OFlag::O_NONBLOCK));
+
+		reader.set_blocking();
+		let flags = reader.get_fl();
+		assert!(!flags.contains(OFlag::O_NONBLOCK));
+		assert!(flags.contains(OFlag::O_APPEND), "set_blocking() cleared O_APPEND");
+	}
+
+	#[test]
+	fn clear_fl_keeps_others()
+	{
+		let (reader, _writer) = io::pipe().unwrap();
+		reader.set_fl(OFlag::O_APPEND | OFlag::O_NONBLOCK);
+		reader.clear_fl(OFlag::O_APPEND);
+		let flags = reader.get_fl();
+		assert!(!flags.contains(OFlag::O_APPEND));
+		assert!(flags.contains(OFlag::O_NONBLOCK), "clear_fl() cleared more than it was given");
+
+		// Clearing what isn't set is fine too.
+		reader.clear_fl(OFlag::O_APPEND);
+		assert_eq!(reader.get_fl(), flags);
 	}
 }
@@ -0,0 +1,320 @@
+//! Built-in [`OutputFilter`]s.
+//!
+//! Each can be combined with the others with [`OutputFilter::then()`].
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+
+use crate::parent::OutputFilter;
+use crate::{Data, DataBuf};
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+/// Where an [`AnsiStripper`] is in an escape sequence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+enum State
+{
+	/// Not in an escape sequence.
+	#[default]
+	Ground,
+	/// Just after `ESC`.
+	Escape,
+	/// After `ESC` and some intermediate bytes, like the `(` in `ESC ( B`.
+	EscapeIntermediate,
+	/// In a control sequence, like `ESC [ 1 ; 31 m`.
+	Csi,
+	/// In an operating system command, like `ESC ] 0 ; title BEL`, which ends with `BEL` or `ST`.
+	Osc,
+	/// In a device control string or similar, which only ends with `ST`.
+	String,
+	/// After an `ESC` in an [`State::Osc`] or [`State::String`], which could be the start of `ST`.
+	StringEscape,
+}
+
+/// Removes ANSI escape sequences from a stream of bytes, leaving the plain text and other control
+/// characters, like newlines and carriage returns, alone.
+///
+/// Escape sequences are tracked across calls to [`AnsiStripper::strip()`], so it doesn't matter
+/// where the stream is split.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct AnsiStripper
+{
+	state: State,
+}
+
+impl AnsiStripper
+{
+	pub fn new() -> Self
+	{
+		Self::default()
+	}
+
+	/// Append `data` to `out`, minus any escape sequences.
+	pub fn strip(&mut self, data: &Data, out: &mut DataBuf)
+	{
+		use State::*;
+		for &byte in data {
+			self.state = match (self.state, byte) {
+				(Ground, ESC) => Escape,
+				(Ground, _) => {
+					out.push(byte);
+					Ground
+				},
+
+				(Escape, b'[') => Csi,
+				(Escape, b']') => Osc,
+				// DCS, SOS, PM, and APC are all strings terminated by ST.
+				(Escape, b'P' | b'X' | b'^' | b'_') => String,
+				(Escape | EscapeIntermediate, 0x20..=0x2f) => EscapeIntermediate,
+				(Escape | EscapeIntermediate, ESC) => Escape,
+				// The final byte, or something invalid, which ends the sequence either way.
+				(Escape | EscapeIntermediate, _) => Ground,
+
+				// Parameter and intermediate bytes.
+				(Csi, 0x20..=0x3f) => Csi,
+				(Csi, 0x40..=0x7e) => Ground,
+				// An `ESC` here cancels the sequence, and starts a new one.
+				(Csi, ESC) => Escape,
+				// Terminals still act on other control characters in the middle of a sequence.
+				(Csi, 0x00..=0x1f) => {
+					out.push(byte);
+					Csi
+				},
+				(Csi, _) => Ground,
+
+				(Osc, BEL) => Ground,
+				(Osc | String, ESC) => StringEscape,
+				(Osc, _) => Osc,
+				(String, _) => String,
+
+				// `ESC \` is ST.
+				(StringEscape, b'\\') => Ground,
+				// Anything else is the sequence getting cut off by a new one.
+				(StringEscape, b'[') => Csi,
+				(StringEscape, b']') => Osc,
+				(StringEscape, b'P' | b'X' | b'^' | b'_') => String,
+				(StringEscape, 0x20..=0x2f) => EscapeIntermediate,
+				(StringEscape, ESC) => Escape,
+				(StringEscape, _) => Ground,
+			};
+		}
+	}
+}
+
+impl From<AnsiStripper> for OutputFilter
+{
+	fn from(mut stripper: AnsiStripper) -> OutputFilter
+	{
+		OutputFilter::new(move |data: &Data, out: &mut DataBuf| stripper.strip(data, out))
+	}
+}
+
+/// Where a [`LinePrefixer`] is in the current line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+enum LinePosition
+{
+	/// Nothing's been written on this line yet, not even the prefix.
+	#[default]
+	Start,
+	/// After the prefix, and maybe more.
+	Middle,
+	/// Just after a carriage return, which could be the first half of `CR LF`, or going back to
+	/// overwrite the line, like progress bars do.
+	CarriageReturn,
+}
+
+/// Puts a prefix at the start of every line in a stream of bytes, like a label saying where it
+/// came from.
+///
+/// The prefix is only written once the first byte of a line arrives, so a chunk that ends right
+/// after a newline doesn't leave a dangling prefix. A carriage return followed by anything but a
+/// newline means the line is about to be overwritten, so the prefix is written again after it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LinePrefixer
+{
+	prefix: Box<[u8]>,
+	position: LinePosition,
+}
+
+impl LinePrefixer
+{
+	pub fn new(prefix: impl AsRef<[u8]>) -> Self
+	{
+		Self {
+			prefix: Box::from(prefix.as_ref()),
+			position: LinePosition::Start,
+		}
+	}
+
+	/// Whether the next byte will start a new line, and so get the prefix.
+	pub fn at_line_start(&self) -> bool
+	{
+		self.position == LinePosition::Start
+	}
+
+	/// Treat whatever comes next as the start of a new line, like when something else has ended
+	/// the line for us.
+	pub fn restart_line(&mut self)
+	{
+		self.position = LinePosition::Start;
+	}
+
+	/// Append `data` to `out`, with the prefix before each line.
+	pub fn prefix(&mut self, data: &Data, out: &mut DataBuf)
+	{
+		use LinePosition::*;
+		for &byte in data {
+			self.position = match (self.position, byte) {
+				(CarriageReturn, b'\n') => {
+					out.push(byte);
+					Start
+				},
+				(Start | CarriageReturn, _) => {
+					out.extend_from_slice(&self.prefix);
+					Self::after(byte, out)
+				},
+				(Middle, _) => Self::after(byte, out),
+			};
+		}
+	}
+
+	/// Append `byte`, which comes after the prefix, and return where that leaves us.
+	fn after(byte: u8, out: &mut DataBuf) -> LinePosition
+	{
+		out.push(byte);
+		match byte {
+			b'\n' => LinePosition::Start,
+			b'\r' => LinePosition::CarriageReturn,
+			_ => LinePosition::Middle,
+		}
+	}
+}
+
+impl From<LinePrefixer> for OutputFilter
+{
+	fn from(mut prefixer: LinePrefixer) -> OutputFilter
+	{
+		OutputFilter::new(move |data: &Data, out: &mut DataBuf| prefixer.prefix(data, out))
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::{AnsiStripper, LinePrefixer};
+	use crate::parent::OutputFilter;
+	use crate::DataBuf;
+
+	/// Colored text, a window title ended by `BEL` and then by `ST`, a DCS string, a charset
+	/// selection, and a newline that's mid-sequence.
+	const ESCAPED: &[u8] = b"\x1b[1;31mred\x1b[0m \x1b]0;title\x07one\x1b]2;x\x1b\\two\
+		\x1bPq#0\x1b\\ \x1b(Bthree\x1b[\n1m.";
+	const PLAIN: &[u8] = b"red onetwo three\n.";
+
+	fn strip_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> DataBuf
+	{
+		let mut stripper = AnsiStripper::new();
+		let mut out = DataBuf::new();
+		for chunk in chunks {
+			stripper.strip(chunk, &mut out);
+		}
+
+		out
+	}
+
+	#[test]
+	fn whole()
+	{
+		assert_eq!(strip_chunks([ESCAPED]), PLAIN);
+	}
+
+	#[test]
+	fn split_anywhere()
+	{
+		for at in 0..=ESCAPED.len() {
+			let (start, end) = ESCAPED.split_at(at);
+			assert_eq!(strip_chunks([start, end]), PLAIN, "split at {at}");
+		}
+		assert_eq!(strip_chunks(ESCAPED.chunks(1)), PLAIN, "one byte at a time");
+	}
+	const LINES: &[u8] = b"one\r\ntwo\n\nthree";
+	const PREFIXED: &[u8] = b"> one\r\n> two\n> \n> three";
+
+	fn prefix_chunks<'a, I>(prefixer: &mut LinePrefixer, chunks: I) -> DataBuf
+	where
+		I: IntoIterator<Item = &'a [u8]>,
+	{
+		let mut out = DataBuf::new();
+		for chunk in chunks {
+			prefixer.prefix(chunk, &mut out);
+		}
+
+		out
+	}
+
+	#[test]
+	fn prefix_split_anywhere()
+	{
+		for at in 0..=LINES.len() {
+			let (start, end) = LINES.split_at(at);
+			let out = prefix_chunks(&mut LinePrefixer::new("> "), [start, end]);
+			assert_eq!(out, PREFIXED, "split at {at}");
+		}
+		let out = prefix_chunks(&mut LinePrefixer::new("> "), LINES.chunks(1));
+		assert_eq!(out, PREFIXED, "one byte at a time");
+	}
+
+	#[test]
+	fn prefix_waits_for_line()
+	{
+		let mut prefixer = LinePrefixer::new("> ");
+		assert!(prefixer.at_line_start());
+		assert_eq!(prefix_chunks(&mut prefixer, [&b"a\n"[..]]), b"> a\n", "dangling prefix");
+		assert!(prefixer.at_line_start());
+		assert_eq!(prefix_chunks(&mut prefixer, [&b"b"[..]]), b"> b");
+		assert!(!prefixer.at_line_start());
+	}
+
+	#[test]
+	fn prefix_carriage_return()
+	{
+		// A lone CR goes back to overwrite the line, which needs the prefix again, but CR LF is
+		// just the end of the line.
+		let mut prefixer = LinePrefixer::new("> ");
+		let out = prefix_chunks(&mut prefixer, [&b"10%\r50%\r100%\r\ndone\n"[..]]);
+		assert_eq!(out, b"> 10%\r> 50%\r> 100%\r\n> done\n");
+
+		// Even when the CR and whatever follows it come separately.
+		let mut prefixer = LinePrefixer::new("> ");
+		let out = prefix_chunks(&mut prefixer, [&b"10%\r"[..], b"50%\r", b"\n"]);
+		assert_eq!(out, b"> 10%\r> 50%\r\n");
+	}
+
+	#[test]
+	fn prefix_restart_line()
+	{
+		let mut prefixer = LinePrefixer::new("> ");
+		assert_eq!(prefix_chunks(&mut prefixer, [&b"partial"[..]]), b"> partial");
+		assert!(!prefixer.at_line_start());
+
+		// Like when another program's output is about to be interleaved with this one.
+		prefixer.restart_line();
+		assert!(prefixer.at_line_start());
+		assert_eq!(prefix_chunks(&mut prefixer, [&b"rest\n"[..]]), b"> rest\n");
+	}
+
+	#[test]
+	fn strip_then_prefix()
+	{
+		let mut filter = OutputFilter::from(AnsiStripper::new())
+			.then(OutputFilter::from(LinePrefixer::new("[sh] ")));
+		let mut out = DataBuf::new();
+		filter.filter(b"\x1b[1mbold\x1b[0m\n\x1b]0;", &mut out);
+		filter.filter(b"title\x07next", &mut out);
+		assert_eq!(out, b"[sh] bold\n[sh] next");
+	}
+}
@@ -35,14 +35,31 @@
 
 pub mod child;
 pub mod pty;
-pub use pty::{openpt, OpenptControl};
+pub use pty::{openpt, openpt_with_flags, open_pty_pair, OpenptControl, PtyMaster};
+
+pub mod syscall;
+
+pub mod errno_error;
+pub use errno_error::{ErrnoError, OtherErrno};
 
 pub mod fdops;
 pub use fdops::FdOps;
 
 pub mod parent;
 
+pub mod run;
+pub use run::{run, spawn};
+
+pub mod session;
+pub use session::{Session, RunningSession};
+
 pub mod poller;
 
+pub mod filter;
+pub use filter::{AnsiStripper, LinePrefixer};
+
+pub mod writer;
+pub use writer::QueuedWriter;
+
 pub mod vecext;
 pub use vecext::{Data, DataExt, DataBuf, DataBufExt, VecExt};
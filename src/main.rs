@@ -3,11 +3,15 @@
 use std::env;
 use std::ffi::{OsString, OsStr};
 use std::fs::File;
-use std::io::{self, IsTerminal, Write};
-use std::os::fd::{AsFd, AsRawFd, OwnedFd};
-use std::os::unix::fs::OpenOptionsExt;
+use std::io::{self, Write};
+use std::os::fd::AsFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::ExitCode;
+use std::process::{Command, ExitCode, Stdio};
+use std::time::Duration;
+
+use log::LevelFilter;
 
 #[allow(unused_imports)]
 use {
@@ -15,12 +19,256 @@ use {
 	bytes::{BufMut, Bytes},
 	log::{trace, debug, info, warn, error},
 	miette::{Context as _, Diagnostic, Error, IntoDiagnostic},
-	nix::{errno::Errno, unistd::ForkResult},
+	nix::errno::Errno,
 	tap::prelude::*,
 };
 
-use floatty::pty::{openpt, unlockpt, ptsname, getwinsz, setwinsz, OpenptControl};
+use floatty::child::{ExecError, NOT_FOUND_CODE};
 use floatty::fdops::FdOps;
+use floatty::filter::{AnsiStripper, LinePrefixer};
+use floatty::parent::{supervise, ChildOutcome, FlushPolicy, Recording, Supervised, Timeout};
+use floatty::poller::EVENTS_LOG_TARGET;
+use floatty::session::Session;
+
+/// Our own `--options`, which all come before the program.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Options
+{
+	/// Extra environment variables to set for the program.
+	env: Vec<(Box<OsStr>, Box<OsStr>)>,
+	/// `TERM` for the program, overriding both ours and `env`.
+	term: Option<Box<OsStr>>,
+	/// Whether the PTY should be the program's controlling terminal.
+	controlling: bool,
+	/// Whether the PTY should echo input, if not its default.
+	echo: Option<bool>,
+	/// Whether `^S` and `^Q` should pause and resume output.
+	flow_control: bool,
+	/// Whether the PTY should be the program's stdin, rather than ours.
+	pty_stdin: bool,
+	/// Whether the PTY should be the program's stderr, rather than ours.
+	pty_stderr: bool,
+	/// Working directory for the program, if not ours.
+	chdir: Option<Box<Path>>,
+	/// Window size for the program, if not ours.
+	size: Option<libc::winsize>,
+	/// Window size in pixels for the program, if not whatever it would otherwise have.
+	pixel_size: Option<(u16, u16)>,
+	/// How long to let the program run for, if not forever.
+	timeout: Option<Duration>,
+	/// How long to let the program go without output, if not forever.
+	idle_timeout: Option<Duration>,
+	/// File to tee the child's output to, if any.
+	log: Option<Box<Path>>,
+	/// File to write `script`-style timing information for `log` to, if any.
+	log_timing: Option<Box<Path>>,
+	/// Remove escape sequences from what goes to `log`.
+	strip_ansi: bool,
+	/// The most output to write to `log`, if there's a limit.
+	max_output: Option<usize>,
+	/// File to record the session to in asciicast format, if any.
+	asciicast: Option<Box<Path>>,
+	/// How many times `-v` was given.
+	verbosity: usize,
+	/// Only log errors.
+	quiet: bool,
+	/// Log every poll event, regardless of `verbosity`.
+	debug_events: bool,
+	/// Flush after every write of the child's output.
+	flush: bool,
+	/// Run the program directly in our terminal, if we're already in one.
+	inherit_tty: bool,
+	/// What to label each program's output with, in order, when there's more than one.
+	labels: Vec<Box<str>>,
+	/// What to start each line of output with, if anything.
+	prefix: Option<Box<OsStr>>,
+	/// Shell command to run whenever the program's window size changes, if any.
+	on_resize: Option<Box<OsStr>>,
+}
+
+impl Default for Options
+{
+	fn default() -> Self
+	{
+		Self {
+			env: Vec::new(),
+			term: None,
+			controlling: true,
+			echo: None,
+			flow_control: true,
+			pty_stdin: true,
+			pty_stderr: true,
+			chdir: None,
+			size: None,
+			pixel_size: None,
+			timeout: None,
+			idle_timeout: None,
+			log: None,
+			log_timing: None,
+			strip_ansi: false,
+			max_output: None,
+			asciicast: None,
+			verbosity: 0,
+			quiet: false,
+			debug_events: false,
+			flush: false,
+			inherit_tty: false,
+			labels: Vec::new(),
+			prefix: None,
+			on_resize: None,
+		}
+	}
+}
+
+impl Options
+{
+	/// Handle `option`, taking its value from `args` if it has one.
+	///
+	/// Returns `Err` with what we should exit with if we should exit rather than run anything.
+	fn handle(
+		&mut self,
+		option: &OsStr,
+		args: &mut impl Iterator<Item = OsString>,
+	) -> Result<(), ExitCode>
+	{
+		// None of our options are anything but ASCII, so anything else is unrecognized anyway.
+		match option.to_str().unwrap_or_default() {
+			"--help" => {
+				print_usage();
+				return Err(ExitCode::SUCCESS);
+			},
+			"--version" => {
+				print_version();
+				return Err(ExitCode::SUCCESS);
+			},
+			"--env" => {
+				let pair = option_value(args, "--env")?;
+				self.env.push(env_pair(pair)?);
+			},
+			"--term" => {
+				let name = option_value(args, "--term")?;
+				self.term = Some(name.into_boxed_os_str());
+			},
+			"--no-ctty" => {
+				self.controlling = false;
+			},
+			"--echo" => {
+				self.echo = Some(true);
+			},
+			"--no-echo" => {
+				self.echo = Some(false);
+			},
+			"--no-flow-control" => {
+				self.flow_control = false;
+			},
+			"--keep-stdin" => {
+				self.pty_stdin = false;
+			},
+			"--split-stderr" => {
+				self.pty_stderr = false;
+			},
+			"--inherit-tty" => {
+				self.inherit_tty = true;
+			},
+			"--label" => {
+				let label = option_value(args, "--label")?;
+				self.labels.push(label.to_string_lossy().into());
+			},
+			"--prefix" => {
+				let prefix = option_value(args, "--prefix")?;
+				self.prefix = Some(prefix.into_boxed_os_str());
+			},
+			"--on-resize" => {
+				let command = option_value(args, "--on-resize")?;
+				self.on_resize = Some(command.into_boxed_os_str());
+			},
+			"--chdir" | "-C" => {
+				let dir = option_value(args, "--chdir")?;
+				self.chdir = Some(PathBuf::from(dir).into_boxed_path());
+			},
+			"--size" => {
+				let spec = option_value(args, "--size")?;
+				self.size = Some(size_arg(spec)?);
+			},
+			"--pixel-size" => {
+				let spec = option_value(args, "--pixel-size")?;
+				self.pixel_size = Some(pixel_size_arg(spec)?);
+			},
+			"--timeout" => {
+				let seconds = option_value(args, "--timeout")?;
+				self.timeout = Some(seconds_arg(seconds, "--timeout")?);
+			},
+			"--idle-timeout" => {
+				let seconds = option_value(args, "--idle-timeout")?;
+				self.idle_timeout = Some(seconds_arg(seconds, "--idle-timeout")?);
+			},
+			"--log" => {
+				let path = option_value(args, "--log")?;
+				self.log = Some(PathBuf::from(path).into_boxed_path());
+			},
+			"--flush" => {
+				self.flush = true;
+			},
+			"--log-timing" => {
+				let path = option_value(args, "--log-timing")?;
+				self.log_timing = Some(PathBuf::from(path).into_boxed_path());
+			},
+			"--strip-ansi" => {
+				self.strip_ansi = true;
+			},
+			"--max-output" => {
+				let bytes = option_value(args, "--max-output")?;
+				self.max_output = Some(bytes_arg(bytes, "--max-output")?);
+			},
+			"--asciicast" => {
+				let path = option_value(args, "--asciicast")?;
+				self.asciicast = Some(PathBuf::from(path).into_boxed_path());
+			},
+			"--verbose" => {
+				self.verbosity += 1;
+			},
+			// Stacked, like `-vvv`.
+			short if is_stacked_verbose(short) => {
+				self.verbosity += short.len() - 1;
+			},
+			"--quiet" | "-q" => {
+				self.quiet = true;
+			},
+			"--debug-events" => {
+				self.debug_events = true;
+			},
+			_ => {
+				eprintln!(
+					"floatty: unrecognized option '{}'\
+					\nTry 'floatty --help' for more information",
+					option.display(),
+				);
+
+				return Err(ExitCode::from(255));
+			},
+		}
+
+		Ok(())
+	}
+
+	/// What we log at unless `RUST_LOG` says otherwise.
+	fn log_level(&self) -> LevelFilter
+	{
+		if self.quiet {
+			return LevelFilter::Error;
+		}
+
+		match self.verbosity {
+			0 => LevelFilter::Warn,
+			1 => LevelFilter::Info,
+			2 => LevelFilter::Debug,
+			_ => LevelFilter::Trace,
+		}
+	}
+}
+
+/// A program to run, and its arguments.
+type Program = (Box<Path>, Box<[Box<OsStr>]>);
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct HandledArgs
@@ -29,6 +277,10 @@ struct HandledArgs
 	prog: Box<Path>,
 	/// Arguments to that program.
 	args: Box<[Box<OsStr>]>,
+	/// Any more programs to run alongside it, and their arguments, which each came after a `::`.
+	others: Vec<Program>,
+	/// Everything else we were told.
+	options: Options,
 }
 
 fn print_usage()
@@ -36,11 +288,60 @@ fn print_usage()
 	let mut stdout = io::stdout();
 	writeln!(
 		stdout,
-		"Usage: floatty <program> <args...>\
+		"Usage: floatty [OPTIONS] [--] <program> <args...> [:: <program> <args...>]...\
+		\n\
+		\nWith more than one program, each runs in its own PTY, and each line of their output\
+		\nstarts with its label. Only the first program gets our input.\
 		\n\
 		\nOPTIONS:\
-		\n  --help     display this help message and exit
-		\n  --version  display version information and exit\
+		\n  --help               display this help message and exit\
+		\n  --version            display version information and exit\
+		\n  --env <KEY=VAL>      set KEY to VAL in the program's environment; may be repeated\
+		\n  --term <NAME>        set TERM for the program (default: inherited, or xterm-256color)\
+		\n  --no-ctty            don't make the PTY the program's controlling terminal, so ^C and\
+		\n                       friends don't send it signals and it gets no SIGHUP on close\
+		\n  --no-echo            don't echo input back from the PTY, like for a password prompt, so\
+		\n                       scripted input doesn't show up in the output\
+		\n  --echo               echo input back from the PTY, which it does by default, to\
+		\n                       override an earlier --no-echo\
+		\n  --no-flow-control    let ^S and ^Q reach the program, instead of pausing and resuming\
+		\n                       its output, for editors and REPLs that use them\
+		\n  --keep-stdin         give the program our stdin directly, instead of through the PTY,\
+		\n                       while its output still goes through the PTY\
+		\n  --split-stderr       give the program our stderr directly, so its errors can be\
+		\n                       redirected apart from its output, and aren't in --log\
+		\n  --inherit-tty        if we're already in a terminal, just run the program in it\
+		\n                       instead of a new PTY, ignoring options that need one\
+		\n  --label <NAME>       label the next program's output with NAME, instead of its name,\
+		\n                       when running more than one; may be repeated\
+		\n  --prefix <STRING>    start each line of the program's output with STRING, in --log too\
+		\n  -C, --chdir <DIR>    run the program in DIR instead of the current directory\
+		\n  --size <COLSxROWS>   fix the program's window size, instead of following ours, or\
+		\n                       COLUMNS and LINES, or 80x24, if we're not in a sized terminal\
+		\n  --pixel-size <WxH>   set the program's window size in pixels, for programs that draw\
+		\n                       images, scaling it as the size in cells changes\
+		\n  --on-resize <COMMAND>\
+		\n                       run COMMAND with sh whenever the program's window size changes,\
+		\n                       and send what it prints to the program as input, like\
+		\n                       \"printf '\\f'\" for programs that don't redraw on their own\
+		\n  --timeout <SECONDS>  kill the program if it's still running after SECONDS, and exit 124\
+		\n  --idle-timeout <SECONDS>\
+		\n                       kill the program if it writes nothing for SECONDS, and exit 125\
+		\n  --log <FILE>         also write the program's output to FILE\
+		\n  --log-timing <FILE>  write timing information for --log to FILE, for scriptreplay\
+		\n  --strip-ansi         leave escape sequences like colors out of --log, so it's plain text\
+		\n  --max-output <BYTES> stop writing to --log once it has BYTES of output, while output\
+		\n                       still goes to our stdout\
+		\n  --asciicast <FILE>   record the program's output and window size changes to FILE, in\
+		\n                       asciicast v2 format, for asciinema to play back\
+		\n  --flush              flush output after every write, whether or not it's to a terminal,\
+		\n                       instead of letting output that isn't pile up while the program\
+		\n                       is busy writing more\
+		\n  -v, --verbose        log more about what floatty is doing; may be repeated, up to -vvv\
+		\n  -q, --quiet          only log errors\
+		\n  --debug-events       log every event floatty handles, with a hexdump of its data\
+		\n\
+		\nRUST_LOG, if set, overrides -v and -q.\
 		\n",
 	).unwrap_or_else(|e| {
 		// If we can't write to stdout for even the help message, then we might as well
@@ -51,6 +352,215 @@ fn print_usage()
 	});
 }
 
+fn print_version()
+{
+	let version = env!("CARGO_PKG_VERSION");
+	match option_env!("FLOATTY_GIT_HASH") {
+		Some(hash) => println!("floatty {version} ({hash})"),
+		None => println!("floatty {version}"),
+	}
+	println!("target: {}", env!("FLOATTY_BUILD_TARGET"));
+}
+
+/// Whether `option` is some number of stacked `-v`s, like `-vvv`.
+fn is_stacked_verbose(option: &str) -> bool
+{
+	match option.strip_prefix('-') {
+		Some(vs) => !vs.is_empty() && vs.bytes().all(|byte| byte == b'v'),
+		None => false,
+	}
+}
+
+/// Get the value for an `--option` that takes one, or complain that there isn't one.
+fn option_value(args: &mut impl Iterator<Item = OsString>, option: &str) -> Result<OsString, ExitCode>
+{
+	args.next().ok_or_else(|| {
+		eprintln!(
+			"floatty: option '{option}' requires an argument\
+			\nTry 'floatty --help' for more information",
+		);
+
+		ExitCode::from(255)
+	})
+}
+
+/// Split a `KEY=VAL` argument to `--env`.
+fn env_pair(pair: OsString) -> Result<(Box<OsStr>, Box<OsStr>), ExitCode>
+{
+	let bytes = pair.as_bytes();
+	let Some(equals) = bytes.iter().position(|&byte| byte == b'=') else {
+		eprintln!(
+			"floatty: argument to '--env' must be of the form KEY=VAL, not '{}'\
+			\nTry 'floatty --help' for more information",
+			pair.display(),
+		);
+
+		return Err(ExitCode::from(255));
+	};
+
+	let key = OsStr::from_bytes(&bytes[..equals]);
+	let val = OsStr::from_bytes(&bytes[equals + 1..]);
+
+	Ok((Box::from(key), Box::from(val)))
+}
+
+/// Parse two non-zero numbers separated by an `x`, like `80x24`.
+fn dimensions(spec: &OsStr) -> Option<(u16, u16)>
+{
+	spec.to_str()
+		.and_then(|spec| spec.split_once('x'))
+		.and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+		.filter(|&(width, height)| width != 0 && height != 0)
+}
+
+/// Parse a `COLSxROWS` argument to `--size`.
+fn size_arg(spec: OsString) -> Result<libc::winsize, ExitCode>
+{
+	let Some((cols, rows)) = dimensions(&spec) else {
+		eprintln!(
+			"floatty: argument to '--size' must be of the form COLSxROWS, like 80x24, \
+			with both non-zero, not '{}'\
+			\nTry 'floatty --help' for more information",
+			spec.display(),
+		);
+
+		return Err(ExitCode::from(255));
+	};
+
+	Ok(libc::winsize {
+		ws_col: cols,
+		ws_row: rows,
+		ws_xpixel: 0,
+		ws_ypixel: 0,
+	})
+}
+
+/// Parse a `WxH` argument to `--pixel-size`.
+fn pixel_size_arg(spec: OsString) -> Result<(u16, u16), ExitCode>
+{
+	dimensions(&spec).ok_or_else(|| {
+		eprintln!(
+			"floatty: argument to '--pixel-size' must be of the form WxH, like 1280x720, \
+			with both non-zero, not '{}'\
+			\nTry 'floatty --help' for more information",
+			spec.display(),
+		);
+
+		ExitCode::from(255)
+	})
+}
+
+/// Parse a positive, possibly fractional, number of seconds for `option`.
+fn seconds_arg(seconds: OsString, option: &str) -> Result<Duration, ExitCode>
+{
+	let parsed = seconds.to_str()
+		.and_then(|seconds| seconds.parse::<f64>().ok())
+		.filter(|&seconds| seconds > 0.0)
+		.and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+
+	parsed.ok_or_else(|| {
+		eprintln!(
+			"floatty: argument to '{option}' must be a positive number of seconds, not '{}'\
+			\nTry 'floatty --help' for more information",
+			seconds.display(),
+		);
+
+		ExitCode::from(255)
+	})
+}
+
+/// Parse the argument to an `--option` that takes a number of bytes.
+fn bytes_arg(bytes: OsString, option: &str) -> Result<usize, ExitCode>
+{
+	let parsed = bytes.to_str().and_then(|bytes| bytes.parse::<usize>().ok());
+
+	parsed.ok_or_else(|| {
+		eprintln!(
+			"floatty: argument to '{option}' must be a number of bytes, not '{}'\
+			\nTry 'floatty --help' for more information",
+			bytes.display(),
+		);
+
+		ExitCode::from(255)
+	})
+}
+
+/// Complain that we weren't given a program to run.
+fn missing_program() -> ExitCode
+{
+	eprintln!(
+		"floatty: error: the following required arguments were not provided:\
+		\n  <program>\
+		",
+	);
+
+	print_usage();
+
+	ExitCode::from(255)
+}
+
+/// Find the program to run, or complain like a shell would if it doesn't exist, so that doesn't
+/// only come up after we've forked.
+fn resolve_program(name: OsString, chdir: Option<&Path>) -> Result<Box<Path>, ExitCode>
+{
+	let not_found = |name: &OsStr| {
+		eprintln!("floatty: command not found: {}", name.display());
+
+		ExitCode::from(NOT_FOUND_CODE as u8)
+	};
+
+	if !name.as_bytes().contains(&b'/') {
+		return which::which(&name)
+			.map(PathBuf::into_boxed_path)
+			.map_err(|_| not_found(&name));
+	}
+
+	// Paths the user gave explicitly are relative to wherever the program runs, so we leave them
+	// for exec() to resolve after changing directory, and only check that they're there.
+	let path = PathBuf::from(name);
+	let exists = match chdir {
+		Some(dir) => dir.join(&path).try_exists(),
+		None => path.try_exists(),
+	};
+	// If we can't even tell, let exec() give the real error.
+	if !exists.unwrap_or(true) {
+		return Err(not_found(path.as_os_str()));
+	}
+
+	Ok(path.into_boxed_path())
+}
+
+/// Replace ourselves with the program, keeping our stdio, for `--inherit-tty`.
+///
+/// Only returns if exec() fails.
+fn exec_in_our_terminal(
+	prog: &Path,
+	args: &[Box<OsStr>],
+	env: &[(Box<OsStr>, Box<OsStr>)],
+	term: Option<&OsStr>,
+	chdir: Option<&Path>,
+) -> miette::Result<ExitCode>
+{
+	let mut command = Command::new(prog);
+	command.args(args);
+	for (key, val) in env {
+		command.env(key, val);
+	}
+	if let Some(term) = term {
+		command.env("TERM", term);
+	}
+	if let Some(dir) = chdir {
+		command.current_dir(dir);
+	}
+
+	debug!("running {command:?} in our own terminal");
+	let error = ExecError::new(prog, command.exec());
+	let code = error.exit_code();
+	eprintln!("floatty: {:?}", miette::Report::new(error));
+
+	Ok(ExitCode::from(code as u8))
+}
+
 /// Pretty raw port of the Zig argument parsing we had.
 fn handle_args() -> Result<HandledArgs, ExitCode>
 {
@@ -60,115 +570,391 @@ fn handle_args() -> Result<HandledArgs, ExitCode>
 	// On the other hand, we don't care about the actual value of argv[0].
 	let Some(_executed_as) = args.next() else { unreachable!(); };
 
-	// The first argument is special.
+	let mut options = Options::default();
+
+	// Our --options all come before the program.
 	// We can't take any --options after accepting positional arguments, so that we don't
 	// interpret things like `floatty ls --help` as `--help` for us.
-	// Also we don't have any cases where --multiple --options make sense at a time, yet,
-	// since we only have `--version` and `--help`.
-	let Some(first) = args.next() else {
-		// No arguments provided.
-		eprintln!(
-			"floatty: error: the following required arguments were not provided:\
-			\n  <program>\
-			",
-		);
+	let first = loop {
+		let Some(arg) = args.next() else {
+			return Err(missing_program());
+		};
 
-		print_usage();
+		// Jesus christ Rust. Get your shit together with OS strings...
+		let hyphen_minus = OsStr::new("-").as_encoded_bytes();
+		if !arg.as_encoded_bytes().starts_with(hyphen_minus) {
+			// The first non-option is the command we want to execute.
+			break arg;
+		}
 
-		return Err(ExitCode::from(255));
+		if arg == OsStr::new("--") {
+			// Explicit end of our options, so the program can be named anything, even `--help`.
+			match args.next() {
+				Some(prog) => break prog,
+				None => return Err(missing_program()),
+			}
+		}
+
+		options.handle(&arg, &mut args)?;
 	};
 
-	// Jesus christ Rust. Get your shit together with OS strings...
-	let hyphen_minus = OsStr::new("-").as_encoded_bytes();
-	let encoded_len = hyphen_minus.len();
-	if &first.as_encoded_bytes()[0..encoded_len] == hyphen_minus {
-		if first == OsStr::new("--help") {
-			print_usage();
-			return Err(ExitCode::SUCCESS);
-		}
+	let log_options = [
+		("--log-timing", options.log_timing.is_some()),
+		("--strip-ansi", options.strip_ansi),
+		("--max-output", options.max_output.is_some()),
+	];
+	for (option, given) in log_options {
+		if given && options.log.is_none() {
+			eprintln!(
+				"floatty: option '{option}' requires '--log'\
+				\nTry 'floatty --help' for more information",
+			);
 
-		if first == OsStr::new("--version") {
-			println!("floatty 0.0.1");
-			return Err(ExitCode::SUCCESS);
+			return Err(ExitCode::from(255));
 		}
+	}
 
+	if options.on_resize.is_some() && options.size.is_some() {
 		eprintln!(
-			"floatty: unrecognized option '{}'\
+			"floatty: option '--on-resize' can't be used with '--size', which keeps the size fixed\
 			\nTry 'floatty --help' for more information",
-			first.display(),
 		);
+
+		return Err(ExitCode::from(255));
 	}
 
-	// If we got here, then we weren't passed any options.
-	// Which means `first` is the command we want to execute.
-	let prog: Box<Path> = which::which(&first)
-		// If `which` doesn't find anything, just use the original argument.
-		.unwrap_or_else(|_| PathBuf::from(first))
-		.into_boxed_path();
+	let prog: Box<Path> = resolve_program(first, options.chdir.as_deref())?;
 
-	let args: Box<[Box<OsStr>]> = args
-		.map(OsString::into_boxed_os_str)
-		.collect::<Vec<_>>()
+	// Everything after the program is its arguments, up until a `::` starts the next program.
+	let mut groups: Vec<Vec<Box<OsStr>>> = vec![Vec::new()];
+	for arg in args {
+		if arg == OsStr::new("::") {
+			groups.push(Vec::new());
+			continue;
+		}
+		groups.last_mut()
+			.unwrap_or_else(|| unreachable!())
+			.push(arg.into_boxed_os_str());
+	}
+	let mut groups = groups.into_iter();
+	let args: Box<[Box<OsStr>]> = groups.next()
+		.unwrap_or_default()
 		.into_boxed_slice();
 
-	Ok(HandledArgs { prog, args })
+	let mut others = Vec::new();
+	for group in groups {
+		let mut group = group.into_iter();
+		let Some(name) = group.next() else {
+			return Err(missing_program());
+		};
+		let other = resolve_program(name.into_os_string(), options.chdir.as_deref())?;
+		others.push((other, group.collect::<Vec<_>>().into_boxed_slice()));
+	}
+
+	if others.is_empty() && !options.labels.is_empty() {
+		eprintln!(
+			"floatty: option '--label' requires more than one program, separated by '::'\
+			\nTry 'floatty --help' for more information",
+		);
+
+		return Err(ExitCode::from(255));
+	}
+	if options.labels.len() > others.len() + 1 {
+		eprintln!(
+			"floatty: more labels than programs\
+			\nTry 'floatty --help' for more information",
+		);
+
+		return Err(ExitCode::from(255));
+	}
+
+	// These are all about a single child, and don't mean anything for several at once.
+	let single_options = [
+		("--log", options.log.is_some()),
+		("--asciicast", options.asciicast.is_some()),
+		("--timeout", options.timeout.is_some()),
+		("--idle-timeout", options.idle_timeout.is_some()),
+		("--keep-stdin", !options.pty_stdin),
+		("--inherit-tty", options.inherit_tty),
+		// Each one's lines already start with its label.
+		("--prefix", options.prefix.is_some()),
+		("--on-resize", options.on_resize.is_some()),
+	];
+	for (option, given) in single_options {
+		if given && !others.is_empty() {
+			eprintln!(
+				"floatty: option '{option}' can't be used with more than one program\
+				\nTry 'floatty --help' for more information",
+			);
+
+			return Err(ExitCode::from(255));
+		}
+	}
+
+	Ok(HandledArgs {
+		prog,
+		args,
+		others,
+		options,
+	})
 }
 
-fn main() -> miette::Result<ExitCode>
+/// Run `command` with `sh` for `--on-resize`, with the new size in `COLUMNS` and `LINES`, and its
+/// output going to the program through `pty`.
+///
+/// We wait for it, since the program shouldn't get its output interleaved with anything else.
+fn run_resize_command(command: &OsStr, pty: &File, size: libc::winsize)
 {
-	env_logger::init();
+	let output = match pty.try_clone() {
+		Ok(output) => output,
+		Err(e) => {
+			warn!("couldn't duplicate child PTY for --on-resize command: {e}");
+			return;
+		},
+	};
+	let status = Command::new("sh")
+		.arg("-c")
+		.arg(command)
+		.env("COLUMNS", size.ws_col.to_string())
+		.env("LINES", size.ws_row.to_string())
+		.stdin(Stdio::null())
+		.stdout(output)
+		.status();
+	match status {
+		Ok(status) if status.success() => (),
+		Ok(status) => warn!("--on-resize command failed: {status}"),
+		Err(e) => warn!("couldn't run --on-resize command: {e}"),
+	}
+}
+
+/// Tell the user how `who` went, if it didn't exit successfully.
+///
+/// `timeout` and `idle_timeout` are what we ran it with, for saying how long it took to time out.
+fn report_outcome(
+	who: &str,
+	outcome: ChildOutcome,
+	timeout: Option<Duration>,
+	idle_timeout: Option<Duration>,
+)
+{
+	match outcome {
+		ChildOutcome::Exited(0) => (),
+		ChildOutcome::Exited(exit_code) => {
+			eprintln!("floatty: {who} exited with non-zero exit code {exit_code}");
+		},
+		ChildOutcome::Signaled { signal, core_dumped } => {
+			let dumped = if core_dumped { " (core dumped)" } else { "" };
+			eprintln!("floatty: {who} killed by {} (signal {}){dumped}", signal.as_str(), signal as i32);
+		},
+		ChildOutcome::TimedOut(Timeout::Total) => {
+			let after = timeout.unwrap_or_default().as_secs_f64();
+			eprintln!("floatty: timed out after {after} seconds, so killed {who}");
+		},
+		ChildOutcome::TimedOut(Timeout::Idle) => {
+			let after = idle_timeout.unwrap_or_default().as_secs_f64();
+			eprintln!("floatty: no output for {after} seconds, so killed {who}");
+		},
+	}
+}
+
+/// Run each of `programs` at once, under [`supervise()`], for when we're given more than one.
+///
+/// Exits with the code of the first program that didn't succeed, if any.
+fn run_several(
+	programs: impl Iterator<Item = Program>,
+	labels: Vec<Box<str>>,
+	new_session: impl Fn(&Path, &[Box<OsStr>]) -> Session,
+	follow_resize: bool,
+	flush: bool,
+) -> miette::Result<ExitCode>
+{
+	let mut labels = labels.into_iter();
+	let mut children = Vec::new();
+	for (prog, args) in programs {
+		let label: Box<str> = labels.next().unwrap_or_else(|| {
+			let name = prog.file_name().unwrap_or(prog.as_os_str());
+			name.to_string_lossy().into()
+		});
+		let (pty, child) = new_session(&prog, &args).spawn()?.into_parts();
+		children.push(Supervised::new(label, child, pty));
+	}
+
+	let labels: Vec<Box<str>> = children.iter().map(|child| child.label.clone()).collect();
+	let outcomes = supervise(children, follow_resize, flush)?;
+
+	let mut code: Option<ExitCode> = None;
+	for (label, outcome) in labels.iter().zip(outcomes) {
+		// supervise() doesn't do timeouts.
+		report_outcome(label, outcome, None, None);
+		if !outcome.success() {
+			code.get_or_insert(ExitCode::from(outcome));
+		}
+	}
+
+	Ok(code.unwrap_or(ExitCode::SUCCESS))
+}
 
-	let HandledArgs { prog, args } = match handle_args() {
+fn main() -> miette::Result<ExitCode>
+{
+	let handled = match handle_args() {
 		Ok(handled) => handled,
 		// Feels slightly weird to use Ok() to return a potential error code...
 		// ...but whatever.
 		Err(code) => return Ok(code),
 	};
 
-	let pty_fd: OwnedFd = openpt(OpenptControl::BecomeControllingTerminal)?;
-
-	pty_fd.as_fd().set_nonblocking();
+	let mut logger = env_logger::Builder::new();
+	logger.filter_level(handled.options.log_level());
+	if handled.options.debug_events {
+		logger.filter_module(EVENTS_LOG_TARGET, LevelFilter::Trace);
+	}
+	logger.parse_default_env().init();
 
-	unlockpt(pty_fd.as_fd())?;
+	let HandledArgs {
+		prog,
+		args,
+		others,
+		options: Options {
+			env,
+			term,
+			controlling,
+			echo,
+			flow_control,
+			pty_stdin,
+			pty_stderr,
+			chdir,
+			size,
+			pixel_size,
+			timeout,
+			idle_timeout,
+			log,
+			log_timing,
+			strip_ansi,
+			max_output,
+			asciicast,
+			verbosity: _,
+			quiet: _,
+			debug_events: _,
+			flush,
+			inherit_tty,
+			labels,
+			prefix,
+			on_resize,
+		},
+	} = handled;
 
-	// ioctl TIOCGPTN "get pty number"
-	// FIXME: use TIOCPTPEER instead
-	let term_name = ptsname(pty_fd.as_fd())?;
-	info!("Our terminal is {}", term_name.display());
+	// A PTY of our own in between would only add a second layer of echo and line editing.
+	let in_terminal = io::stdin().is_tty() && io::stdout().is_tty();
+	if in_terminal {
+		if inherit_tty {
+			return exec_in_our_terminal(&prog, &args, &env, term.as_deref(), chdir.as_deref());
+		}
+		warn!("already in a terminal; --inherit-tty would run the program in it directly");
+	}
 
-	let other_side = File::options()
-		.read(true)
-		.write(true)
-		.custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
-		.open(&term_name)
+	// Open this before we spawn anything, so we don't start a child we can't record.
+	let create = |path: Box<Path>, what: &str| {
+		File::create(&path)
+			.into_diagnostic()
+			.with_context(|| format!("opening {what} file {}", path.display()))
+	};
+	let timing_file: Option<File> = log_timing
+		.map(|path| create(path, "log timing"))
+		.transpose()?;
+	let log_file: Option<Recording> = log
+		.map(|path| create(path, "log"))
+		.transpose()?
+		.map(|data| Recording::new(data, timing_file))
+		.transpose()
 		.into_diagnostic()
-		.with_context(|| format!("opening terminal child {}", term_name.display()))?;
+		.context("writing log file header")?
+		.map(|log| match strip_ansi {
+			true => log.with_filter(AnsiStripper::new().into()),
+			false => log,
+		})
+		.map(|log| match max_output {
+			Some(bytes) => log.with_limit(bytes),
+			None => log,
+		});
+	let cast_file: Option<File> = asciicast
+		.map(|path| create(path, "asciicast"))
+		.transpose()?;
 
-	// Surprisingly, `pty_fd` is NOT a terminal, but this definitely should be.
-	debug_assert!(other_side.is_terminal());
-
-	debug!("Got file descriptors {} and {}", pty_fd.as_raw_fd(), other_side.as_raw_fd());
+	let new_session = |prog: &Path, args: &[Box<OsStr>]| {
+		let mut session = Session::new(prog);
+		session.args(args)
+			.controlling(controlling)
+			.pty_stdin(pty_stdin)
+			.pty_stderr(pty_stderr);
+		if flush {
+			session.flush_policy(FlushPolicy::Interactive);
+		}
+		for (key, val) in &env {
+			session.env(key, val);
+		}
+		if let Some(term) = &term {
+			session.env("TERM", term);
+		}
+		if let Some(echo) = echo {
+			session.echo(echo);
+		}
+		if !flow_control {
+			session.flow_control(false);
+		}
+		if let Some(dir) = &chdir {
+			session.current_dir(dir);
+		}
+		if let Some(size) = size {
+			session.window_size(size);
+		}
+		if let Some((width, height)) = pixel_size {
+			session.pixel_size(width, height);
+		}
+		session
+	};
 
-	let current_size = getwinsz(io::stdin().as_fd());
-	setwinsz(pty_fd.as_fd(), current_size);
+	if !others.is_empty() {
+		let programs = [(prog, args)].into_iter().chain(others);
+		return run_several(programs, labels, new_session, size.is_none(), flush);
+	}
 
-	// Spawn a new process, and then use setsid() and TIOCSCTTY to make this terminal
-	// the controlling terminal for that process, and then spawn the requested command.
-	use ForkResult::*;
-	match unsafe { nix::unistd::fork() } {
-		Ok(Child) => {
-			drop(pty_fd);
+	let mut session = new_session(&prog, &args);
+	if let Some(prefix) = &prefix {
+		// Before anything's recorded, so the log's filter sees it too, and --strip-ansi leaves
+		// it alone.
+		session.output_filter(LinePrefixer::new(prefix.as_bytes()).into());
+	}
+	if let Some(timeout) = timeout {
+		session.timeout(timeout);
+	}
+	if let Some(timeout) = idle_timeout {
+		session.idle_timeout(timeout);
+	}
 
-			info!("prog: {prog:?}, args: {args:?}");
-			floatty::child::child_process(prog, args, OwnedFd::from(other_side))?;
-		},
-		Ok(Parent { child }) => {
-			floatty::parent::parent_process(child, pty_fd)?;
-		},
-		Err(e) => {
-			panic!("fork() failed: {e}");
-		},
+	let mut running = session.spawn()?;
+	if let Some(log) = log_file {
+		running.record(log);
 	}
+	if let Some(command) = on_resize {
+		let pty = running.pty()
+			.as_fd()
+			.try_clone_to_owned()
+			.map(File::from)
+			.into_diagnostic()
+			.context("duplicating child PTY for --on-resize")?;
+		running.on_resize(move |size| run_resize_command(&command, &pty, size));
+	}
+	if let Some(data) = cast_file {
+		// Now that the child's PTY has its starting size.
+		let size = running.pty().winsize()?;
+		let cast = Recording::asciicast(data, size)
+			.into_diagnostic()
+			.context("writing asciicast header")?;
+		running.record(cast);
+	}
+
+	let outcome: ChildOutcome = running.wait()?;
+	report_outcome("child", outcome, timeout, idle_timeout);
 
-	Ok(ExitCode::SUCCESS)
+	Ok(ExitCode::from(outcome))
 }
@@ -1,9 +1,13 @@
-use std::io::{self, Write};
+use std::io::{self, BufWriter, IsTerminal, Stdout, Write};
 use std::ffi::c_int;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::fs::File;
 use std::ptr;
 use std::ops::ControlFlow;
-use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::process::{ExitCode, ExitStatus};
+use std::time::{Duration, Instant};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
 
 #[allow(unused_imports)]
 use {
@@ -14,134 +18,1594 @@ use {
 	nix::errno::Errno,
 	tap::prelude::*,
 };
-use nix::unistd::Pid;
+use nix::unistd::{getpgrp, Pid};
+use nix::sys::wait::WaitStatus;
 use nix::sys::{
-	signal::{Signal, SigmaskHow, sigprocmask},
+	signal::{Signal, SigmaskHow, killpg, sigprocmask},
 	signalfd::{SfdFlags, SigSet},
+	termios::SetArg,
 };
 
-use crate::poller::{Poller, PollInterest};
+use crate::poller::{
+	is_pollable,
+	InterestHandle,
+	PollEvent,
+	Poller,
+	PollInterest,
+	NonblockingRead,
+	TimeoutHandle,
+	DEFAULT_BUFFER_SIZE,
+	EVENTS_LOG_TARGET,
+};
+use crate::pty::{
+	get_foreground_pgrp,
+	get_termios,
+	getwinsz,
+	send_tty_signal,
+	set_termios,
+	setwinsz,
+	sync_winsize,
+	RawMode,
+	TermiosError,
+	WinSize,
+};
+use crate::writer::QueuedWriter;
+use crate::{Data, DataBuf, DataBufExt};
+
+/// The traditional `VEOF` character.
+const CONTROL_D: u8 = 0x04;
 
 mod signalfd_error;
 pub use signalfd_error::SignalfdError;
+mod siginfo;
+pub use siginfo::{decode_siginfo, SignalInfo};
+mod asciicast;
+use asciicast::Asciicast;
+mod supervise;
+pub use supervise::{supervise, Supervised};
+#[cfg(feature = "tokio")]
+mod async_loop;
+#[cfg(feature = "tokio")]
+pub use async_loop::parent_loop_async;
 
-/// Caller is responsible for closing the file descriptor.
-pub fn signalfd(fd: RawFd, mask: &SigSet, flags: SfdFlags) -> Result<RawFd, SignalfdError>
+/// Rust wrapper for `signalfd(2)`, which creates a new file descriptor to read the signals in
+/// `mask` from, instead of having them delivered.
+///
+/// Those signals should already be blocked, or they'll still be delivered as usual. Changing the
+/// mask of an existing signalfd isn't supported, so this always creates a new one, which is
+/// closed when dropped.
+pub fn signalfd(mask: &SigSet, flags: SfdFlags) -> Result<OwnedFd, SignalfdError>
 {
+	// Per `signalfd(2)`, `-1` creates a new file descriptor for us.
+	const NEW_FD: RawFd = -1;
+
 	let mask: *const libc::sigset_t = ptr::from_ref(mask.as_ref());
 	let flags: c_int = flags.bits();
-	let signal_fd: RawFd = unsafe { libc::signalfd(fd, mask, flags) };
+	let signal_fd: RawFd = unsafe { libc::signalfd(NEW_FD, mask, flags) };
 	if signal_fd < 0 {
 		let errno = Errno::last();
 		let err = SignalfdError::from_errno(errno);
 		return Err(err);
 	}
 
+	// SAFETY: `signalfd()` returns a newly opened file descriptor, which is now ours.
+	let signal_fd = unsafe { OwnedFd::from_raw_fd(signal_fd) };
+
 	Ok(signal_fd)
 }
 
+/// Restores the signal mask from before [`handle_signals_as_file()`] when dropped, so the signals
+/// don't stay blocked after the session ends.
+#[derive(Debug)]
+#[must_use = "the signals are unblocked again as soon as this is dropped"]
+pub(crate) struct SignalMaskGuard
+{
+	old_mask: SigSet,
+}
+
+impl Drop for SignalMaskGuard
+{
+	fn drop(&mut self)
+	{
+		trace!("restoring signal mask {:?}", self.old_mask);
+		if let Err(e) = sigprocmask(SigmaskHow::SIG_SETMASK, Some(&self.old_mask), None) {
+			warn!("couldn't restore signal mask {:?}: {e}", self.old_mask);
+		}
+	}
+}
+
 /// Block a signal and convert it to a [File].
-fn handle_signals_as_file(signals: &[Signal]) -> miette::Result<File>
+///
+/// The signals stay blocked until the returned guard is dropped, which should be after we're done
+/// with the file.
+fn handle_signals_as_file(signals: &[Signal]) -> miette::Result<(File, SignalMaskGuard)>
 {
 	let mut set = SigSet::empty();
 	for &sig in signals {
 		set.add(sig);
 	}
 
-	sigprocmask(SigmaskHow::SIG_BLOCK, Some(&set), None)
+	let mut old_mask = SigSet::empty();
+	sigprocmask(SigmaskHow::SIG_BLOCK, Some(&set), Some(&mut old_mask))
 		.into_diagnostic()
 		.with_context(|| format!("blocking the following signals: {set:?}"))?;
+	// Set up the guard first, so the signals get unblocked even if signalfd() fails.
+	let guard = SignalMaskGuard { old_mask };
 
-	// Per `signalfd(2)`, `-1` creates a new file descriptor for us.
-	const NEW_FD: RawFd = -1;
-	let signal_fd: RawFd = signalfd(NEW_FD, &set, SfdFlags::SFD_NONBLOCK)
+	let signal_fd: OwnedFd = signalfd(&set, SfdFlags::SFD_NONBLOCK)
 		.into_diagnostic()
 		.with_context(|| format!("calling signalfd() on the following signals: {set:?}"))?;
 
-	let signal_file = unsafe { File::from_raw_fd(signal_fd) };
+	Ok((File::from(signal_fd), guard))
+}
+
+/// A `script(1)`-style recording of the child's output, or an asciicast of it, from
+/// [`Recording::asciicast()`].
+#[derive(Debug)]
+pub struct Recording
+{
+	data: File,
+	/// `scriptreplay(1)`-compatible timing file, if any.
+	timing: Option<File>,
+	/// Set if `data` is an asciicast, rather than just the output.
+	cast: Option<Asciicast>,
+	last_write: Instant,
+	/// What to pass output through before it's recorded, separately from what reaches stdout.
+	filter: Option<OutputFilter>,
+	/// Scratch space for `filter`'s output.
+	filtered: DataBuf,
+	/// The most output to record, if there's a limit.
+	limit: Option<usize>,
+	/// How much output we've recorded so far, not counting the header.
+	recorded: usize,
+}
+
+impl Recording
+{
+	pub fn new(mut data: File, timing: Option<File>) -> io::Result<Self>
+	{
+		if timing.is_some() {
+			// `scriptreplay` skips the first line of the log, since `script` writes a header there.
+			writeln!(data, "Script started by floatty")?;
+		}
+
+		Ok(Self {
+			data,
+			timing,
+			cast: None,
+			last_write: Instant::now(),
+			filter: None,
+			filtered: DataBuf::new(),
+			limit: None,
+			recorded: 0,
+		})
+	}
+
+	/// Record to `data` in [asciicast v2] format instead, for a terminal that starts out as `size`,
+	/// so it can be played back with `asciinema play`, or shared.
+	///
+	/// The times of each event are in the file itself, so there's no separate timing file.
+	///
+	/// [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+	pub fn asciicast(mut data: File, size: libc::winsize) -> io::Result<Self>
+	{
+		let (cast, header) = Asciicast::start(size);
+		data.write_all(header.as_bytes())?;
+
+		Ok(Self {
+			cast: Some(cast),
+			..Self::new(data, None)?
+		})
+	}
+
+	/// Stop recording once `bytes` of output have been recorded, after any filter.
+	///
+	/// Output past that is just left out, rather than failing, since the log isn't worth taking
+	/// down the whole session over.
+	pub fn with_limit(mut self, bytes: usize) -> Self
+	{
+		self.limit = Some(bytes);
+		self
+	}
+
+	/// Pass everything through `filter` before recording it, like an
+	/// [`AnsiStripper`](crate::AnsiStripper) for a plain text log.
+	pub fn with_filter(mut self, filter: OutputFilter) -> Self
+	{
+		self.filter = Some(filter);
+		self
+	}
+
+	/// Write `data` to the log, and how long it's been since the last write to the timing file.
+	pub fn record(&mut self, data: &[u8]) -> io::Result<()>
+	{
+		let data: &Data = match &mut self.filter {
+			Some(filter) => {
+				self.filtered.clear();
+				filter.filter(data, &mut self.filtered);
+				&self.filtered
+			},
+			None => data,
+		};
+		let mut reached_limit: Option<usize> = None;
+		let data: &Data = match self.limit {
+			Some(limit) if self.recorded + data.len() > limit => {
+				let mut room = limit.saturating_sub(self.recorded);
+				if room > 0 {
+					warn!("log reached its {limit} byte limit; leaving the rest of the output out");
+				}
+				if self.cast.is_some() {
+					// A cast can only show whole characters, so leave out all of the one the
+					// limit falls in.
+					room = asciicast::char_boundary(data, room);
+				}
+				reached_limit = Some(limit);
+				&data[..room]
+			},
+			_ => data,
+		};
+
+		if !data.is_empty() {
+			match &mut self.cast {
+				Some(cast) => {
+					if let Some(line) = cast.output(data) {
+						self.data.write_all(line.as_bytes())?;
+					}
+				},
+				None => self.data.write_all(data)?,
+			}
+			self.recorded += data.len();
+			self.data.flush()?;
+
+			// Only count bytes that actually made it to the log.
+			let now = Instant::now();
+			let delay = now.duration_since(self.last_write);
+			self.last_write = now;
+
+			if let Some(timing) = &mut self.timing {
+				// One write per line, so a partial line is as unlikely as we can make it.
+				let line = format!("{:.6} {}\n", delay.as_secs_f64(), data.len());
+				timing.write_all(line.as_bytes())?;
+				timing.flush()?;
+			}
+		}
+		if let Some(limit) = reached_limit {
+			// Even if that was cut short of the limit, nothing more gets recorded, so nothing
+			// will finish a character the cast is still waiting on either.
+			self.recorded = limit;
+			if let Some(cast) = &mut self.cast {
+				cast.discard_incomplete();
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Note that the child's terminal is now `size`, which only asciicasts keep track of.
+	pub fn resize(&mut self, size: libc::winsize) -> io::Result<()>
+	{
+		let Some(line) = self.cast.as_mut().and_then(|cast| cast.resize(size)) else {
+			return Ok(());
+		};
+
+		self.data.write_all(line.as_bytes())?;
+		self.data.flush()?;
+
+		Ok(())
+	}
+}
+
+/// Transforms each chunk of the child's output before it's written anywhere, by appending what
+/// should be written instead to the buffer it's given.
+///
+/// A chunk is whatever one round of reads from the PTY got, so chunk boundaries can fall anywhere,
+/// including in the middle of an escape sequence or a UTF-8 character. Filters that care about
+/// those need to keep the incomplete part around until the next chunk.
+pub struct OutputFilter(Box<FilterFn>);
+
+type FilterFn = dyn FnMut(&Data, &mut DataBuf);
+
+impl OutputFilter
+{
+	pub fn new<F>(filter: F) -> Self
+	where
+		F: FnMut(&Data, &mut DataBuf) + 'static,
+	{
+		Self(Box::new(filter))
+	}
+
+	/// Append the filtered version of `data` to `out`.
+	pub fn filter(&mut self, data: &Data, out: &mut DataBuf)
+	{
+		(self.0)(data, out);
+	}
+
+	/// Pass our output through `next`, so both apply, in that order.
+	pub fn then(mut self, mut next: OutputFilter) -> OutputFilter
+	{
+		let mut between = DataBuf::new();
+		OutputFilter::new(move |data: &Data, out: &mut DataBuf| {
+			between.clear();
+			self.filter(data, &mut between);
+			next.filter(&between, out);
+		})
+	}
+}
+
+impl Debug for OutputFilter
+{
+	fn fmt(&self, f: &mut Formatter) -> FmtResult
+	{
+		f.debug_tuple("OutputFilter").finish_non_exhaustive()
+	}
+}
+
+/// Called with the child's new window size whenever a change to ours is passed along to it, like
+/// to nudge programs that don't redraw on `SIGWINCH` into doing it anyway.
+///
+/// This happens after the child's PTY has already been resized, so the child sees the new size
+/// by the time it's called, and the child has already been sent its own `SIGWINCH`.
+pub struct ResizeHook(Box<ResizeFn>);
+
+type ResizeFn = dyn FnMut(libc::winsize);
+
+impl ResizeHook
+{
+	pub fn new<F>(hook: F) -> Self
+	where
+		F: FnMut(libc::winsize) + 'static,
+	{
+		Self(Box::new(hook))
+	}
+
+	pub fn call(&mut self, size: libc::winsize)
+	{
+		(self.0)(size);
+	}
+}
+
+impl Debug for ResizeHook
+{
+	fn fmt(&self, f: &mut Formatter) -> FmtResult
+	{
+		f.debug_tuple("ResizeHook").finish_non_exhaustive()
+	}
+}
+
+/// How long the child gets to exit after `SIGTERM` from a timeout, before we `SIGKILL` it.
+pub const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// When the child's output gets flushed out of our stdout buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FlushPolicy
+{
+	/// After every write, so each bit of output shows up as soon as the child writes it, like
+	/// for a person watching.
+	Interactive,
+	/// Once everything that was ready has been read, and we're about to wait for more, so a
+	/// chatty child's output goes out in as few writes as possible.
+	Batch,
+}
+
+impl FlushPolicy
+{
+	/// [`FlushPolicy::Interactive`] for a terminal, and [`FlushPolicy::Batch`] otherwise.
+	fn for_stdout(stdout: &Stdout) -> Self
+	{
+		match stdout.is_terminal() {
+			true => FlushPolicy::Interactive,
+			false => FlushPolicy::Batch,
+		}
+	}
+}
+
+/// How [`parent_process()`] should run the child, beyond forwarding stdio.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LoopOptions
+{
+	/// Pass changes to our window size along to the child.
+	pub follow_resize: bool,
+	/// Kill the child if it's still running after this long.
+	pub timeout: Option<Duration>,
+	/// Kill the child if it goes this long without any output.
+	pub idle_timeout: Option<Duration>,
+	/// When to flush the child's output to stdout, or `None` to pick based on whether stdout is
+	/// a terminal.
+	///
+	/// Either way, whatever's still buffered is flushed before the loop returns, even if it
+	/// returns an error.
+	pub flush: Option<FlushPolicy>,
+	/// The width and height of one of the child's cells in pixels, for when we follow a
+	/// terminal that doesn't report its own pixel size.
+	///
+	/// Our terminal's pixel size is passed along whenever it has one, and otherwise the child's
+	/// pixel size is scaled by this as its size in cells changes, so its aspect ratio stays right.
+	pub cell_pixels: Option<(u16, u16)>,
+	/// Read our stdin and forward it to the child, with our terminal in raw mode.
+	///
+	/// This should be off if the child reads our stdin itself, like with
+	/// [`StdioConfig::stdin`](crate::child::StdioConfig::stdin) off, so that we don't compete
+	/// with it for input, or change the terminal out from under it.
+	pub forward_stdin: bool,
+	/// Write the child's output to our stdout.
+	///
+	/// This can be off when the output is only wanted somewhere else, like when it's captured by
+	/// [`parent_process_capturing()`], or recorded.
+	pub stream: bool,
+	/// The most of the child's output to keep in memory when capturing it, if there's a limit.
+	///
+	/// Only the capture is limited; this has no effect on what goes to stdout.
+	pub capture_limit: Option<CaptureLimit>,
+}
+
+impl Default for LoopOptions
+{
+	fn default() -> Self
+	{
+		Self {
+			follow_resize: true,
+			timeout: None,
+			idle_timeout: None,
+			flush: None,
+			cell_pixels: None,
+			forward_stdin: true,
+			stream: true,
+			capture_limit: None,
+		}
+	}
+}
+
+/// What to do when the child's output goes past its [`CaptureLimit`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OverLimit
+{
+	/// Keep the output up to the limit, drop the rest, and mark the capture as
+	/// [truncated](Captured::truncated).
+	Truncate,
+	/// Stop with an error, which kills the child.
+	Fail,
+}
+
+/// Per [`LoopOptions::capture_limit`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CaptureLimit
+{
+	pub bytes: usize,
+	pub over_limit: OverLimit,
+}
+
+/// The child's output, from [`parent_process_capturing()`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Captured
+{
+	pub output: DataBuf,
+	/// Whether some of the output was dropped for going past [`LoopOptions::capture_limit`].
+	pub truncated: bool,
+}
+
+impl Captured
+{
+	/// Append `data`, as much as `limit` leaves room for.
+	fn push(&mut self, data: &Data, limit: Option<CaptureLimit>) -> miette::Result<()>
+	{
+		let room = match limit {
+			Some(limit) => limit.bytes.saturating_sub(self.output.len()),
+			None => usize::MAX,
+		};
+		if data.len() <= room {
+			self.output.extend_from_slice(data);
+			return Ok(());
+		}
+
+		let limit = limit.unwrap_or_else(|| unreachable!());
+		match limit.over_limit {
+			OverLimit::Truncate => {
+				if !self.truncated {
+					let bytes = limit.bytes;
+					warn!("child output went past the {bytes} byte capture limit; dropping the rest");
+				}
+				self.output.extend_from_slice(&data[..room]);
+				self.truncated = true;
+
+				Ok(())
+			},
+			OverLimit::Fail => {
+				miette::bail!("child output went past the {} byte capture limit", limit.bytes);
+			},
+		}
+	}
+}
+
+/// Which of the [`LoopOptions`] timeouts the child was killed for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Timeout
+{
+	/// [`LoopOptions::timeout`].
+	Total,
+	/// [`LoopOptions::idle_timeout`].
+	Idle,
+}
 
-	Ok(signal_file)
+/// State that the poll loop callback needs access to.
+#[derive(Debug)]
+struct LoopState
+{
+	/// Flushed per `flush_policy`, and when the loop is done. Dropping it flushes it too, so
+	/// bailing out early with `?` doesn't lose anything.
+	stdout: BufWriter<Stdout>,
+	/// Per [`LoopOptions::stream`].
+	stream: bool,
+	flush_policy: FlushPolicy,
+	/// Everything the child's written, after `filter`, if we're keeping it.
+	capture: Option<Captured>,
+	/// Per [`LoopOptions::capture_limit`].
+	capture_limit: Option<CaptureLimit>,
+	/// A duplicate of the PTY controller, since the original is owned by the [Poller].
+	pty: QueuedWriter,
+	/// So we can stop polling stdin once it hits end-of-file.
+	stdin_interest: Option<InterestHandle>,
+	/// Where to tee the child's output to, if anywhere.
+	logs: Vec<Recording>,
+	/// What to pass the child's output through before writing it anywhere, if anything.
+	filter: Option<OutputFilter>,
+	/// Scratch space for `filter`'s output.
+	filtered: DataBuf,
+	/// Called once the child's been resized, if anything.
+	on_resize: Option<ResizeHook>,
+	/// The child, whose process group gets the signals we forward.
+	child: Pid,
+	/// The error that made us break out of the poll loop, if any.
+	error: Option<miette::Report>,
+	/// When to give up on the child, per [`LoopOptions::timeout`].
+	deadline: Option<Instant>,
+	/// Per [`LoopOptions::idle_timeout`], which is pushed back whenever the child writes something.
+	idle_timeout: Option<Duration>,
+	idle_deadline: Option<Instant>,
+	/// When to give up on the child exiting gracefully, once we've sent it `SIGTERM`.
+	kill_at: Option<Instant>,
+	/// Why we've killed the child, if we have.
+	timed_out: Option<Timeout>,
+	/// So we wake up for deadlines even if nothing else is happening.
+	timeout: Option<TimeoutHandle>,
+	/// Set once the child has exited, after which we only forward whatever it left behind.
+	child_exited: bool,
+	/// Our terminal's attributes from before we put it in raw mode, if we did, so we can put
+	/// them back while we're stopped.
+	cooked: Option<libc::termios>,
+	/// Per [`LoopOptions::cell_pixels`].
+	cell_pixels: Option<(u16, u16)>,
+	/// Whether to reap the child ourselves as soon as it exits, instead of leaving it for the
+	/// caller to `waitpid()`.
+	reap: bool,
+	/// The child's wait status, once we've reaped it.
+	reaped: Option<WaitStatus>,
 }
 
-fn parent_loop(pty: File) -> miette::Result<()>
+impl LoopState
 {
-	let pty_key = pty.as_raw_fd() as u64;
-	// Switch to file descriptor based handling for SIGCHLD and SIGWINCH,
+	/// Pass the new size of our terminal along to the child, including its size in pixels.
+	fn resize_child(&mut self)
+	{
+		self.set_child_size();
+
+		if self.logs.is_empty() && self.on_resize.is_none() {
+			return;
+		}
+		let size = match getwinsz(self.pty.file().as_fd()) {
+			Ok(size) => size,
+			Err(e) => {
+				warn!("couldn't get new window size of child PTY: {e}");
+				return;
+			},
+		};
+		Self::each_log(&mut self.logs, |log| log.resize(size));
+		if let Some(on_resize) = &mut self.on_resize {
+			on_resize.call(size);
+		}
+	}
+
+	/// [`LoopState::resize_child()`], minus recording it.
+	fn set_child_size(&self)
+	{
+		if self.cell_pixels.is_none() {
+			debug!("resizing child PTY to match stdin");
+			if let Err(e) = sync_winsize(io::stdin().as_fd(), self.pty.file().as_fd()) {
+				warn!("couldn't pass our new window size along to the child PTY: {e}");
+			}
+			return;
+		}
+
+		match getwinsz(io::stdin().as_fd()) {
+			Ok(new_size) => {
+				let new_size = match self.cell_pixels {
+					Some((width, height)) if !WinSize::from(new_size).has_pixels() => {
+						WinSize::from_cells(new_size.ws_col, new_size.ws_row, width, height).into()
+					},
+					_ => new_size,
+				};
+				debug!("resizing child PTY to {}x{}", new_size.ws_col, new_size.ws_row);
+				if let Err(e) = setwinsz(self.pty.file().as_fd(), new_size) {
+					warn!("couldn't resize child PTY: {e}");
+				}
+			},
+			Err(e) => {
+				warn!("couldn't get new window size of stdin: {e}");
+			},
+		}
+	}
+
+	fn forward_signal(&self, signal: Signal, info: &SignalInfo)
+	{
+		// Like a ^C typed into the PTY, so it reaches whatever the child has in the foreground,
+		// the way it would have if we weren't in between.
+		if signal == Signal::SIGINT && self.signal_foreground(signal) {
+			return;
+		}
+
+		debug!("forwarding {signal} from {} to child process group {}", info.pid(), self.child);
+		self.signal_child(signal);
+	}
+
+	/// Send `signal` to the PTY's foreground process group, returning whether we could.
+	fn signal_foreground(&self, signal: Signal) -> bool
+	{
+		let pty = self.pty.file().as_fd();
+
+		// Without one, say for `--no-ctty`, `TIOCSIG` would quietly do nothing.
+		let foreground = match get_foreground_pgrp(pty) {
+			Ok(pgrp) if pgrp.as_raw() > 0 => pgrp,
+			Ok(_none) => return false,
+			Err(e) => {
+				debug!("couldn't get foreground process group of child PTY: {e}");
+				return false;
+			},
+		};
+
+		match send_tty_signal(pty, signal) {
+			Ok(()) => {
+				debug!("sent {signal} to foreground process group {foreground} through child PTY");
+				true
+			},
+			Err(e) => {
+				debug!("couldn't send {signal} through child PTY: {e}");
+				false
+			},
+		}
+	}
+
+	fn signal_child(&self, signal: Signal)
+	{
+		// The child is a session leader, so its process group ID is its PID.
+		if let Err(e) = killpg(self.child, signal) {
+			warn!("couldn't send {signal} to child process group {}: {e}", self.child);
+		}
+	}
+
+	/// The next time we need to wake up for, if any.
+	fn next_deadline(&self) -> Option<Instant>
+	{
+		[self.deadline, self.idle_deadline, self.kill_at].into_iter().flatten().min()
+	}
+
+	/// Point the poller's timeout at whichever deadline is next.
+	fn update_timeout(&self)
+	{
+		if let Some(handle) = &self.timeout {
+			if self.child_exited {
+				// Stop as soon as there's nothing left to forward.
+				handle.set(Some(Duration::ZERO));
+				return;
+			}
+
+			let now = Instant::now();
+			handle.set(self.next_deadline().map(|deadline| deadline.saturating_duration_since(now)));
+		}
+	}
+
+	/// Kill the child for whichever deadlines have passed.
+	fn check_deadlines(&mut self)
+	{
+		let now = Instant::now();
+
+		if self.kill_at.is_some_and(|kill_at| now >= kill_at) {
+			warn!(
+				"child {} still running {KILL_GRACE_PERIOD:?} after SIGTERM; sending SIGKILL",
+				self.child,
+			);
+			self.kill_at = None;
+			self.signal_child(Signal::SIGKILL);
+		}
+
+		if self.deadline.is_some_and(|deadline| now >= deadline) {
+			self.deadline = None;
+			self.time_out(Timeout::Total);
+		}
+
+		if self.idle_deadline.is_some_and(|deadline| now >= deadline) {
+			self.idle_deadline = None;
+			self.time_out(Timeout::Idle);
+		}
+
+		self.update_timeout();
+	}
+
+	/// Ask the child to exit with `SIGTERM`, and make sure it does with `SIGKILL` if it takes
+	/// too long. We keep forwarding output in the meantime.
+	fn time_out(&mut self, timeout: Timeout)
+	{
+		if self.timed_out.is_some() {
+			// Already on it.
+			return;
+		}
+
+		info!("child {} timed out ({timeout:?}); sending SIGTERM", self.child);
+		self.timed_out = Some(timeout);
+		// Don't let the other kind reset things while we wait for the child to die.
+		self.deadline = None;
+		self.idle_deadline = None;
+		self.signal_child(Signal::SIGTERM);
+		self.kill_at = Some(Instant::now() + KILL_GRACE_PERIOD);
+	}
+
+	/// Whether our child has actually exited, rather than just stopped or continued.
+	///
+	/// This doesn't reap it, so [`parent_process()`] can still `waitpid()` it for its status.
+	fn child_has_exited(&self) -> bool
+	{
+		has_exited(self.child)
+	}
+
+	/// Reap the child if it has exited, without blocking, and return whether it has.
+	///
+	/// This also notices the child stopping, and [`LoopState::suspend()`]s us along with it, then
+	/// checks again once we're continued, in case it exited in the meantime.
+	fn try_reap(&mut self) -> bool
+	{
+		use nix::sys::wait::{waitpid, WaitPidFlag};
+
+		// A later SIGCHLD can be from something else, like an --on-resize command, and waiting on
+		// the child again would only fail.
+		if self.reaped.is_some() {
+			return true;
+		}
+
+		let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED;
+		loop {
+			match waitpid(self.child, Some(flags)) {
+				Ok(WaitStatus::StillAlive) => return false,
+				Ok(status @ WaitStatus::Stopped(..)) => {
+					debug!("child {} has stopped: {status:?}", self.child);
+					self.suspend();
+				},
+				Ok(status) if ChildOutcome::from_wait_status(status).is_some() => {
+					debug!("reaped child {}: {status:?}", self.child);
+					self.reaped = Some(status);
+					return true;
+				},
+				Ok(status) => {
+					debug!("ignoring wait status {status:?} of child {}", self.child);
+					return false;
+				},
+				Err(Errno::EINTR) => (),
+				Err(e) => {
+					// Not much else we can do with it, if we can't even wait on it.
+					warn!("waitpid() on child {} failed, so treating it as gone: {e}", self.child);
+					return true;
+				},
+			}
+		}
+	}
+
+	/// Whether our child has been stopped, like by `SIGTSTP` from a Ctrl-Z.
+	///
+	/// This consumes the stop, so we only see each one once.
+	fn child_has_stopped(&self) -> bool
+	{
+		use nix::sys::wait::{waitid, Id, WaitPidFlag, WaitStatus};
+
+		let flags = WaitPidFlag::WSTOPPED | WaitPidFlag::WNOHANG;
+		match waitid(Id::Pid(self.child), flags) {
+			Ok(status @ WaitStatus::Stopped(..)) => {
+				debug!("child {} has stopped: {status:?}", self.child);
+				true
+			},
+			Ok(_status) => false,
+			Err(e) => {
+				warn!("waitid() on child {} failed: {e}", self.child);
+				false
+			},
+		}
+	}
+
+	/// Stop ourselves along with our stopped child, so Ctrl-Z puts the whole thing in the
+	/// background of whatever shell started us, and then continue the child once we're
+	/// continued.
+	///
+	/// While we're stopped, our terminal goes back to the attributes it had before raw mode,
+	/// since the shell taking it back over won't expect it to be raw. Once we're continued, it
+	/// goes back into raw mode, even if the shell changed it in the meantime.
+	///
+	/// If stdin isn't a terminal, there's probably no shell doing job control for us to return
+	/// to, and stopping would just hang whatever's running us, so we leave the child stopped
+	/// until someone else continues it (and, usually, us with it).
+	fn suspend(&mut self)
+	{
+		let stdin = io::stdin();
+		if !stdin.is_terminal() {
+			info!("child {} stopped, but stdin isn't a terminal; not stopping ourselves", self.child);
+			return;
+		}
+
+		let raw = match self.cooked {
+			Some(cooked) => {
+				let raw = get_termios(stdin.as_fd())
+					.inspect_err(|e| warn!("couldn't get raw terminal attributes: {e}"))
+					.ok();
+				if let Err(e) = set_termios(stdin.as_fd(), SetArg::TCSADRAIN, &cooked) {
+					warn!("couldn't restore terminal from raw mode before stopping: {e}");
+				}
+				raw
+			},
+			None => None,
+		};
+
+		info!("child {} stopped; stopping ourselves too", self.child);
+		// Our whole process group, like the terminal driver would for Ctrl-Z, so whatever
+		// started us as a job sees it stop.
+		if let Err(e) = killpg(getpgrp(), Signal::SIGSTOP) {
+			warn!("couldn't stop ourselves: {e}");
+		}
+
+		// We don't get here until we're continued.
+		info!("continued; continuing child {}", self.child);
+		if let Some(raw) = raw {
+			if let Err(e) = set_termios(stdin.as_fd(), SetArg::TCSANOW, &raw) {
+				warn!("couldn't put terminal back into raw mode: {e}");
+			}
+		}
+		self.signal_child(Signal::SIGCONT);
+	}
+
+	/// Forward whatever output is still in the PTY, since the child may have written a last burst
+	/// right before exiting that we haven't gotten an event for yet.
+	fn drain_pty(&mut self) -> miette::Result<()>
+	{
+		let mut buffer = DataBuf::zeroed(DEFAULT_BUFFER_SIZE);
+		let mut data = DataBuf::new();
+		let mut pty: &File = self.pty.file();
+		match pty.read_until_block_into(&mut buffer, &mut data) {
+			Ok(_count) => (),
+			// The usual end, once the child side is closed and everything's been read.
+			Err(e) if e.raw_os_error() == Some(libc::EIO) => (),
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => {
+				return Err(e).into_diagnostic().context("draining child PTY");
+			},
+		}
+		trace!("drained {} more bytes from child PTY", data.len());
+
+		self.pty_output(&data)
+	}
+
+	/// Write whatever input we couldn't write to the child before.
+	fn flush_pty(&mut self) -> miette::Result<()>
+	{
+		self.pty.flush_pending()
+			.into_diagnostic()
+			.context("writing queued input to child PTY")
+	}
+
+	/// Pass output from the child along to stdout, the log if we have one, and the capture buffer
+	/// if we're capturing.
+	fn pty_output(&mut self, data: &Data) -> miette::Result<()>
+	{
+		if data.is_empty() {
+			return Ok(());
+		}
+
+		if let (Some(idle_timeout), None) = (self.idle_timeout, self.timed_out) {
+			self.idle_deadline = Some(Instant::now() + idle_timeout);
+		}
+
+		let data: &Data = match &mut self.filter {
+			Some(filter) => {
+				self.filtered.clear();
+				filter.filter(data, &mut self.filtered);
+				&self.filtered
+			},
+			None => data,
+		};
+		if data.is_empty() {
+			// Filtered out entirely, or held back for now.
+			return Ok(());
+		}
+
+		if self.stream {
+			self.stdout.write_all(data)
+				.into_diagnostic()
+				.context("writing child output to stdout")?;
+			if self.flush_policy == FlushPolicy::Interactive {
+				self.stdout.flush()
+					.into_diagnostic()
+					.context("flushing child output to stdout")?;
+			}
+		}
+
+		if let Some(capture) = &mut self.capture {
+			capture.push(data, self.capture_limit)?;
+		}
+
+		Self::each_log(&mut self.logs, |log| log.record(data));
+
+		Ok(())
+	}
+
+	/// Flush stdout, if [`FlushPolicy::Batch`] has been holding output back until everything
+	/// that was ready has been handled.
+	fn end_batch(&mut self) -> miette::Result<()>
+	{
+		if self.flush_policy != FlushPolicy::Batch {
+			return Ok(());
+		}
+
+		self.stdout.flush()
+			.into_diagnostic()
+			.context("flushing child output to stdout")
+	}
+
+	/// Do `write` to each of `logs`, and stop logging to any that fail.
+	fn each_log<F>(logs: &mut Vec<Recording>, mut write: F)
+	where
+		F: FnMut(&mut Recording) -> io::Result<()>,
+	{
+		logs.retain_mut(|log| match write(log) {
+			Ok(()) => true,
+			Err(e) => {
+				// Don't take down the whole session over the log file.
+				error!("error writing to log file, no longer logging to it: {e}");
+				false
+			},
+		});
+	}
+
+	/// Pass input from stdin along to the child, and then end-of-file too, if stdin hit it (`eof`).
+	fn stdin_input(&mut self, data: &Data, eof: bool) -> miette::Result<()>
+	{
+		if !data.is_empty() {
+			self.pty.write(data)
+				.into_diagnostic()
+				.context("forwarding stdin to child PTY")?;
+		}
+		if !eof {
+			return Ok(());
+		}
+
+		debug!("stdin reached end-of-file; no longer forwarding it to the child");
+		if let Some(interest) = &self.stdin_interest {
+			interest.set_read(false);
+		}
+
+		// Let the child know too, the same way a user at a terminal would.
+		let eof_char: u8 = match get_termios(self.pty.file().as_fd()) {
+			Ok(termios) => termios.c_cc[libc::VEOF],
+			Err(e) => {
+				warn!("couldn't get EOF character of child PTY, assuming ^D: {e}");
+				CONTROL_D
+			},
+		};
+
+		self.pty.write(&[eof_char])
+			.into_diagnostic()
+			.context("forwarding end-of-file to child PTY")
+	}
+
+	/// Handle signals read from our signalfd. Breaks once the child has changed state.
+	fn signals(&mut self, data: &Data) -> miette::Result<ControlFlow<()>>
+	{
+		let infos = decode_siginfo(data)?;
+
+		let mut child_changed = false;
+		let mut resized = false;
+		for info in infos {
+			match info.signal() {
+				Some(Signal::SIGCHLD) => {
+					debug!(
+						"SIGCHLD from {} with code {} and status {}",
+						info.pid(),
+						info.code(),
+						info.status(),
+					);
+					// `SIGCHLD`s coalesce, so this one could be a stop or continue, yet still
+					// stand in for an exit too. We check what actually happened below.
+					child_changed = true;
+				},
+				Some(Signal::SIGWINCH) => {
+					trace!("got sigwinch!");
+					resized = true;
+				},
+				Some(signal) if FORWARDED_SIGNALS.contains(&signal) => {
+					self.forward_signal(signal, &info);
+				},
+				Some(Signal::SIGCONT) => {
+					// We were stopped by something other than our child stopping, since
+					// `suspend()` continues the child itself. Either way, the child shouldn't
+					// stay stopped if we aren't.
+					debug!("continued by {}; continuing child {}", info.pid(), self.child);
+					self.signal_child(Signal::SIGCONT);
+				},
+				_ => {
+					warn!("got unexpected signal {} from signalfd", info.signo());
+				},
+			}
+		}
+
+		// We only care about the latest size, no matter how many SIGWINCHes are queued.
+		if resized {
+			self.resize_child();
+		}
+
+		if child_changed {
+			let exited = if self.reap { self.try_reap() } else { self.child_has_exited() };
+			if exited {
+				self.drain_pty()?;
+				self.child_exited = true;
+				return Ok(ControlFlow::Break(()));
+			}
+			// try_reap() handles stops itself.
+			if !self.reap && self.child_has_stopped() {
+				self.suspend();
+			}
+		}
+
+		Ok(ControlFlow::Continue(()))
+	}
+}
+
+/// Whether `child` has actually exited, rather than just stopped or continued, without reaping it.
+fn has_exited(child: Pid) -> bool
+{
+	use nix::sys::wait::{waitid, Id, WaitPidFlag, WaitStatus};
+
+	let flags = WaitPidFlag::WEXITED | WaitPidFlag::WNOHANG | WaitPidFlag::WNOWAIT;
+	match waitid(Id::Pid(child), flags) {
+		Ok(WaitStatus::StillAlive) => false,
+		Ok(status) => {
+			debug!("child {child} has exited: {status:?}");
+			true
+		},
+		Err(e) => {
+			// Not much else we can do with it, if we can't even wait on it.
+			warn!("waitid() on child {child} failed, so treating it as gone: {e}");
+			true
+		},
+	}
+}
+
+/// How often [`terminate_child()`] checks whether the child has exited yet.
+const TERMINATE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Make sure `child` doesn't outlive us: ask its process group to exit with `SIGTERM`, give it
+/// up to `grace` to do so, and then `SIGKILL` it if it still hasn't, and reap it either way.
+///
+/// This is for when we have to give up on the child before it's exited, like when the poll loop
+/// fails, rather than waiting on something that may never exit.
+pub fn terminate_child(child: Pid, grace: Duration) -> nix::Result<WaitStatus>
+{
+	use nix::sys::wait::{waitpid, WaitPidFlag};
+
+	info!("terminating child {child}; sending SIGTERM");
+	// The child is a session leader, so its process group ID is its PID.
+	// It could be stopped, too, in which case it can't act on SIGTERM until it's continued.
+	for signal in [Signal::SIGTERM, Signal::SIGCONT] {
+		if let Err(e) = killpg(child, signal) {
+			warn!("couldn't send {signal} to child process group {child}: {e}");
+		}
+	}
+
+	let give_up_at = Instant::now() + grace;
+	loop {
+		match waitpid(child, Some(WaitPidFlag::WNOHANG))? {
+			WaitStatus::StillAlive => (),
+			status => {
+				debug!("waitpid() after SIGTERM returned {status:?}");
+				return Ok(status);
+			},
+		};
+		if Instant::now() >= give_up_at {
+			break;
+		}
+		std::thread::sleep(TERMINATE_POLL_INTERVAL);
+	}
+
+	warn!("child {child} still running {grace:?} after SIGTERM; sending SIGKILL");
+	if let Err(e) = killpg(child, Signal::SIGKILL) {
+		warn!("couldn't send SIGKILL to child process group {child}: {e}");
+	}
+	let status = waitpid(child, None)?;
+	debug!("waitpid() after SIGKILL returned {status:?}");
+
+	Ok(status)
+}
+
+/// Duplicate stdin so we can forward it to the child, if it's something we can poll.
+fn stdin_source() -> Option<File>
+{
+	let stdin: File = match io::stdin().as_fd().try_clone_to_owned() {
+		Ok(fd) => File::from(fd),
+		Err(e) => {
+			warn!("couldn't duplicate stdin, so not forwarding it to the child: {e}");
+			return None;
+		},
+	};
+
+	// FIXME: forward things like regular files with plain reads instead.
+	if !is_pollable(&stdin) {
+		debug!("stdin can't be polled; not forwarding it to the child");
+		return None;
+	}
+
+	Some(stdin)
+}
+
+/// Signals sent to us that we pass along to the child's process group.
+///
+/// In raw mode, things like Ctrl-C reach the child directly as input, so these really matter
+/// when floatty itself gets signalled, like by a supervisor or when its terminal hangs up.
+///
+/// That goes for `SIGTSTP` too: in raw mode Ctrl-Z is just input to the child, and its own
+/// terminal sends it `SIGTSTP`. Either way, we only stop ourselves once the child actually has
+/// stopped, in [`LoopState::suspend()`], so a child that ignores `SIGTSTP` keeps us running too.
+/// Note that since the child leads its own session, the kernel discards `SIGTSTP` for it unless
+/// it handles it, the way programs like editors do, by stopping itself with `SIGSTOP`.
+const FORWARDED_SIGNALS: [Signal; 4] = [
+	Signal::SIGINT,
+	Signal::SIGTERM,
+	Signal::SIGHUP,
+	Signal::SIGTSTP,
+];
+
+/// Every signal the parent loop handles through its signalfd.
+fn loop_signals(follow_resize: bool) -> Vec<Signal>
+{
+	let mut signals = vec![Signal::SIGCHLD];
+	// SIGWINCH is ignored by default, so if we don't care about it, we can just leave it be.
+	if follow_resize {
+		signals.push(Signal::SIGWINCH);
+	}
+	signals.extend(FORWARDED_SIGNALS);
+	// So we can continue the child if something continues us.
+	signals.push(Signal::SIGCONT);
+
+	signals
+}
+
+/// The sources in the parent loop's [Poller].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Source
+{
+	/// Our signalfd, for all of [`loop_signals()`].
+	Signals,
+	Pty,
+	Stdin,
+}
+
+/// At most how much of each event's data we hexdump when tracing events.
+const MAX_LOGGED_PAYLOAD: usize = 64;
+
+/// Log what `event` was and how much data came with it, and at trace level, the (start of the)
+/// data itself.
+fn log_event<K: Debug>(event: &PollEvent<K>, data: &Data)
+{
+	debug!(
+		target: EVENTS_LOG_TARGET,
+		"{:?} event: readable: {}, writable: {}, {} bytes",
+		event.key,
+		event.readable,
+		event.writable,
+		data.len(),
+	);
+
+	if data.is_empty() || !log::log_enabled!(target: EVENTS_LOG_TARGET, log::Level::Trace) {
+		return;
+	}
+
+	let shown = &data[..data.len().min(MAX_LOGGED_PAYLOAD)];
+	let hex: Vec<String> = shown.iter().map(|byte| format!("{byte:02x}")).collect();
+	let truncated = if shown.len() < data.len() { " ..." } else { "" };
+	trace!(
+		target: EVENTS_LOG_TARGET,
+		"{:?} data: {}{truncated} ({:?}{truncated})",
+		event.key,
+		hex.join(" "),
+		BStr::new(shown),
+	);
+}
+
+/// What [`parent_loop()`] hands back whether or not it fails.
+#[derive(Debug, Default)]
+struct LoopLeftovers
+{
+	/// The child's wait status, if the loop reaped it.
+	reaped: Option<WaitStatus>,
+	/// Everything the child wrote, if the loop was asked to capture it.
+	captured: Option<Captured>,
+}
+
+/// Returns why the child was killed, if we killed it.
+///
+/// The child is reaped as soon as we see it exit, and its wait status put in `leftovers`, along
+/// with its output if `leftovers.captured` starts out as `Some`, even if we go on to return an
+/// error.
+#[allow(clippy::too_many_arguments)]
+fn parent_loop(
+	child: Pid,
+	pty: File,
+	logs: Vec<Recording>,
+	filter: Option<OutputFilter>,
+	on_resize: Option<ResizeHook>,
+	cooked: Option<libc::termios>,
+	options: LoopOptions,
+	leftovers: &mut LoopLeftovers,
+) -> miette::Result<Option<Timeout>>
+{
+	let pty_raw = pty.as_raw_fd();
+	let pty_dup = pty.try_clone()
+		.into_diagnostic()
+		.context("duplicating PTY file descriptor for writes and window size changes")?;
+	// Switch to file descriptor based handling for all the signals we care about,
 	// so we can multiplex them and PTY output.
-	let sigchld: File = handle_signals_as_file(&[Signal::SIGCHLD])
-		.context("turning SIGCHLD into a file descriptor")?;
-	let sigchld_key = sigchld.as_raw_fd() as u64;
-	trace!("turned SIGCHLD into file descriptor {}", sigchld.as_raw_fd());
+	let signals = loop_signals(options.follow_resize);
+	// Declared before the poller, so the poller closes the signalfd before the mask is restored.
+	let (signal_file, _mask_guard): (File, _) = handle_signals_as_file(&signals)
+		.with_context(|| format!("turning {signals:?} into a file descriptor"))?;
+	trace!("turned {signals:?} into file descriptor {}", signal_file.as_raw_fd());
 
-	let sigwinch: File = handle_signals_as_file(&[Signal::SIGWINCH])
-		.context("turning SIGWINCH into a file descriptor")?;
-	let sigwinch_key = sigwinch.as_raw_fd() as u64;
-	trace!("turned SIGWINCH into file descriptor {}", sigwinch.as_raw_fd());
+	let poll_signals = PollInterest::read(signal_file).with_key(Source::Signals);
+	let poll_pty = PollInterest::read_pty(pty).with_key(Source::Pty);
 
-	let poll_sigchld = PollInterest::read(sigchld);
-	let poll_sigwinch = PollInterest::read(sigwinch);
-	let poll_pty = PollInterest::read(pty);
+	let mut sources = vec![poll_signals, poll_pty];
+
+	// We leave stdin blocking, since its file status flags are probably shared with stdout,
+	// and whatever shell we were started from.
+	let stdin = if options.forward_stdin { stdin_source() } else { None };
+	let stdin_raw = stdin.as_ref().map(|stdin| stdin.as_raw_fd());
+	if let Some(stdin) = stdin {
+		sources.push(PollInterest::read_blocking(stdin).with_key(Source::Stdin));
+	}
 
-	let sources = [poll_sigchld, poll_sigwinch, poll_pty];
 	let mut poller = Poller::with_sources(sources)
-		.context("initializing pollers for SIGCHLD, SIGWINCH, and child PTY")?;
-
-	let mut stdout = io::stdout();
-	poller.each_with(&mut stdout, |stdout, event, data| {
-		debug!("got event: {event:?}");
-
-		if event.key as u64 == pty_key {
-			stdout.write_all(&data).unwrap();
-		} else if event.key as u64 == sigwinch_key {
-			trace!("got sigwinch!");
-		} else if event.key as u64 == sigchld_key {
-			trace!("got sigchld");
+		.context("initializing pollers for signals, child PTY, and stdin")?;
+
+	let pty_interest = poller.interest(pty_raw).unwrap_or_else(|| unreachable!());
+	let stdin_interest = stdin_raw.and_then(|raw_fd| poller.interest(raw_fd));
+
+	let stdout = io::stdout();
+	let mut state = LoopState {
+		flush_policy: options.flush.unwrap_or_else(|| FlushPolicy::for_stdout(&stdout)),
+		stdout: BufWriter::new(stdout),
+		stream: options.stream,
+		capture: leftovers.captured.take(),
+		capture_limit: options.capture_limit,
+		pty: QueuedWriter::with_interest(pty_dup, pty_interest),
+		stdin_interest,
+		logs,
+		filter,
+		filtered: DataBuf::new(),
+		on_resize,
+		child,
+		error: None,
+		deadline: options.timeout.map(|timeout| Instant::now() + timeout),
+		idle_timeout: options.idle_timeout,
+		idle_deadline: options.idle_timeout.map(|timeout| Instant::now() + timeout),
+		kill_at: None,
+		timed_out: None,
+		timeout: Some(poller.timeout_handle()),
+		child_exited: false,
+		cooked,
+		cell_pixels: options.cell_pixels,
+		reap: true,
+		reaped: None,
+	};
+	state.update_timeout();
+
+	let on_event = |state: &mut LoopState, event: PollEvent<Source>, data: &Data| {
+		log_event(&event, data);
+
+		let result = match event.key {
+			Source::Pty => try {
+				if event.writable {
+					state.flush_pty()?;
+				}
+				state.pty_output(data)?;
+				ControlFlow::Continue(())
+			},
+			Source::Stdin => state.stdin_input(data, event.eof).map(ControlFlow::Continue),
+			// Even once the child has exited, the rest of this round of events could have more of
+			// its output, so we let `on_idle` stop us once there's nothing left instead.
+			Source::Signals => state.signals(data).map(|_flow| ControlFlow::Continue(())),
+		};
+
+		// Output can move the idle deadline, and any event resets the poller's timer.
+		state.update_timeout();
+
+		// The poller's about to wait for more, so this is as much output as we'll get for now.
+		let result = match (result, event.last) {
+			(Ok(flow), true) => state.end_batch().map(|()| flow),
+			(result, _) => result,
+		};
+
+		result.unwrap_or_else(|e| {
+			state.error = Some(e);
+			ControlFlow::Break(())
+		})
+	};
+	let on_idle = |state: &mut LoopState| {
+		if state.child_exited {
 			return ControlFlow::Break(());
 		}
-
+		state.check_deadlines();
 		ControlFlow::Continue(())
-	})?;
+	};
+	let mut poll_result = poller.each_with_idle(&mut state, &on_event, &on_idle);
+
+	// The poller also stops once the child closes its side of the PTY, which it usually does by
+	// exiting, often before we've seen its SIGCHLD. Rather than block in waitpid(), keep handling
+	// signals until it's reaped, so they still get forwarded if it's actually still running.
+	// Its SIGCHLD could have already been ready alongside the PTY's end, and the poller leaves
+	// that for us to read now.
+	if poll_result.is_ok() && state.error.is_none() && !state.child_exited {
+		debug!("child closed its side of the PTY; waiting for it to exit");
+		poll_result = try {
+			poller.remove_source(pty_raw)?;
+			if let Some(raw_fd) = stdin_raw {
+				poller.remove_source(raw_fd)?;
+			}
+			poller.each_with_idle(&mut state, &on_event, &on_idle)?;
+		};
+	}
 
 	info!("exited poll loop");
+	leftovers.reaped = state.reaped;
+	leftovers.captured = state.capture.take();
+
+	// Make sure everything the child wrote before we stopped makes it out.
+	if let Err(e) = state.stdout.flush() {
+		warn!("couldn't flush stdout: {e}");
+	}
+
+	poll_result?;
+	if let Some(e) = state.error {
+		return Err(e);
+	}
 
-	Ok(())
+	Ok(state.timed_out)
 }
 
-pub fn parent_process(child: Pid, pty_fd: OwnedFd) -> miette::Result<()>
+/// How the child process terminated, as reported by `waitpid()`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChildOutcome
+{
+	/// The child exited normally, with this exit code.
+	Exited(i32),
+	/// The child was killed by `signal`.
+	Signaled
+	{
+		signal: Signal,
+		/// Whether it left a core dump behind.
+		core_dumped: bool,
+	},
+	/// We killed the child, because of this timeout.
+	TimedOut(Timeout),
+}
+
+impl ChildOutcome
+{
+	/// The outcome `status` describes, or `None` if it's not a termination at all, like a stop.
+	pub fn from_wait_status(status: WaitStatus) -> Option<Self>
+	{
+		match status {
+			WaitStatus::Exited(_pid, exit_code) => Some(ChildOutcome::Exited(exit_code)),
+			WaitStatus::Signaled(_pid, signal, core_dumped) => {
+				Some(ChildOutcome::Signaled { signal, core_dumped })
+			},
+			_ => None,
+		}
+	}
+
+	/// Whether the child exited normally with code 0.
+	pub fn success(self) -> bool
+	{
+		self == ChildOutcome::Exited(0)
+	}
+}
+
+/// What we exit with when [`LoopOptions::timeout`] passes, the same as `timeout(1)`.
+pub const TIMED_OUT_CODE: i32 = 124;
+/// What we exit with when [`LoopOptions::idle_timeout`] passes, so it can be told apart
+/// from [`TIMED_OUT_CODE`].
+pub const IDLE_TIMED_OUT_CODE: i32 = 125;
+
+/// Follows shell conventions: the exit code itself for [`ChildOutcome::Exited`],
+/// `128 + signal` for [`ChildOutcome::Signaled`], and [`TIMED_OUT_CODE`] like `timeout(1)`
+/// or [`IDLE_TIMED_OUT_CODE`] for [`ChildOutcome::TimedOut`].
+impl From<ChildOutcome> for ExitCode
+{
+	fn from(outcome: ChildOutcome) -> ExitCode
+	{
+		use ChildOutcome::*;
+		let code: i32 = match outcome {
+			Exited(exit_code) => exit_code,
+			Signaled { signal, .. } => 128 + signal as i32,
+			TimedOut(Timeout::Total) => TIMED_OUT_CODE,
+			TimedOut(Timeout::Idle) => IDLE_TIMED_OUT_CODE,
+		};
+
+		// Exit codes are truncated to their low 8 bits by the OS anyway.
+		ExitCode::from(code as u8)
+	}
+}
+
+/// The bit of a raw wait status that says the child dumped core, as tested by `WCOREDUMP()`.
+const CORE_DUMP_FLAG: i32 = 0x80;
+
+/// Equivalent to the wait status the child would have had, or for [`ChildOutcome::TimedOut`],
+/// the status of exiting with the same code as [`ExitCode`] would have.
+impl From<ChildOutcome> for ExitStatus
+{
+	fn from(outcome: ChildOutcome) -> ExitStatus
+	{
+		use ChildOutcome::*;
+		match outcome {
+			Exited(exit_code) => ExitStatus::from_raw((exit_code & 0xff) << 8),
+			Signaled { signal, core_dumped } => {
+				let core_flag = if core_dumped { CORE_DUMP_FLAG } else { 0 };
+				ExitStatus::from_raw(signal as i32 | core_flag)
+			},
+			TimedOut(Timeout::Total) => ExitStatus::from_raw(TIMED_OUT_CODE << 8),
+			TimedOut(Timeout::Idle) => ExitStatus::from_raw(IDLE_TIMED_OUT_CODE << 8),
+		}
+	}
+}
+
+/// Forward stdio between us and the child on `pty_fd` until it exits, and reap it.
+///
+/// `on_resize` is only ever called with [`LoopOptions::follow_resize`] on.
+///
+/// This doesn't print anything about how the child went; that's up to the caller, with the
+/// returned outcome.
+pub fn parent_process(
+	child: Pid,
+	pty_fd: OwnedFd,
+	logs: impl IntoIterator<Item = Recording>,
+	filter: Option<OutputFilter>,
+	on_resize: Option<ResizeHook>,
+	options: LoopOptions,
+) -> miette::Result<ChildOutcome>
+{
+	let (outcome, _captured) = forward_until_exit(
+		child,
+		pty_fd,
+		logs,
+		filter,
+		on_resize,
+		options,
+		false,
+	)?;
+
+	Ok(outcome)
+}
+
+/// Like [`parent_process()`], but also keep everything the child wrote, after `filter`, and
+/// return it along with the outcome.
+///
+/// Turn off [`LoopOptions::stream`] if it shouldn't go to stdout as well, and set
+/// [`LoopOptions::capture_limit`] if the child could write more than is worth keeping.
+pub fn parent_process_capturing(
+	child: Pid,
+	pty_fd: OwnedFd,
+	logs: impl IntoIterator<Item = Recording>,
+	filter: Option<OutputFilter>,
+	on_resize: Option<ResizeHook>,
+	options: LoopOptions,
+) -> miette::Result<(ChildOutcome, Captured)>
+{
+	let (outcome, captured) = forward_until_exit(
+		child,
+		pty_fd,
+		logs,
+		filter,
+		on_resize,
+		options,
+		true,
+	)?;
+
+	Ok((outcome, captured.unwrap_or_default()))
+}
+
+/// [`parent_process()`], returning the child's output too if `capture` is set.
+fn forward_until_exit(
+	child: Pid,
+	pty_fd: OwnedFd,
+	logs: impl IntoIterator<Item = Recording>,
+	filter: Option<OutputFilter>,
+	on_resize: Option<ResizeHook>,
+	options: LoopOptions,
+	capture: bool,
+) -> miette::Result<(ChildOutcome, Option<Captured>)>
 {
 	info!("forked to process {child}");
 
 	// We must not close this file before we waitpid().
 	let pty_file = File::from(pty_fd);
 
-	let result = parent_loop(pty_file);
+	// Put our own terminal into raw mode, so keystrokes reach the child unmodified.
+	// The guard restores it when dropped, even if the poll loop errors out or panics.
+	let stdin = io::stdin();
+	let raw_mode = if options.forward_stdin {
+		match RawMode::enable(stdin.as_fd()) {
+			Ok(guard) => Some(guard),
+			Err(TermiosError::NotATty) => {
+				debug!("stdin is not a terminal; not enabling raw mode");
+				None
+			},
+			Err(e) => {
+				warn!("couldn't put stdin into raw mode: {e}");
+				None
+			},
+		}
+	} else {
+		debug!("child reads stdin itself; not enabling raw mode");
+		None
+	};
 
-	// Gotta reap those children!
-	let status = nix::sys::wait::waitpid(child, None)
-		.into_diagnostic()
-		.with_context(|| format!("waitpid() on child {child}"))?;
-	debug!("waitpid() returned {status:?}");
+	let cooked = raw_mode.as_ref().map(|raw_mode| *raw_mode.original());
+	let mut leftovers = LoopLeftovers {
+		reaped: None,
+		captured: capture.then(Captured::default),
+	};
+	let logs: Vec<Recording> = logs.into_iter().collect();
+	let result = parent_loop(
+		child,
+		pty_file,
+		logs,
+		filter,
+		on_resize,
+		cooked,
+		options,
+		&mut leftovers,
+	);
 
-	use nix::sys::wait::WaitStatus::*;
-	match status {
-		Exited(_pid, exit_code) if exit_code != 0 => {
-			eprintln!("floatty: child exited with non-zero exit code {exit_code}");
-		},
-		Exited(_pid, _exit_code) => (),
-		Signaled(_pid, signal, _dumped) => {
-			let name = signal.as_str();
-			let number = signal as i32;
-			eprintln!("floatty: child killed by {} (signal {})", name, number);
-		},
-		Stopped(_pid, signal) => {
-			let name = signal.as_str();
-			let number = signal as i32;
-			eprintln!("floatty: child stopped by {} (signal {})", name, number);
+	// Restore cooked mode before the caller prints anything else.
+	drop(raw_mode);
+
+	// Gotta reap those children!
+	let status = match leftovers.reaped {
+		Some(status) => status,
+		// The loop failed before the child exited, so nothing's forwarding its I/O anymore,
+		// and it might never exit on its own.
+		None if result.is_err() && !has_exited(child) => terminate_child(child, KILL_GRACE_PERIOD)
+			.into_diagnostic()
+			.with_context(|| format!("terminating child {child}"))?,
+		// The loop stopped before the child exited without failing, which shouldn't happen,
+		// but we still have to wait for it.
+		None => {
+			let status = nix::sys::wait::waitpid(child, None)
+				.into_diagnostic()
+				.with_context(|| format!("waitpid() on child {child}"))?;
+			debug!("waitpid() returned {status:?}");
+			status
 		},
-		other => {
-			eprintln!("floatty: unknown waitpid() status {other:?} (floatty bug)")
-		}
-	}
+	};
+
+	let Some(outcome) = ChildOutcome::from_wait_status(status) else {
+		// We didn't ask for WUNTRACED, so we shouldn't be getting stops either.
+		miette::bail!("waitpid() status {status:?} for child {child} isn't a termination (floatty bug)");
+	};
+
+	let outcome = match result? {
+		Some(timeout) => ChildOutcome::TimedOut(timeout),
+		None => outcome,
+	};
 
-	result
+	Ok((outcome, leftovers.captured))
 }
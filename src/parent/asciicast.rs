@@ -0,0 +1,285 @@
+//! Encoding a [`Recording`](super::Recording) as an [asciicast v2] file, like `asciinema rec`
+//! makes.
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use std::fmt::Write as _;
+use std::str;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+
+use crate::{Data, DataBuf};
+
+/// Turns output and resizes into asciicast event lines, with their times relative to when the
+/// recording started.
+#[derive(Debug)]
+pub(super) struct Asciicast
+{
+	started: Instant,
+	/// The start of a UTF-8 character that the last chunk of output cut off.
+	incomplete: DataBuf,
+	/// The last size recorded, as `(columns, rows)`.
+	size: (u16, u16),
+}
+
+impl Asciicast
+{
+	/// Start a recording, returning the header line for a terminal of `size`.
+	pub(super) fn start(size: libc::winsize) -> (Self, String)
+	{
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |since| since.as_secs());
+		let header = format!(
+			"{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {timestamp}}}\n",
+			size.ws_col,
+			size.ws_row,
+		);
+		let cast = Self {
+			started: Instant::now(),
+			incomplete: DataBuf::new(),
+			size: (size.ws_col, size.ws_row),
+		};
+
+		(cast, header)
+	}
+
+	/// The event line for the child writing `data`, or `None` if there's nothing to show for it
+	/// yet, like if it's only part of a UTF-8 character.
+	///
+	/// Asciicast output is text, so anything that isn't UTF-8 becomes U+FFFD, like a terminal
+	/// would show it, except for characters split across chunks, which are put back together.
+	pub(super) fn output(&mut self, data: &Data) -> Option<String>
+	{
+		let mut joined = std::mem::take(&mut self.incomplete);
+		joined.extend_from_slice(data);
+
+		let mut text = String::with_capacity(joined.len());
+		let mut rest: &[u8] = &joined;
+		while !rest.is_empty() {
+			match str::from_utf8(rest) {
+				Ok(valid) => {
+					text.push_str(valid);
+					rest = &[];
+				},
+				Err(e) => {
+					let (valid, invalid) = rest.split_at(e.valid_up_to());
+					text.push_str(str::from_utf8(valid).unwrap_or_else(|_| unreachable!()));
+					match e.error_len() {
+						Some(len) => {
+							text.push(char::REPLACEMENT_CHARACTER);
+							rest = &invalid[len..];
+						},
+						None => {
+							// Cut off at the end, so the rest of it is in the next chunk.
+							self.incomplete = invalid.to_vec();
+							rest = &[];
+						},
+					}
+				},
+			}
+		}
+
+		if text.is_empty() {
+			return None;
+		}
+
+		Some(self.event("o", &text))
+	}
+
+	/// Forget the start of a character that the last chunk of output cut off, for when no more
+	/// output is coming to finish it.
+	pub(super) fn discard_incomplete(&mut self)
+	{
+		if !self.incomplete.is_empty() {
+			debug!("dropping {} bytes of an unfinished character", self.incomplete.len());
+			self.incomplete.clear();
+		}
+	}
+
+	/// The event line for the terminal being resized to `size`, or `None` if that's the size it
+	/// already was, since one resize can easily be reported more than once.
+	pub(super) fn resize(&mut self, size: libc::winsize) -> Option<String>
+	{
+		let size = (size.ws_col, size.ws_row);
+		if size == self.size {
+			return None;
+		}
+		self.size = size;
+
+		Some(self.event("r", &format!("{}x{}", size.0, size.1)))
+	}
+
+	/// `[time, "code", "data"]`, on its own line.
+	fn event(&self, code: &str, data: &str) -> String
+	{
+		let time = self.started.elapsed().as_secs_f64();
+		let mut line = format!("[{time:.6}, \"{code}\", \"");
+		push_json_escaped(&mut line, data);
+		line.push_str("\"]\n");
+
+		line
+	}
+}
+
+/// Where to cut `data` at or before `at` without splitting a UTF-8 character, by backing up to
+/// the start of the character `at` falls inside of, if any.
+pub(super) fn char_boundary(data: &Data, at: usize) -> usize
+{
+	let mut at = at.min(data.len());
+	// Continuation bytes are `0b10xxxxxx`, and a character has at most three of them.
+	let earliest = at.saturating_sub(3);
+	while at > earliest && at < data.len() && data[at] & 0xc0 == 0x80 {
+		at -= 1;
+	}
+
+	at
+}
+
+/// Append `text` to `out` as the inside of a JSON string.
+fn push_json_escaped(out: &mut String, text: &str)
+{
+	for c in text.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			// Everything else below space, like ESC, has to be escaped as a code point.
+			'\0'..='\x1f' => {
+				let _ = write!(out, "\\u{:04x}", c as u32);
+			},
+			c => out.push(c),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::fs::{self, File};
+
+	use super::{char_boundary, Asciicast};
+	use crate::parent::Recording;
+	use crate::pty::WinSize;
+
+	fn cast() -> Asciicast
+	{
+		Asciicast::start(WinSize::from_cells(80, 24, 0, 0).into()).0
+	}
+
+	/// The JSON-escaped data of an event `line`, after checking it's a `code` event.
+	fn event_data<'a>(line: &'a str, code: &str) -> &'a str
+	{
+		let (_time, rest) = line.split_once(", ").expect("event has a time");
+		rest.strip_prefix(&format!("\"{code}\", \""))
+			.and_then(|rest| rest.strip_suffix("\"]\n"))
+			.unwrap_or_else(|| panic!("not a {code:?} event: {line:?}"))
+	}
+
+	#[test]
+	fn split_characters()
+	{
+		let mut cast = cast();
+		// "é" is C3 A9, and "€" is E2 82 AC.
+		assert_eq!(event_data(&cast.output(b"caf\xc3").unwrap(), "o"), "caf");
+		assert_eq!(event_data(&cast.output(b"\xa9 ").unwrap(), "o"), "é ");
+		assert_eq!(cast.output(b"\xe2"), None);
+		assert_eq!(cast.output(b"\x82"), None);
+		assert_eq!(event_data(&cast.output(b"\xac").unwrap(), "o"), "€");
+	}
+
+	#[test]
+	fn invalid_bytes()
+	{
+		let mut cast = cast();
+		let line = cast.output(b"a\xffb\xc3(").unwrap();
+		assert_eq!(event_data(&line, "o"), "a\u{fffd}b\u{fffd}(");
+	}
+
+	#[test]
+	fn json_escaping()
+	{
+		let mut cast = cast();
+		let line = cast.output(b"\x1b[1m\"quoted\" back\\slash\0\x07\r\n\t").unwrap();
+		assert_eq!(
+			event_data(&line, "o"),
+			r#"\u001b[1m\"quoted\" back\\slash\u0000\u0007\r\n\t"#,
+		);
+	}
+
+	#[test]
+	fn duplicate_resize()
+	{
+		let mut cast = cast();
+		assert_eq!(cast.resize(WinSize::from_cells(80, 24, 0, 0).into()), None);
+		let line = cast.resize(WinSize::from_cells(100, 30, 0, 0).into()).unwrap();
+		assert_eq!(event_data(&line, "r"), "100x30");
+		// Pixels don't matter to a cast, so that's still the same size.
+		assert_eq!(cast.resize(WinSize::from_cells(100, 30, 8, 16).into()), None);
+	}
+
+	#[test]
+	fn char_boundaries()
+	{
+		let data = "aé€".as_bytes();
+		assert_eq!(char_boundary(data, 0), 0);
+		assert_eq!(char_boundary(data, 1), 1);
+		assert_eq!(char_boundary(data, 2), 1);
+		assert_eq!(char_boundary(data, 3), 3);
+		assert_eq!(char_boundary(data, 5), 3);
+		assert_eq!(char_boundary(data, 6), 6);
+		assert_eq!(char_boundary(data, 100), 6);
+	}
+
+	/// What got recorded to an asciicast with a `limit`, of `chunks` of output, and what it was
+	/// left waiting on.
+	fn record_limited(name: &str, limit: usize, chunks: &[&[u8]]) -> (Vec<String>, Vec<u8>)
+	{
+		let path = std::env::temp_dir()
+			.join(format!("floatty-test-{}-{name}.cast", std::process::id()));
+		let file = File::create(&path).unwrap();
+		let size = WinSize::from_cells(80, 24, 0, 0).into();
+		let mut recording = Recording::asciicast(file, size).unwrap().with_limit(limit);
+		for chunk in chunks {
+			recording.record(chunk).unwrap();
+		}
+		let incomplete = recording.cast.as_ref().unwrap().incomplete.clone();
+		drop(recording);
+
+		let cast = fs::read_to_string(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		let output = cast
+			.split_inclusive('\n')
+			.skip(1)
+			.map(|line| event_data(line, "o").to_owned())
+			.collect();
+
+		(output, incomplete)
+	}
+
+	#[test]
+	fn limit_mid_character()
+	{
+		// The limit falls inside the second "é", just after the one the first chunk started.
+		let chunks: &[&[u8]] = &[b"ab\xc3", b"\xa9\xc3\xa9 more", b"after"];
+		let (output, incomplete) = record_limited("mid-character", 5, chunks);
+		assert_eq!(output, ["ab", "é"]);
+		assert!(incomplete.is_empty(), "cast still waiting on {incomplete:?}");
+	}
+
+	#[test]
+	fn limit_after_partial_character()
+	{
+		// The limit is reached with half an "é" recorded, which nothing will ever finish.
+		let (output, incomplete) = record_limited("after-partial", 3, &[b"ab\xc3", b"\xa9 more"]);
+		assert_eq!(output, ["ab"]);
+		assert!(incomplete.is_empty(), "cast still waiting on {incomplete:?}");
+	}
+}
@@ -0,0 +1,197 @@
+//! A [`tokio`] equivalent of the [`Poller`](crate::poller::Poller) based parent loop.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	miette::{Context as _, IntoDiagnostic},
+	tap::prelude::*,
+};
+use nix::unistd::Pid;
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+
+use crate::poller::{NonblockingRead, DEFAULT_BUFFER_SIZE};
+use crate::writer::QueuedWriter;
+use crate::{DataBuf, DataBufExt};
+use super::{handle_signals_as_file, loop_signals, stdin_source, FlushPolicy, LoopState, Recording};
+
+/// Whether a read from `fd` wouldn't block, for files that aren't `O_NONBLOCK`.
+///
+/// tokio's readiness is edge-triggered, so we can only clear it once there's nothing left.
+fn has_input(fd: BorrowedFd) -> bool
+{
+	let mut pollfd = libc::pollfd {
+		fd: fd.as_raw_fd(),
+		events: libc::POLLIN,
+		revents: 0,
+	};
+
+	// A zero timeout means this only checks, and never waits.
+	// SAFETY: `pollfd` is a valid, initialized array of the one `pollfd` we say there is, and it
+	// lives for the whole call.
+	let code = unsafe { libc::poll(&raw mut pollfd, 1, 0) };
+	code > 0
+}
+
+/// Forward stdio between us and `child` over `pty` until the child changes state, like the loop
+/// in [`parent_process()`](super::parent_process), but by awaiting readiness in the current
+/// tokio runtime instead of blocking the thread.
+///
+/// Unlike [`parent_process()`](super::parent_process), this doesn't put our terminal into raw
+/// mode, reap the child, or support timeouts; that's up to the caller, who can use tokio's own
+/// timers for the latter.
+///
+/// The signals we handle are blocked with `sigprocmask()`, which only affects the calling
+/// thread, so other runtime threads should block them too, or things like `SIGINT` may still
+/// take down the whole process instead of being forwarded.
+pub async fn parent_loop_async(
+	child: Pid,
+	mut pty: AsyncFd<File>,
+	log: Option<Recording>,
+	follow_resize: bool,
+) -> miette::Result<()>
+{
+	let pty_dup = pty.get_ref().try_clone()
+		.into_diagnostic()
+		.context("duplicating PTY file descriptor for writes and window size changes")?;
+
+	let signals = loop_signals(follow_resize);
+	let (signal_file, _mask_guard): (File, _) = handle_signals_as_file(&signals)
+		.with_context(|| format!("turning {signals:?} into a file descriptor"))?;
+	trace!("turned {signals:?} into file descriptor {}", signal_file.as_raw_fd());
+	let mut signal_file = AsyncFd::with_interest(signal_file, Interest::READABLE)
+		.into_diagnostic()
+		.context("registering signal file descriptor with tokio")?;
+
+	// Like the sync loop, we leave stdin blocking, and only do one read per readiness.
+	let mut stdin: Option<AsyncFd<File>> = stdin_source().and_then(|stdin| {
+		AsyncFd::with_interest(stdin, Interest::READABLE)
+			.inspect_err(|e| warn!("couldn't register stdin with tokio, so not forwarding it: {e}"))
+			.ok()
+	});
+
+	let stdout = io::stdout();
+	let mut state = LoopState {
+		flush_policy: FlushPolicy::for_stdout(&stdout),
+		stdout: BufWriter::new(stdout),
+		stream: true,
+		capture: None,
+		capture_limit: None,
+		pty: QueuedWriter::new(pty_dup),
+		stdin_interest: None,
+		logs: log.into_iter().collect(),
+		filter: None,
+		filtered: DataBuf::new(),
+		on_resize: None,
+		child,
+		error: None,
+		deadline: None,
+		idle_timeout: None,
+		idle_deadline: None,
+		kill_at: None,
+		timed_out: None,
+		timeout: None,
+		child_exited: false,
+		cooked: None,
+		cell_pixels: None,
+		// Our caller reaps the child.
+		reap: false,
+		reaped: None,
+	};
+
+	let mut buffer = DataBuf::zeroed(DEFAULT_BUFFER_SIZE);
+	let mut data = DataBuf::new();
+
+	loop {
+		data.clear();
+		// We only get back here once a read would block, or something else needs handling.
+		state.end_batch()?;
+
+		let pty_interest = if state.pty.has_pending() {
+			Interest::READABLE | Interest::WRITABLE
+		} else {
+			Interest::READABLE
+		};
+
+		tokio::select! {
+			guard = pty.ready_mut(pty_interest) => {
+				let mut guard = guard.into_diagnostic().context("waiting on child PTY")?;
+				let ready = guard.ready();
+
+				if ready.is_writable() {
+					state.flush_pty()?;
+					if state.pty.has_pending() {
+						guard.clear_ready_matching(tokio::io::Ready::WRITABLE);
+					}
+				}
+
+				if ready.is_readable() {
+					match guard.get_inner_mut().read_until_block_into(&mut buffer, &mut data) {
+						// Nothing left, so now we can wait for the next edge.
+						Ok(0) => guard.clear_ready_matching(tokio::io::Ready::READABLE),
+						Ok(_count) => state.pty_output(&data)?,
+						Err(e) if e.raw_os_error() == Some(libc::EIO) => {
+							// Linux's way of saying the other side of the PTY was closed.
+							debug!("child PTY returned EIO; treating as end-of-file");
+							break;
+						},
+						Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+						Err(e) => {
+							return Err(e).into_diagnostic().context("reading from child PTY");
+						},
+					}
+				}
+			},
+
+			guard = signal_file.readable_mut() => {
+				let mut guard = guard.into_diagnostic().context("waiting on signal file descriptor")?;
+				match guard.get_inner_mut().read_until_block_into(&mut buffer, &mut data) {
+					Ok(0) => guard.clear_ready(),
+					Ok(_count) => {
+						if state.signals(&data)?.is_break() {
+							break;
+						}
+					},
+					Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+					Err(e) => {
+						return Err(e).into_diagnostic().context("reading from signal file descriptor");
+					},
+				}
+			},
+
+			// Lazy, so this only gets unwrapped if the precondition passed.
+			guard = async { stdin.as_mut().unwrap().readable_mut().await }, if stdin.is_some() => {
+				let mut guard = guard.into_diagnostic().context("waiting on stdin")?;
+				let count = match guard.get_inner_mut().read(&mut buffer) {
+					Ok(count) => count,
+					Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+					Err(e) => {
+						return Err(e).into_diagnostic().context("reading from stdin");
+					},
+				};
+				if !has_input(guard.get_inner().as_fd()) {
+					guard.clear_ready();
+				}
+				drop(guard);
+
+				data.extend_from_slice(&buffer[..count]);
+				state.stdin_input(&data, count == 0)?;
+				if count == 0 {
+					stdin = None;
+				}
+			},
+		}
+	}
+
+	info!("exited async parent loop");
+
+	if let Err(e) = state.stdout.flush() {
+		warn!("couldn't flush stdout: {e}");
+	}
+
+	Ok(())
+}
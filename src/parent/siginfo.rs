@@ -0,0 +1,80 @@
+use std::mem;
+use std::ptr;
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+
+use crate::Data;
+
+/// One `struct signalfd_siginfo`, as read from a signalfd.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SignalInfo(pub libc::signalfd_siginfo);
+
+impl SignalInfo
+{
+	pub const SIZE: usize = mem::size_of::<libc::signalfd_siginfo>();
+
+	/// The raw signal number.
+	pub fn signo(&self) -> i32
+	{
+		self.0.ssi_signo as i32
+	}
+
+	/// The signal, if it's one [`nix`] knows about.
+	pub fn signal(&self) -> Option<Signal>
+	{
+		Signal::try_from(self.signo()).ok()
+	}
+
+	/// The PID of the process that sent the signal, or for `SIGCHLD`, the child that
+	/// changed state.
+	pub fn pid(&self) -> Pid
+	{
+		Pid::from_raw(self.0.ssi_pid as libc::pid_t)
+	}
+
+	/// For `SIGCHLD`, the exit status or signal of the child.
+	pub fn status(&self) -> i32
+	{
+		self.0.ssi_status
+	}
+
+	/// The signal code, like `CLD_EXITED` for `SIGCHLD`.
+	pub fn code(&self) -> i32
+	{
+		self.0.ssi_code
+	}
+}
+
+/// Decode data read from a signalfd into each signal it contains, since a single read
+/// can contain multiple queued signals.
+///
+/// Fails if `data` isn't a whole number of `struct signalfd_siginfo`s, which means it didn't
+/// actually come from a signalfd.
+pub fn decode_siginfo(data: &Data) -> miette::Result<Vec<SignalInfo>>
+{
+	if !data.len().is_multiple_of(SignalInfo::SIZE) {
+		miette::bail!(
+			"signalfd data of {} bytes is not a multiple of signalfd_siginfo ({} bytes)",
+			data.len(),
+			SignalInfo::SIZE,
+		);
+	}
+
+	let infos = data
+		.chunks_exact(SignalInfo::SIZE)
+		.map(|chunk| {
+			// SAFETY: `chunk` is exactly the size of a `signalfd_siginfo`, which is plain old
+			// data. Our buffer isn't necessarily aligned for it, hence the unaligned read.
+			let raw = unsafe { ptr::read_unaligned(chunk.as_ptr().cast::<libc::signalfd_siginfo>()) };
+			SignalInfo(raw)
+		})
+		.collect();
+
+	Ok(infos)
+}
@@ -1,6 +1,3 @@
-use std::error::Error as StdError;
-use std::fmt::{Display, Result as FmtResult, Formatter};
-
 #[allow(unused_imports)]
 use {
     log::{trace, debug, info, warn, error},
@@ -11,21 +8,47 @@ use {
 	nix::errno::Errno,
 };
 
-/// The error type for [`handle_signals_as_file()`], which contains variants for all error codes
-/// that can be returned by `signalfd(2)`.
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`signalfd()`](super::signalfd), which contains variants for all error
+/// codes that can be returned by `signalfd(2)` when creating a new file descriptor.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SignalfdError
 {
 	/// `flags` is invalid.
+	#[diagnostic(
+		code(floatty::signalfd::invalid_flags),
+		help("this is probably a bug in floatty"),
+	)]
 	InvalidFlags,
 	/// The per-process limit on the number of open file descriptors has been reached.
+	#[diagnostic(
+		code(floatty::signalfd::exhausted_file_descriptors),
+		help("close some file descriptors, or raise the limit with `ulimit -n`"),
+	)]
 	ExhaustedFileDescriptors,
 	/// The system-wide limit on the total number of open files has been reached.
+	#[diagnostic(
+		code(floatty::signalfd::exhausted_files),
+		help("close some files, or raise the system limit in /proc/sys/fs/file-max"),
+	)]
 	ExhaustedFiles,
 	/// Could not mount (internal) anonymous inode device.
+	#[diagnostic(
+		code(floatty::signalfd::failed_to_mount_inode),
+		help("the kernel couldn't set up anonymous inodes; this is a system problem"),
+	)]
 	FailedToMountInode,
 	/// There was insufficient memory to create a new signalfd file descriptor.
+	#[diagnostic(
+		code(floatty::signalfd::exhausted_memory),
+		help("free up some memory and try again"),
+	)]
 	ExhaustedMemory,
+	/// An error code that `signalfd()` isn't documented to return.
+	#[diagnostic(code(floatty::signalfd::other))]
+	Other(OtherErrno),
 }
 
 impl SignalfdError
@@ -48,16 +71,6 @@ impl SignalfdError
 		Some(signalfd_error)
 	}
 
-	pub fn from_errno(raw: Errno) -> Self
-	{
-		match Self::try_from_raw(raw) {
-			Some(err) => err,
-			None => {
-				panic!("signalfd() gave supposedly impossible error code {raw}");
-			},
-		}
-	}
-
 	pub const fn to_errno(self) -> Errno
 	{
 		use Errno::*;
@@ -68,24 +81,12 @@ impl SignalfdError
 			ExhaustedFiles => ENFILE,
 			FailedToMountInode => ENODEV,
 			ExhaustedMemory => ENOMEM,
-		}
-	}
-
-	pub const fn as_errno(self) -> &'static Errno
-	{
-		use Errno::*;
-		use SignalfdError::*;
-		match self {
-			InvalidFlags => &EINVAL,
-			ExhaustedFileDescriptors => &EMFILE,
-			ExhaustedFiles => &ENFILE,
-			FailedToMountInode => &ENODEV,
-			ExhaustedMemory => &ENOMEM,
+			Other(other) => other.errno(),
 		}
 	}
 
 	/// Not to be confused with [`std::error::Error::description()`].
-	pub const fn desc(self) -> &'static str
+	pub fn desc(self) -> &'static str
 	{
 		// Descriptions from `signalfd(2)`.
 		use SignalfdError::*;
@@ -105,46 +106,9 @@ impl SignalfdError
 			ExhaustedMemory => {
 				"There was insufficient memory to create a new signalfd file descriptor"
 			},
+			Other(other) => other.errno().desc(),
 		}
 	}
 }
 
-impl Display for SignalfdError
-{
-	fn fmt(&self, f: &mut Formatter) -> FmtResult
-	{
-		let description: &'static str = self.desc();
-		f.write_str(description)?;
-
-		Ok(())
-	}
-}
-
-/// [`std::error::Error::source()`] returns the [`nix::Error`] that caused this error.
-impl StdError for SignalfdError
-{
-	fn source(&self) -> Option<&(dyn StdError + 'static)>
-	{
-		// We can actually reconstruct the source error trivially,
-		// so we don't even need to store it.
-		let nix_error: &'static Errno = self.as_errno();
-
-		Some(nix_error)
-	}
-}
-
-impl From<Errno> for SignalfdError
-{
-	fn from(other: Errno) -> Self
-	{
-		Self::from_errno(other)
-	}
-}
-
-impl From<SignalfdError> for Errno
-{
-	fn from(other: SignalfdError) -> Self
-	{
-		SignalfdError::to_errno(other)
-	}
-}
+impl_errno_error!(SignalfdError, "signalfd()");
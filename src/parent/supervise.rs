@@ -0,0 +1,431 @@
+//! Supervising several children at once, each on its own PTY, from a single poll loop.
+
+use std::fs::File;
+use std::io::{self, IsTerminal, Stdout, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd, RawFd};
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	miette::{Context as _, IntoDiagnostic},
+	tap::prelude::*,
+};
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+
+use crate::filter::LinePrefixer;
+use crate::poller::{InterestHandle, Poller, PollInterest, DEFAULT_BUFFER_SIZE};
+use crate::pty::{get_termios, sync_winsize, RawMode, TermiosError};
+use crate::writer::QueuedWriter;
+use crate::{Data, DataBuf, DataBufExt};
+use super::{
+	decode_siginfo,
+	handle_signals_as_file,
+	has_exited,
+	log_event,
+	loop_signals,
+	stdin_source,
+	terminate_child,
+	ChildOutcome,
+	CONTROL_D,
+	FORWARDED_SIGNALS,
+	KILL_GRACE_PERIOD,
+};
+
+/// One of the children for [`supervise()`], already started on its own PTY, like with
+/// [`Session::spawn()`](crate::session::Session::spawn).
+#[derive(Debug)]
+pub struct Supervised
+{
+	/// What each line of this child's output starts with, in brackets, so it can be told apart
+	/// from everyone else's.
+	pub label: Box<str>,
+	pub child: Pid,
+	/// The controller side of the child's PTY, which should be `O_NONBLOCK`.
+	pub pty: OwnedFd,
+}
+
+impl Supervised
+{
+	pub fn new(label: impl Into<Box<str>>, child: Pid, pty: impl Into<OwnedFd>) -> Self
+	{
+		Self {
+			label: label.into(),
+			child,
+			pty: pty.into(),
+		}
+	}
+}
+
+/// The sources in [`supervise()`]'s [Poller].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Source
+{
+	/// Our signalfd, for all of [`loop_signals()`].
+	Signals,
+	/// The PTY of the child at this index.
+	Pty(usize),
+	Stdin,
+}
+
+/// What we keep track of for each child we're supervising.
+#[derive(Debug)]
+struct ChildState
+{
+	label: Box<str>,
+	child: Pid,
+	/// A duplicate of the PTY the poller has, for draining and window size changes.
+	pty: File,
+	/// The PTY's file descriptor in the poller, until we stop polling it.
+	polled: Option<RawFd>,
+	/// Puts the label in front of each line of output.
+	prefixer: LinePrefixer,
+	exited: bool,
+}
+
+#[derive(Debug)]
+struct SupervisorState
+{
+	stdout: Stdout,
+	flush_stdout: bool,
+	children: Vec<ChildState>,
+	/// Our stdin only goes to the first child, since there's no telling who else it's meant for.
+	input: QueuedWriter,
+	stdin_interest: Option<InterestHandle>,
+	/// Whose output we wrote last, so we can tell when someone cuts in on a half-written line.
+	last_output: Option<usize>,
+}
+
+impl SupervisorState
+{
+	/// Pass output from the child at `index` along to stdout, with its label at the start of
+	/// each line.
+	fn pty_output(&mut self, index: usize, data: &Data) -> miette::Result<()>
+	{
+		if data.is_empty() {
+			return Ok(());
+		}
+
+		let mut out = DataBuf::with_capacity(data.len());
+
+		// Finish off whoever was mid-line first, so lines from different children never share
+		// a line of ours. They get their label again when they pick it back up.
+		if let Some(last) = self.last_output.filter(|&last| last != index) {
+			let last = &mut self.children[last];
+			if !last.prefixer.at_line_start() {
+				out.extend_from_slice(b"\r\n");
+				last.prefixer.restart_line();
+			}
+		}
+		self.last_output = Some(index);
+
+		let child = &mut self.children[index];
+		child.prefixer.prefix(data, &mut out);
+
+		self.stdout.write_all(&out)
+			.into_diagnostic()
+			.with_context(|| format!("writing output of {} to stdout", child.label))?;
+		if self.flush_stdout {
+			self.stdout.flush()
+				.into_diagnostic()
+				.with_context(|| format!("flushing output of {} to stdout", child.label))?;
+		}
+
+		Ok(())
+	}
+
+	/// Forward whatever output the child at `index` left in its PTY before exiting.
+	fn drain_pty(&mut self, index: usize) -> miette::Result<()>
+	{
+		let mut data = DataBuf::new();
+		let mut pty: &File = &self.children[index].pty;
+		match data.fill_until_block(&mut pty, DEFAULT_BUFFER_SIZE) {
+			Ok(_count) => (),
+			// The usual end, once the child side is closed and everything's been read.
+			Err(e) if e.raw_os_error() == Some(libc::EIO) => (),
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => {
+				return Err(e)
+					.into_diagnostic()
+					.with_context(|| format!("draining PTY of {}", self.children[index].label));
+			},
+		}
+
+		self.pty_output(index, &data)
+	}
+
+	/// Write whatever input we couldn't write to the first child before.
+	fn flush_input(&mut self) -> miette::Result<()>
+	{
+		self.input.flush_pending()
+			.into_diagnostic()
+			.context("writing queued input to first child's PTY")
+	}
+
+	fn stdin_input(&mut self, data: &Data, eof: bool) -> miette::Result<()>
+	{
+		if !data.is_empty() {
+			self.input.write(data)
+				.into_diagnostic()
+				.context("forwarding stdin to first child's PTY")?;
+		}
+		if !eof {
+			return Ok(());
+		}
+
+		debug!("stdin reached end-of-file; no longer forwarding it");
+		if let Some(interest) = &self.stdin_interest {
+			interest.set_read(false);
+		}
+
+		let eof_char: u8 = match get_termios(self.input.file().as_fd()) {
+			Ok(termios) => termios.c_cc[libc::VEOF],
+			Err(e) => {
+				warn!("couldn't get EOF character of first child's PTY, assuming ^D: {e}");
+				CONTROL_D
+			},
+		};
+
+		self.input.write(&[eof_char])
+			.into_diagnostic()
+			.context("forwarding end-of-file to first child's PTY")
+	}
+
+	/// Send `signal` to every child that's still around.
+	fn signal_children(&self, signal: Signal)
+	{
+		for child in self.children.iter().filter(|child| !child.exited) {
+			// Each child is a session leader, so its process group ID is its PID.
+			if let Err(e) = killpg(child.child, signal) {
+				let (label, pgid) = (&child.label, child.child);
+				warn!("couldn't send {signal} to {label} (process group {pgid}): {e}");
+			}
+		}
+	}
+
+	/// Handle signals read from our signalfd, and return the indices of any children that have
+	/// exited since.
+	fn signals(&mut self, data: &Data) -> miette::Result<Vec<usize>>
+	{
+		let infos = decode_siginfo(data)?;
+
+		let mut child_changed = false;
+		let mut resized = false;
+		for info in infos {
+			match info.signal() {
+				Some(Signal::SIGCHLD) => {
+					debug!("SIGCHLD from {} with code {}", info.pid(), info.code());
+					child_changed = true;
+				},
+				Some(Signal::SIGWINCH) => {
+					resized = true;
+				},
+				Some(signal) if FORWARDED_SIGNALS.contains(&signal) => {
+					debug!("forwarding {signal} from {} to every child", info.pid());
+					self.signal_children(signal);
+				},
+				Some(Signal::SIGCONT) => {
+					debug!("continued by {}; continuing every child", info.pid());
+					self.signal_children(Signal::SIGCONT);
+				},
+				_ => {
+					warn!("got unexpected signal {} from signalfd", info.signo());
+				},
+			}
+		}
+
+		if resized {
+			for child in self.children.iter().filter(|child| !child.exited) {
+				debug!("resizing PTY of {} to match stdin", child.label);
+				if let Err(e) = sync_winsize(io::stdin().as_fd(), child.pty.as_fd()) {
+					warn!("couldn't pass our new window size along to {}: {e}", child.label);
+				}
+			}
+		}
+
+		let mut exited = Vec::new();
+		if child_changed {
+			// `SIGCHLD`s coalesce, so any number of them could have exited.
+			for index in 0..self.children.len() {
+				let child = &self.children[index];
+				if child.exited || !has_exited(child.child) {
+					continue;
+				}
+				info!("{} (process {}) has exited", child.label, child.child);
+				self.drain_pty(index)?;
+				self.children[index].exited = true;
+				exited.push(index);
+			}
+		}
+
+		Ok(exited)
+	}
+
+	/// Stop polling the PTY of the child at `index`, if we still are.
+	fn stop_polling(&mut self, poller: &mut Poller<Source>, index: usize) -> miette::Result<()>
+	{
+		match self.children[index].polled.take() {
+			Some(raw_fd) => poller.remove_source(raw_fd),
+			None => Ok(()),
+		}
+	}
+}
+
+fn supervise_loop(children: &[Supervised], follow_resize: bool, flush: bool) -> miette::Result<()>
+{
+	let signals = loop_signals(follow_resize);
+	// Declared before the poller, so the poller closes the signalfd before the mask is restored.
+	let (signal_file, _mask_guard): (File, _) = handle_signals_as_file(&signals)
+		.with_context(|| format!("turning {signals:?} into a file descriptor"))?;
+
+	let mut sources = vec![PollInterest::read(signal_file).with_key(Source::Signals)];
+	let mut states = Vec::with_capacity(children.len());
+	for (index, supervised) in children.iter().enumerate() {
+		let pty = supervised.pty.try_clone()
+			.into_diagnostic()
+			.with_context(|| format!("duplicating PTY file descriptor of {}", supervised.label))?;
+		let dup = pty.try_clone()
+			.into_diagnostic()
+			.with_context(|| format!("duplicating PTY file descriptor of {}", supervised.label))?;
+		states.push(ChildState {
+			label: supervised.label.clone(),
+			child: supervised.child,
+			pty: File::from(dup),
+			polled: Some(pty.as_raw_fd()),
+			prefixer: LinePrefixer::new(format!("[{}] ", supervised.label)),
+			exited: false,
+		});
+		sources.push(PollInterest::read_pty(File::from(pty)).with_key(Source::Pty(index)));
+	}
+
+	// We leave stdin blocking, for the same reasons as the single child loop.
+	let stdin = stdin_source();
+	let stdin_raw = stdin.as_ref().map(|stdin| stdin.as_raw_fd());
+	if let Some(stdin) = stdin {
+		sources.push(PollInterest::read_blocking(stdin).with_key(Source::Stdin));
+	}
+
+	let mut poller = Poller::with_sources(sources)
+		.context("initializing pollers for signals, child PTYs, and stdin")?;
+
+	let first = &states[0];
+	let input_interest = first.polled
+		.and_then(|raw_fd| poller.interest(raw_fd))
+		.unwrap_or_else(|| unreachable!());
+	let input = first.pty.try_clone()
+		.into_diagnostic()
+		.with_context(|| format!("duplicating PTY file descriptor of {} for input", first.label))?;
+
+	let stdout = io::stdout();
+	let mut state = SupervisorState {
+		flush_stdout: flush || !stdout.is_terminal(),
+		stdout,
+		children: states,
+		input: QueuedWriter::with_interest(input, input_interest),
+		stdin_interest: stdin_raw.and_then(|raw_fd| poller.interest(raw_fd)),
+		last_output: None,
+	};
+
+	let result: miette::Result<()> = try {
+		while state.children.iter().any(|child| !child.exited) {
+			for (event, data) in poller.wait_once(None)? {
+				log_event(&event, &data);
+
+				match event.key {
+					Source::Signals => {
+						for index in state.signals(&data)? {
+							state.stop_polling(&mut poller, index)?;
+						}
+					},
+					Source::Pty(index) => {
+						if event.writable && index == 0 {
+							state.flush_input()?;
+						}
+						state.pty_output(index, &data)?;
+						if event.eof {
+							// Nothing more to read until the child exits, and we'd just keep
+							// waking up for it.
+							debug!("PTY of {} closed", state.children[index].label);
+							state.stop_polling(&mut poller, index)?;
+						}
+					},
+					Source::Stdin => state.stdin_input(&data, event.eof)?,
+				}
+			}
+		}
+	};
+
+	info!("exited poll loop");
+
+	if let Err(e) = state.stdout.flush() {
+		warn!("couldn't flush stdout: {e}");
+	}
+
+	result
+}
+
+/// Forward stdio between us and each of `children`, like
+/// [`parent_process()`](super::parent_process) does for one child, until all of them have exited,
+/// and reap them.
+///
+/// Each line of output is prefixed with the label of the child it came from, and our stdin only
+/// goes to the first child. Signals we forward go to all of them, and so do window size changes
+/// if `follow_resize` is set. A child stopping doesn't stop us, since the others may still be
+/// running.
+///
+/// Returns how each child terminated, in the same order as `children`.
+pub fn supervise(
+	children: Vec<Supervised>,
+	follow_resize: bool,
+	flush: bool,
+) -> miette::Result<Vec<ChildOutcome>>
+{
+	if children.is_empty() {
+		return Ok(Vec::new());
+	}
+	for supervised in &children {
+		info!("supervising {} (process {})", supervised.label, supervised.child);
+	}
+
+	let stdin = io::stdin();
+	let raw_mode = match RawMode::enable(stdin.as_fd()) {
+		Ok(guard) => Some(guard),
+		Err(TermiosError::NotATty) => {
+			debug!("stdin is not a terminal; not enabling raw mode");
+			None
+		},
+		Err(e) => {
+			warn!("couldn't put stdin into raw mode: {e}");
+			None
+		},
+	};
+
+	let result = supervise_loop(&children, follow_resize, flush);
+
+	drop(raw_mode);
+
+	// Reap everyone, even if the loop failed, before we close their PTYs. If it did fail,
+	// nothing's forwarding their I/O anymore, so we don't wait for them to exit on their own.
+	let mut outcomes = Vec::with_capacity(children.len());
+	for supervised in &children {
+		let child = supervised.child;
+		let status = if result.is_err() && !has_exited(child) {
+			terminate_child(child, KILL_GRACE_PERIOD)
+		} else {
+			nix::sys::wait::waitpid(child, None)
+		};
+		let status = status
+			.into_diagnostic()
+			.with_context(|| format!("waitpid() on {} (process {child})", supervised.label))?;
+		debug!("waitpid() on {} returned {status:?}", supervised.label);
+
+		let Some(outcome) = ChildOutcome::from_wait_status(status) else {
+			miette::bail!("waitpid() status {status:?} for child {child} isn't a termination (floatty bug)");
+		};
+		outcomes.push(outcome);
+	}
+
+	result?;
+
+	Ok(outcomes)
+}
@@ -1,8 +1,13 @@
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::io::{ErrorKind as IoErrorKind, Read, Result as IoResult};
 use std::fs::File;
 use std::mem;
+use std::fmt::Debug;
 use std::ops::ControlFlow;
-use std::os::fd::{AsRawFd, RawFd};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 #[allow(unused_imports)]
 use {
@@ -13,120 +18,510 @@ use {
 	tap::prelude::*,
 };
 
-use crate::{DataBuf, DataBufExt};
+use crate::{Data, DataBuf, DataBufExt};
 
-// FIXME: what buffer size?
-const BUFFER_SIZE: usize = 4096;
+/// Default size of the scratch buffer for each `read()` in [`NonblockingRead::read_until_block()`].
+///
+/// High-throughput children may want something bigger, like 64 KiB, to cut down on syscalls.
+pub const DEFAULT_BUFFER_SIZE: usize = 4096;
 
+/// The log target for a line about every poll event, so it can be turned on by itself, like with
+/// `floatty --debug-events`.
+pub const EVENTS_LOG_TARGET: &str = "floatty::events";
+
+/// Anything a [Poller] can poll and read from, like a [`File`], a pipe, a socket, or a
+/// [`PtyMaster`](crate::PtyMaster).
+///
+/// This is implemented for everything that's [Read] and owns a file descriptor, so there's no
+/// need to implement it yourself.
+pub trait PollSource: Read + AsFd + AsRawFd + Debug
+{
+	/// Give up the source as just its file descriptor, which is left open.
+	fn into_fd(self: Box<Self>) -> OwnedFd;
+}
+
+impl<T> PollSource for T
+where
+	T: Read + AsFd + AsRawFd + Into<OwnedFd> + Debug,
+{
+	fn into_fd(self: Box<Self>) -> OwnedFd
+	{
+		(*self).into()
+	}
+}
+
+/// A source to register with a [Poller], and what to poll it for.
+///
+/// `K` is what the [Poller] hands back in each [`PollEvent`] for this source, so callers can tell
+/// sources apart by something meaningful. The constructors use the file descriptor; use
+/// [`PollInterest::with_key()`] for anything else.
+///
+/// Most of the constructors take anything that owns a file descriptor, like a [`File`], a pipe,
+/// or a [`PtyMaster`](crate::PtyMaster), and read from it as a [`File`].
+/// [`PollInterest::new()`] keeps its [`PollSource`] as it is instead.
 #[derive(Debug)]
-pub struct PollInterest
+pub struct PollInterest<K = RawFd>
 {
-	pub file: File,
+	pub source: Box<dyn PollSource>,
+	pub key: K,
 	pub read: bool,
 	pub write: bool,
+	/// Treat `EIO` from reads as end-of-stream instead of an error, which is how Linux reports
+	/// that the other side of a PTY has been closed. That end-of-stream also ends
+	/// [`Poller::each_with_idle()`].
+	pub eof_on_eio: bool,
+	/// This file isn't `O_NONBLOCK`, so only do one `read()` per event, since a second one
+	/// might block.
+	pub blocking: bool,
 }
 
 impl PollInterest
 {
-	pub fn read(file: File) -> Self
+	/// Poll `source` for reads, writes, or both, reading from it with its own [Read] impl.
+	pub fn new(source: impl PollSource + 'static, read: bool, write: bool) -> Self
 	{
 		Self {
-			file,
+			key: source.as_raw_fd(),
+			source: Box::new(source),
+			read,
+			write,
+			eof_on_eio: false,
+			blocking: false,
+		}
+	}
+
+	pub fn read(fd: impl Into<OwnedFd>) -> Self
+	{
+		let file = File::from(fd.into());
+		Self {
+			key: file.as_raw_fd(),
+			source: Box::new(file),
 			read: true,
 			write: false,
+			eof_on_eio: false,
+			blocking: false,
+		}
+	}
+
+	/// Write interest only. Note that most files are writable most of the time, so the poll loop
+	/// will spin for as long as this source is registered.
+	pub fn write(fd: impl Into<OwnedFd>) -> Self
+	{
+		let file = File::from(fd.into());
+		Self {
+			key: file.as_raw_fd(),
+			source: Box::new(file),
+			read: false,
+			write: true,
+			eof_on_eio: false,
+			blocking: false,
+		}
+	}
+
+	pub fn read_write(fd: impl Into<OwnedFd>) -> Self
+	{
+		let file = File::from(fd.into());
+		Self {
+			key: file.as_raw_fd(),
+			source: Box::new(file),
+			read: true,
+			write: true,
+			eof_on_eio: false,
+			blocking: false,
+		}
+	}
+
+	/// Read interest in a file that isn't `O_NONBLOCK`, like a stdin whose file status flags
+	/// we don't want to change out from under whoever else shares it.
+	pub fn read_blocking(fd: impl Into<OwnedFd>) -> Self
+	{
+		Self {
+			blocking: true,
+			..Self::read(fd)
+		}
+	}
+
+	/// Read interest in a PTY controller, which stops the poll loop once the PTY child
+	/// has been closed.
+	pub fn read_pty(fd: impl Into<OwnedFd>) -> Self
+	{
+		Self {
+			eof_on_eio: true,
+			..Self::read(fd)
 		}
 	}
 }
 
-/// Extension trait for [Read] which allows continually reading until a read would block.
-/// Meant to be used with `O_NONBLOCK`.
-pub trait NonblockingRead: Read
+impl<K> PollInterest<K>
 {
-	fn read_until_block(&mut self) -> IoResult<DataBuf>;
+	/// Identify this source by `key` instead.
+	pub fn with_key<NewK>(self, key: NewK) -> PollInterest<NewK>
+	{
+		PollInterest { key, ..self }
+	}
+}
+
+impl<K> AsFd for PollInterest<K>
+{
+	fn as_fd(&self) -> BorrowedFd<'_>
+	{
+		self.source.as_fd()
+	}
 }
 
-impl NonblockingRead for File
+impl<K> AsRawFd for PollInterest<K>
+{
+	fn as_raw_fd(&self) -> RawFd
+	{
+		self.source.as_raw_fd()
+	}
+}
+
+impl<K> IntoRawFd for PollInterest<K>
+{
+	fn into_raw_fd(self) -> RawFd
+	{
+		self.source.into_fd().into_raw_fd()
+	}
+}
+
+/// An event from a [Poller], for the source registered with `key`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PollEvent<K = RawFd>
+{
+	pub key: K,
+	pub readable: bool,
+	pub writable: bool,
+	/// The source reached end-of-stream: a [blocking](PollInterest::read_blocking) one read
+	/// nothing, or one with [`PollInterest::eof_on_eio`] got `EIO`.
+	pub eof: bool,
+	/// This is the last event from this wait, so everything else that was ready has already been
+	/// handed out, and the poller waits for more next.
+	pub last: bool,
+}
+
+/// Whether `file` can be added to a [Poller] at all.
+///
+/// Regular files and some character devices like `/dev/null` are always "ready", so `epoll()`
+/// refuses them.
+pub fn is_pollable(file: &File) -> bool
+{
+	let Ok(probe) = polling::Poller::new() else {
+		return false;
+	};
+
+	let raw_fd: RawFd = file.as_raw_fd();
+	// SAFETY: `raw_fd` comes from an `std::io::File`, and is deleted again before we return.
+	match unsafe { probe.add(raw_fd, polling::Event::none(0)) } {
+		Ok(()) => {
+			let _ = probe.delete(file);
+			true
+		},
+		Err(e) => {
+			trace!("fd {raw_fd} is not pollable: {e}");
+			false
+		},
+	}
+}
+
+/// Shared handle to which events a source in a [Poller] is interested in, so that interest can be
+/// changed from inside the poll loop, e.g. to only poll for writability when there's something
+/// waiting to be written.
+///
+/// Changes take effect once the current poll callback returns.
+#[derive(Debug, Clone)]
+pub struct InterestHandle
+{
+	/// Read and write interest, respectively.
+	interest: Rc<Cell<(bool, bool)>>,
+}
+
+impl InterestHandle
+{
+	fn new(read: bool, write: bool) -> Self
+	{
+		Self {
+			interest: Rc::new(Cell::new((read, write))),
+		}
+	}
+
+	pub fn read(&self) -> bool
+	{
+		self.interest.get().0
+	}
+
+	pub fn write(&self) -> bool
+	{
+		self.interest.get().1
+	}
+
+	pub fn set_read(&self, read: bool)
+	{
+		let (_read, write) = self.interest.get();
+		self.interest.set((read, write));
+	}
+
+	pub fn set_write(&self, write: bool)
+	{
+		let (read, _write) = self.interest.get();
+		self.interest.set((read, write));
+	}
+}
+
+/// Extension trait for [Read] which allows continually reading until a read would block.
+/// Meant to be used with `O_NONBLOCK`.
+pub trait NonblockingRead: Read
 {
 	/// Read until `std::io::ErrorKind::WouldBlock` is returned, and return all data read,
 	/// unless some other error occured.
+	///
+	/// If some other error occurs after some data has already been read, then that data is
+	/// returned instead, and the error is left for the next read to find.
 	fn read_until_block(&mut self) -> IoResult<DataBuf>
+	{
+		self.read_until_block_with_capacity(DEFAULT_BUFFER_SIZE)
+	}
+
+	/// Same as [`NonblockingRead::read_until_block()`], but reads up to `buf_size` bytes
+	/// at a time.
+	fn read_until_block_with_capacity(&mut self, buf_size: usize) -> IoResult<DataBuf>
 	{
 		let mut data = DataBuf::new();
+		data.fill_until_block(self, buf_size)?;
 
-		let mut buffer = DataBuf::zeroed(BUFFER_SIZE);
-		loop {
-			match self.read(&mut buffer) {
-				Ok(0) => {
-					// No more data at all I guess? Is this necessary?
-					warn!("Nonblocking reader returned 0 bytes; I guess this is possible after all!");
-					break;
-				},
-				Ok(count) => {
-					let read_data = &buffer[0..count];
-					data.extend_from_slice(read_data);
-				},
-				Err(e) => {
-					if e.kind() == IoErrorKind::WouldBlock {
-						// No more data ready right now. We're done here.
-						break;
-					}
-					error!("error while doing non-blocking read: {e:?}");
-					return Err(e);
-				}
-			}
+		Ok(data)
+	}
+
+	/// Same as [`NonblockingRead::read_until_block()`], but allocation-free: `buffer` is used as
+	/// scratch space for each `read()`, and all data read is appended to `data`.
+	///
+	/// Returns how many bytes were appended.
+	fn read_until_block_into(&mut self, buffer: &mut Data, data: &mut DataBuf) -> IoResult<usize>
+	{
+		data.fill_until_block_with(self, buffer)
+	}
+}
+
+impl<R: Read + ?Sized> NonblockingRead for R {}
+
+/// A [PollInterest] after it's been registered with a [Poller].
+#[derive(Debug)]
+struct Registered<K>
+{
+	source: Box<dyn PollSource>,
+	key: K,
+	eof_on_eio: bool,
+	blocking: bool,
+	/// What we want to be interested in.
+	interest: InterestHandle,
+	/// What we last told the OS we're interested in, as read and write, respectively.
+	armed: (bool, bool),
+}
+
+impl<K> Registered<K>
+{
+	/// What we give the OS poller, which is always the file descriptor.
+	fn poll_key(&self) -> usize
+	{
+		let raw_fd: RawFd = self.source.as_raw_fd();
+		raw_fd.try_into().unwrap_or_else(|e| {
+			panic!("file descriptor {raw_fd} does not fit in a usize? {e}");
+		})
+	}
+
+	/// Tell `poller` about any change in what we want, or re-establish it regardless if this
+	/// source just `fired`, since oneshot mode disarms sources after each event.
+	fn rearm(&mut self, poller: &polling::Poller, fired: bool) -> miette::Result<()>
+	{
+		let wanted = self.interest.interest.get();
+		if !fired && wanted == self.armed {
+			return Ok(());
 		}
 
-		Ok(data)
+		let (read, write) = wanted;
+		let interest = polling::Event::new(self.poll_key(), read, write);
+		poller.modify(self.source.as_fd(), interest)
+			.into_diagnostic()
+			.with_context(|| format!("re-adding poller for fd {}", self.source.as_raw_fd()))?;
+		self.armed = wanted;
+
+		Ok(())
+	}
+}
+
+/// Shared handle to how long a [Poller] waits without any events before calling its idle
+/// callback, so that it can be changed from inside the poll loop, e.g. to count down
+/// to a deadline.
+///
+/// Changes take effect once the current callback returns.
+#[derive(Debug, Clone, Default)]
+pub struct TimeoutHandle
+{
+	timeout: Rc<Cell<Option<Duration>>>,
+}
+
+impl TimeoutHandle
+{
+	pub fn get(&self) -> Option<Duration>
+	{
+		self.timeout.get()
+	}
+
+	/// `None` means wait forever.
+	pub fn set(&self, timeout: Option<Duration>)
+	{
+		self.timeout.set(timeout);
 	}
 }
 
 #[derive(Debug)]
-pub struct Poller
+pub struct Poller<K = RawFd>
 {
 	inner: polling::Poller,
-	sources: Vec<File>,
+	/// How long to go without events before calling the idle callback, if ever.
+	timeout: TimeoutHandle,
+	/// Keyed by file descriptor, which is also the key in each [`polling::Event`].
+	sources: HashMap<RawFd, Registered<K>>,
+	/// Scratch space for each `read()`, reused across events.
+	buffer: DataBuf,
+	/// What each wait fills in, reused across waits so we only allocate it once.
+	events: polling::Events,
 }
 
 /// API
-impl Poller
+impl<K: Copy> Poller<K>
 {
 	pub fn with_sources<I>(sources: I) -> miette::Result<Self>
 	where
-		I: IntoIterator<Item = PollInterest, IntoIter: ExactSizeIterator>,
+		I: IntoIterator<Item = PollInterest<K>, IntoIter: ExactSizeIterator>,
 	{
 		let mut poller = polling::Poller::new()
 			.into_diagnostic()
 			.context("registering base file poller with operating system")?;
 		let sources = sources.into_iter();
-		let mut fds: Vec<File> = Vec::with_capacity(sources.len());
-
-		for PollInterest { file, read, write } in sources {
-			let raw_fd: RawFd = file.as_raw_fd();
-			fds.push(file);
+		let mut fds: HashMap<RawFd, Registered<K>> = HashMap::with_capacity(sources.len());
 
-			let key: usize = raw_fd.try_into().unwrap_or_else(|e| {
-				panic!("file descriptor {raw_fd} does not fit in a usize? {e}");
-			});
+		for PollInterest { source, key, read, write, eof_on_eio, blocking } in sources {
+			let raw_fd: RawFd = source.as_raw_fd();
+			let source = Registered {
+				source,
+				key,
+				eof_on_eio,
+				blocking,
+				interest: InterestHandle::new(read, write),
+				armed: (read, write),
+			};
+			let poll_key: usize = source.poll_key();
 
-			let interest = polling::Event::new(key, read, write);
-			// SAFETY: `raw_fd` comes from an `std::io::File`. It can only be invalid if some other
-			// unsafe code has made it so.
+			let interest = polling::Event::new(poll_key, read, write);
+			// SAFETY: `raw_fd` comes from a `PollSource`, which owns it. It can only be invalid if
+			// some other unsafe code has made it so.
 			unsafe { poller.add(raw_fd, interest) }.unwrap_or_else(|e| {
 				// Deleting already added sources is apparently a safety issue? Weird.
 				Self::cleanup(&mut poller, mem::take(&mut fds));
 				panic!("error adding file descriptor {raw_fd} to poller: {e}");
 			});
+			fds.insert(raw_fd, source);
 		}
 
 		Ok(Self {
 			inner: poller,
+			timeout: TimeoutHandle::default(),
 			sources: fds,
+			buffer: DataBuf::zeroed(DEFAULT_BUFFER_SIZE),
+			events: polling::Events::new(),
 		})
 	}
 
-	pub fn each<F>(&mut self, f: F) -> miette::Result<()>
+	/// Register another source after construction.
+	///
+	/// This can't be done from inside [`Poller::each_with()`], but interest in existing sources
+	/// can be turned off with an [`InterestHandle`] instead.
+	pub fn add_source(&mut self, interest: PollInterest<K>) -> miette::Result<()>
+	{
+		let PollInterest { source, key, read, write, eof_on_eio, blocking } = interest;
+		let raw_fd: RawFd = source.as_raw_fd();
+		if self.sources.contains_key(&raw_fd) {
+			miette::bail!("file descriptor {raw_fd} is already registered with this poller");
+		}
+
+		let source = Registered {
+			source,
+			key,
+			eof_on_eio,
+			blocking,
+			interest: InterestHandle::new(read, write),
+			armed: (read, write),
+		};
+
+		let event = polling::Event::new(source.poll_key(), read, write);
+		// SAFETY: `raw_fd` comes from a `PollSource`, which we keep (and delete from the
+		// poller) until it's closed.
+		unsafe { self.inner.add(raw_fd, event) }
+			.into_diagnostic()
+			.with_context(|| format!("adding file descriptor {raw_fd} to poller"))?;
+
+		self.sources.insert(raw_fd, source);
+
+		Ok(())
+	}
+
+	/// Unregister the source with file descriptor `raw_fd`, and close its file.
+	pub fn remove_source(&mut self, raw_fd: RawFd) -> miette::Result<()>
+	{
+		let Some(source) = self.sources.remove(&raw_fd) else {
+			miette::bail!("file descriptor {raw_fd} is not registered with this poller");
+		};
+
+		// The file must stay open until it's deleted from the poller.
+		self.inner.delete(source.source.as_fd())
+			.into_diagnostic()
+			.with_context(|| format!("deleting file descriptor {raw_fd} from poller"))?;
+
+		trace!("removed file descriptor {raw_fd} from poller");
+		drop(source);
+
+		Ok(())
+	}
+
+	/// The file descriptors of every registered source, in no particular order.
+	pub fn source_fds(&self) -> impl Iterator<Item = RawFd> + '_
+	{
+		self.sources.keys().copied()
+	}
+
+	/// Get a handle for changing which events the source with file descriptor `raw_fd` is
+	/// interested in, if there is such a source.
+	pub fn interest(&self, raw_fd: RawFd) -> Option<InterestHandle>
+	{
+		self.sources
+			.get(&raw_fd)
+			.map(|source| source.interest.clone())
+	}
+
+	/// Set how many bytes to read at a time from sources. Defaults to [`DEFAULT_BUFFER_SIZE`].
+	pub fn set_buffer_size(&mut self, buffer_size: usize)
+	{
+		assert!(buffer_size > 0, "poller buffer size must be non-zero");
+		self.buffer = DataBuf::zeroed(buffer_size);
+	}
+
+	/// Set how long to go without any events before calling the idle callback passed to
+	/// [`Poller::each_with_idle()`]. `None`, the default, means wait forever.
+	pub fn set_timeout(&self, timeout: Option<Duration>)
+	{
+		self.timeout.set(timeout);
+	}
+
+	/// Get a handle for changing the timeout from inside the poll loop.
+	pub fn timeout_handle(&self) -> TimeoutHandle
+	{
+		self.timeout.clone()
+	}
+
+	pub fn each<F>(&mut self, mut f: F) -> miette::Result<()>
 	where
-		F: Fn(polling::Event, DataBuf) -> ControlFlow<()>
+		F: FnMut(PollEvent<K>, &Data) -> ControlFlow<()>
 	{
 		let mut unit = ();
 		self.each_with(&mut unit, |_, event, data| f(event, data))
@@ -135,51 +530,268 @@ impl Poller
 	pub fn each_with<T, F>(&mut self, user_data: &mut T, f: F) -> miette::Result<()>
 	where
 		T: ?Sized,
-		F: Fn(&mut T, polling::Event, DataBuf) -> ControlFlow<()>,
+		F: FnMut(&mut T, PollEvent<K>, &Data) -> ControlFlow<()>,
 	{
-		let mut events = polling::Events::new();
-		'outer: loop {
-			events.clear();
-			self.inner.wait(&mut events, None).expect("todo");
+		self.each_with_idle(user_data, f, |_| ControlFlow::Continue(()))
+	}
 
-			for event in events.iter() {
+	/// Same as [`Poller::each_with()`], but also calls `idle` whenever the timeout from
+	/// [`Poller::set_timeout()`] passes without any events.
+	///
+	/// Each source is only read once its event is about to be handed to `f`, so when `f` breaks,
+	/// or a source with [`PollInterest::eof_on_eio`] reaches end-of-stream, whatever else was
+	/// ready is left unread, and the next call gets it instead. `f` still gets that end-of-stream
+	/// event, along with anything read before it.
+	pub fn each_with_idle<T, F, I>(
+		&mut self,
+		user_data: &mut T,
+		mut f: F,
+		mut idle: I,
+	) -> miette::Result<()>
+	where
+		T: ?Sized,
+		F: FnMut(&mut T, PollEvent<K>, &Data) -> ControlFlow<()>,
+		I: FnMut(&mut T) -> ControlFlow<()>,
+	{
+		let mut last_activity = Instant::now();
+		loop {
+			let timeout = self.timeout.get();
+			let remaining = timeout.map(|timeout| timeout.saturating_sub(last_activity.elapsed()));
+			let ready = self.wait_ready(remaining)?;
 
-				let raw_fd = event.key as RawFd;
-				let matching_file = self.sources
-					.iter_mut()
-					.find(|source| source.as_raw_fd() == raw_fd)
-					.unwrap_or_else(|| unreachable!());
+			if ready.is_empty() {
+				// We can wake up without any events before the timeout, too, so check.
+				let timed_out = timeout.is_some_and(|timeout| last_activity.elapsed() >= timeout);
+				if !timed_out {
+					trace!("poll woke up with no events; waiting again");
+					continue;
+				}
 
-				let data = matching_file.read_until_block()
-					.into_diagnostic()
-					.with_context(|| format!("attempting non-blocking reads from fd {raw_fd}"))?;
-				let flow = f(user_data, event, data);
-				if flow.is_break() {
-					break 'outer;
+				last_activity = Instant::now();
+				if idle(user_data).is_break() {
+					return Ok(());
+				}
+
+				continue;
+			}
+
+			last_activity = Instant::now();
+			let count = ready.len();
+			let mut ready = ready.into_iter().enumerate();
+			while let Some((index, source)) = ready.next() {
+				let ends_loop = self.sources[&source.raw_fd].eof_on_eio;
+				let Some((event, data)) = self.read_ready(source, index + 1 == count)? else {
+					continue;
+				};
+				if f(user_data, event, &data).is_break() || (event.eof && ends_loop) {
+					self.skip_ready(ready.map(|(_index, source)| source))?;
+					return Ok(());
 				}
+			}
+		}
+	}
+
+	/// Wait for events once, for at most `timeout` if it's not `None`, and return each of them
+	/// with everything that could be read from its source without blocking.
+	///
+	/// This is the single step that [`Poller::each_with()`] loops over, for callers who want to
+	/// run their own loop. Changes to interest made through an [`InterestHandle`] take effect on
+	/// the next call. An empty result means the timeout passed, or we woke up for nothing.
+	pub fn wait_once(
+		&mut self,
+		timeout: Option<Duration>,
+	) -> miette::Result<Vec<(PollEvent<K>, DataBuf)>>
+	{
+		let ready = self.wait_ready(timeout)?;
+		let count = ready.len();
+
+		ready.into_iter()
+			.enumerate()
+			.filter_map(|(index, source)| self.read_ready(source, index + 1 == count).transpose())
+			.collect()
+	}
+}
+
+/// Internals
+impl<K: Copy> Poller<K>
+{
+	/// Wait for events once, like [`Poller::wait_once()`], but only say which sources are ready,
+	/// without reading anything from them yet.
+	fn wait_ready(&mut self, timeout: Option<Duration>) -> miette::Result<Vec<Ready>>
+	{
+		// Pick up any interest changes since last time.
+		for source in self.sources.values_mut() {
+			source.rearm(&self.inner, false)?;
+		}
 
-				// Re-establish interest in this file.
-				self.inner.modify(matching_file, event)
+		// Whatever's left from last time was already handed out.
+		self.events.clear();
+		match self.inner.wait(&mut self.events, timeout) {
+			Ok(_count) => (),
+			Err(e) if e.kind() == IoErrorKind::Interrupted => {
+				// Nothing wrong here, the caller can just try again.
+				trace!("poll wait interrupted");
+				return Ok(Vec::new());
+			},
+			Err(e) => {
+				return Err(e)
 					.into_diagnostic()
-					.with_context(|| format!("re-adding poller for fd {}", raw_fd))?;
+					.context("waiting on poll events");
+			},
+		}
+
+		let ready = self.events
+			.iter()
+			.map(|event| Ready {
+				raw_fd: event.key as RawFd,
+				readable: event.readable,
+				writable: event.writable,
+			})
+			.collect();
+
+		Ok(ready)
+	}
+
+	/// Read everything that can be read without blocking from the `ready` source, and make it
+	/// the event to hand out. `last` is whether it's the last one from its wait.
+	///
+	/// `None` means a signal interrupted the read before there was anything to hand out, so the
+	/// source is just re-armed for the next wait to report again.
+	fn read_ready(
+		&mut self,
+		ready: Ready,
+		last: bool,
+	) -> miette::Result<Option<(PollEvent<K>, DataBuf)>>
+	{
+		let Ready { raw_fd, readable, writable } = ready;
+		let matching = self.sources
+			.get_mut(&raw_fd)
+			.unwrap_or_else(|| unreachable!());
+
+		let mut data = DataBuf::new();
+		let mut eof = false;
+		let mut interrupted = false;
+		// Don't try to read from something that's only writable.
+		if readable {
+			let result = if matching.blocking {
+				// Just the one read, which poll() has promised us won't block, so if it reads
+				// nothing, there's nothing left.
+				matching.source.read(&mut self.buffer).map(|count| {
+					data.extend_from_slice(&self.buffer[..count]);
+					if count == 0 {
+						debug!("fd {raw_fd} reached end-of-file");
+						eof = true;
+					}
+				})
+			} else {
+				matching.source.read_until_block_into(&mut self.buffer, &mut data)
+					.map(drop)
+			};
+			match result {
+				Ok(()) => (),
+				Err(e) if matching.eof_on_eio && e.raw_os_error() == Some(libc::EIO) => {
+					debug!("fd {raw_fd} reached end-of-stream");
+					eof = true;
+				},
+				Err(e) if e.kind() == IoErrorKind::Interrupted => {
+					// We'll get another event for this.
+					trace!("read from fd {raw_fd} interrupted");
+					interrupted = true;
+				},
+				Err(e) => {
+					return Err(e)
+						.into_diagnostic()
+						.with_context(|| format!("attempting non-blocking reads from fd {raw_fd}"));
+				},
 			}
 		}
 
-		Self::cleanup(&mut self.inner, mem::take(&mut self.sources));
+		debug!(
+			target: EVENTS_LOG_TARGET,
+			"fd {raw_fd}: readable: {readable}, writable: {writable}, read {} bytes",
+			data.len(),
+		);
+
+		// Oneshot mode disarmed it, so re-establish interest in it.
+		matching.rearm(&self.inner, true)?;
+
+		if interrupted && data.is_empty() && !writable {
+			return Ok(None);
+		}
+
+		let event = PollEvent {
+			key: matching.key,
+			readable,
+			writable,
+			eof,
+			last,
+		};
+
+		Ok(Some((event, data)))
+	}
+
+	/// Re-arm each of the `ready` sources without reading them, so that whatever they have
+	/// waiting gets reported again by the next wait.
+	fn skip_ready(&mut self, ready: impl IntoIterator<Item = Ready>) -> miette::Result<()>
+	{
+		for Ready { raw_fd, .. } in ready {
+			trace!("leaving fd {raw_fd} unread until the next wait");
+			let matching = self.sources
+				.get_mut(&raw_fd)
+				.unwrap_or_else(|| unreachable!());
+			matching.rearm(&self.inner, true)?;
+		}
 
 		Ok(())
 	}
 }
 
+/// A source that a wait said is ready, before it's been read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct Ready
+{
+	raw_fd: RawFd,
+	readable: bool,
+	writable: bool,
+}
+
+/// The OS poller itself, like an epoll file descriptor, which becomes readable when any source
+/// has an event, so this poller can be nested in another event loop.
+impl<K> AsFd for Poller<K>
+{
+	fn as_fd(&self) -> BorrowedFd<'_>
+	{
+		self.inner.as_fd()
+	}
+}
+
+impl<K> AsRawFd for Poller<K>
+{
+	fn as_raw_fd(&self) -> RawFd
+	{
+		self.inner.as_raw_fd()
+	}
+}
+
+/// Every source is removed from the poller and closed when the poller goes away, however that
+/// happens, so an early return from [`Poller::each_with()`] can't leak any of them.
+impl<K> Drop for Poller<K>
+{
+	fn drop(&mut self)
+	{
+		Self::cleanup(&mut self.inner, mem::take(&mut self.sources));
+	}
+}
+
 /// Implementation details.
-impl Poller
+impl<K> Poller<K>
 {
-	fn cleanup(poller: &mut polling::Poller, sources: Vec<File>)
+	fn cleanup(poller: &mut polling::Poller, sources: HashMap<RawFd, Registered<K>>)
 	{
-		for source in sources {
-			let raw_fd: RawFd = source.as_raw_fd();
+		for source in sources.into_values() {
+			let raw_fd: RawFd = source.source.as_raw_fd();
 			trace!("deleting file descriptor {raw_fd} for inner poller");
-			poller.delete(source).unwrap_or_else(|e| {
+			poller.delete(source.source.as_fd()).unwrap_or_else(|e| {
 				// FIXME: return actual errors?
 				error!("error dropping poller for file descriptor {raw_fd}: {e}");
 			});
@@ -1,11 +1,17 @@
-use std::ffi::{OsString, c_char};
+use std::ffi::{OsString, c_char, c_int};
+use std::mem::MaybeUninit;
 use std::path::{PathBuf, Path};
-use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::os::unix::ffi::OsStringExt;
 use std::sync::LazyLock;
 
 use bstr::ByteSlice;
 use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::NixPath;
+use nix::sys::termios::SetArg;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 #[allow(unused_imports)]
 use {
     log::{trace, debug, info, warn, error},
@@ -13,21 +19,52 @@ use {
 };
 
 use crate::{DataBuf, DataBufExt, DataExt};
+use crate::syscall::retry_on_eintr;
 
 mod openpt_error;
 pub use openpt_error::OpenptError;
+mod grantpt_error;
+pub use grantpt_error::GrantptError;
 mod unlockpt_error;
 pub use unlockpt_error::UnlockptError;
 mod ptsname_error;
 pub use ptsname_error::PtsnameError;
+#[cfg(target_os = "linux")]
+mod pty_number_error;
+#[cfg(target_os = "linux")]
+pub use pty_number_error::PtyNumberError;
 mod csctty_error;
 pub use csctty_error::CscttyError;
+mod get_foreground_pgrp_error;
+pub use get_foreground_pgrp_error::GetForegroundPgrpError;
+mod set_foreground_pgrp_error;
+pub use set_foreground_pgrp_error::SetForegroundPgrpError;
+mod packet_mode_error;
+pub use packet_mode_error::PacketModeError;
+mod send_tty_signal_error;
+pub use send_tty_signal_error::SendTtySignalError;
+mod winsize_error;
+pub use winsize_error::WinsizeError;
+mod pty_peer_error;
+pub use pty_peer_error::PtyPeerError;
+mod open_peer_error;
+pub use open_peer_error::OpenPeerError;
+mod pty_pair_error;
+pub use pty_pair_error::PtyPairError;
+mod termios_error;
+pub use termios_error::TermiosError;
+mod raw;
+pub use raw::RawMode;
+mod master;
+pub use master::PtyMaster;
+mod packet;
+pub use packet::{Packet, PacketControl};
 
 pub const NUL_CHAR: c_char = 0;
 pub const NUL_BYTE: u8 = 0;
 
 /// Access will panic on the few error conditions that *should* be unreachable.
-static TTY_NAME_MAX: LazyLock<usize> = LazyLock::new(|| {
+pub(crate) static TTY_NAME_MAX: LazyLock<usize> = LazyLock::new(|| {
 	use nix::unistd::SysconfVar;
 
 	let limit: i64 = nix::unistd::sysconf(SysconfVar::TTY_NAME_MAX)
@@ -45,24 +82,67 @@ static TTY_NAME_MAX: LazyLock<usize> = LazyLock::new(|| {
 });
 
 
-/// Argument for [`openpt()`].
+/// Argument for [`openpt()`], which is whether to pass it `O_NOCTTY`.
+///
+/// This only decides whether opening the controller is *allowed* to make it our controlling
+/// terminal. Linux never does that for a PTY controller either way; the terminal child only
+/// becomes a controlling terminal with `TIOCSCTTY`, as from [`csctty()`], which is what
+/// [`Session::controlling()`](crate::Session::controlling) actually controls. Other systems may
+/// make the controller our controlling terminal if we don't have one yet, without `O_NOCTTY`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum OpenptControl
 {
+	/// Without `O_NOCTTY`.
 	BecomeControllingTerminal,
+	/// With `O_NOCTTY`.
 	BecomeNonControllingTerminal,
 }
 
+impl OpenptControl
+{
+	pub const fn from_noctty(no_ctty: bool) -> Self
+	{
+		if no_ctty {
+			OpenptControl::BecomeNonControllingTerminal
+		} else {
+			OpenptControl::BecomeControllingTerminal
+		}
+	}
+
+	/// The flags this means for `posix_openpt()`, which always include `O_RDWR`.
+	pub const fn as_oflag(self) -> OFlag
+	{
+		match self {
+			OpenptControl::BecomeControllingTerminal => OFlag::O_RDWR,
+			OpenptControl::BecomeNonControllingTerminal => {
+				OFlag::O_RDWR.union(OFlag::O_NOCTTY)
+			},
+		}
+	}
+}
+
 /// Rust wrapper for `posix_openpt(3p)`, implemented with [`nix::pty::posix_openpt()`].
+///
+/// The controller is close-on-exec, like Rust's own file descriptors, since nothing but us should
+/// need it; use [`openpt_with_flags()`] without `O_CLOEXEC` if it should be inherited.
 pub fn openpt(control_type: OpenptControl) -> Result<OwnedFd, OpenptError>
 {
-	use nix::fcntl::OFlag;
+	openpt_with_flags(control_type, OFlag::O_CLOEXEC)
+}
 
-	use OpenptControl::*;
-	let flags = match control_type {
-		BecomeControllingTerminal => OFlag::O_RDWR,
-		BecomeNonControllingTerminal => OFlag::O_RDWR | OFlag::O_NOCTTY,
-	};
+/// Like [`openpt()`], but opens the controller with `extra_flags`, which aren't `O_CLOEXEC`
+/// unless that's one of them.
+///
+/// POSIX only defines `O_RDWR` and `O_NOCTTY` for `posix_openpt()`, so what else works depends
+/// on the system; Linux allows `O_NONBLOCK`, for instance. `O_CLOEXEC` works everywhere, since
+/// we set it with `fcntl()` instead.
+pub fn openpt_with_flags(
+	control_type: OpenptControl,
+	extra_flags: OFlag,
+) -> Result<OwnedFd, OpenptError>
+{
+	let cloexec = extra_flags.contains(OFlag::O_CLOEXEC);
+	let flags = control_type.as_oflag() | extra_flags.difference(OFlag::O_CLOEXEC);
 
 	let pty_controller = match nix::pty::posix_openpt(flags) {
 		Ok(fd) => fd,
@@ -79,52 +159,71 @@ pub fn openpt(control_type: OpenptControl) -> Result<OwnedFd, OpenptError>
 	// other special handling.
 	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
 
+	if cloexec {
+		use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+		// Per POSIX, this can only fail for invalid file descriptors, which this isn't.
+		fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))
+			.unwrap_or_else(|e| panic!("fcntl(F_SETFD) on fresh PTY controller failed: {e}"));
+	}
+
 	Ok(fd)
 }
 
+/// Rust wrapper for `grantpt(3p)`, implemented with [`libc::grantpt()`].
+///
+/// On Linux with devpts this is effectively a no-op, but other Unixes may need it to set the
+/// ownership and permissions of the terminal child before it can be opened.
+pub fn grantpt(pty_fd: BorrowedFd) -> Result<(), GrantptError>
+{
+	let fd = pty_fd.as_raw_fd();
+	// SAFETY: no memory shenanigans here!
+	let code = match retry_on_eintr(|| unsafe { libc::grantpt(fd) }) {
+		Ok(code) => code,
+		Err(errno) => {
+			let grantpt_err = GrantptError::from_errno(errno);
+
+			return Err(grantpt_err);
+		},
+	};
+	// Per POSIX, `grantpt()` may only return `0`, or `-1`.
+	debug_assert!(code == 0, "grantpt() returned invalid code {code}");
+
+	Ok(())
+}
+
 /// Rust wrapper for `unlockpt(3p)`, implemented with [`libc::unlockpt()`].
 pub fn unlockpt(pty_fd: BorrowedFd) -> Result<(), UnlockptError>
 {
 	let fd = pty_fd.as_raw_fd();
 	// SAFETY: no memory shenanigans here!
-	let code = unsafe { libc::unlockpt(fd) };
-	if code < 0 {
-		let errno = Errno::last();
-		let unlockpt_err = UnlockptError::from_errno(errno);
+	let code = match retry_on_eintr(|| unsafe { libc::unlockpt(fd) }) {
+		Ok(code) => code,
+		Err(errno) => {
+			let unlockpt_err = UnlockptError::from_errno(errno);
 
-		return Err(unlockpt_err);
-	}
+			return Err(unlockpt_err);
+		},
+	};
 	// Per POSIX, `unlockpt()` may only return `0`, or `-1`.
 	debug_assert!(code == 0, "unlockpt() returned invalid code {code}");
 
 	Ok(())
 }
 
-/// Rust wrapper for `ptsname_r(3p)`, implemented with [`libc::ptsname_r()`].
+/// Rust wrapper for `ptsname_r(3p)`, implemented with [`libc::ptsname_r()`] where it exists.
+///
+/// Apple platforms don't have `ptsname_r()`, so there we use the `TIOCPTYGNAME` ioctl instead.
+/// Anywhere else without it, we fall back to `ptsname(3p)`, which returns a pointer to a static
+/// buffer. We only ever call that with a lock held, but that can't stop other code in this process
+/// that calls `ptsname()` from clobbering it, so this is not fully thread-safe on those platforms.
 pub fn ptsname(pty_fd: BorrowedFd) -> Result<Box<Path>, PtsnameError>
 {
-	// + 1 for the NUL terminator.
-	let buf_len = *TTY_NAME_MAX + 1;
-	let mut buffer = DataBuf::zeroed(buf_len);
-	debug_assert_eq!(buf_len, buffer.len());
-	let buf_ptr: *mut c_char = buffer.as_c_buf_mut();
-
-	let fd = pty_fd.as_raw_fd();
-	// SAFETY: `buf_ptr` is non-null, and has already been zeroed with `buffer.len()` characters.
-	let code = unsafe { libc::ptsname_r(fd, buf_ptr, buffer.len()) };
-	if code < 0 {
-		let errno = Errno::last();
-		let ptsname_err = PtsnameError::from_errno(errno);
-
-		return Err(ptsname_err);
-	}
-	// Per POSIX, `ptsname_r` may only return `0` or `-1`.
-	debug_assert!(code == 0, "ptsname_r() returned invalid code {code}");
+	let mut buffer: DataBuf = ptsname_bytes(pty_fd).map_err(PtsnameError::from_errno)?;
 
 	let nul_pos = match buffer.find_byte(NUL_BYTE) {
 		Some(pos) => pos,
 		None => {
-			panic!("`ptsname_r()` filled our string with garbage (no NUL): {:?}", buffer);
+			panic!("`ptsname()` filled our string with garbage (no NUL): {:?}", buffer);
 		},
 	};
 
@@ -142,7 +241,296 @@ pub fn ptsname(pty_fd: BorrowedFd) -> Result<Box<Path>, PtsnameError>
 	Ok(path)
 }
 
-pub fn getwinsz(fd: BorrowedFd) -> libc::winsize
+/// Rust wrapper for `ioctl(TIOCGPTN)`, which gets the number of the terminal child for `pty_fd`,
+/// the `N` in [`ptsname()`]'s `/dev/pts/N`.
+///
+/// There's no way to ask Linux for a particular number when opening a pseudo-terminal; the
+/// kernel always picks the lowest one that isn't in use.
+#[cfg(target_os = "linux")]
+pub fn pty_number(pty_fd: BorrowedFd) -> Result<u32, PtyNumberError>
+{
+	let mut number: libc::c_uint = 0;
+	let result = retry_on_eintr(|| {
+		unsafe { libc::ioctl(pty_fd.as_raw_fd(), libc::TIOCGPTN, &raw mut number) }
+	});
+	trace!("ioctl(TIOCGPTN) returned {result:?}");
+	if let Err(errno) = result {
+		let err = PtyNumberError::from_errno(errno);
+		return Err(err);
+	}
+
+	Ok(number)
+}
+
+/// The NUL-terminated name of the terminal child for `pty_fd`, from `ptsname_r()`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn ptsname_bytes(pty_fd: BorrowedFd) -> Result<DataBuf, Errno>
+{
+	// + 1 for the NUL terminator.
+	let buf_len = *TTY_NAME_MAX + 1;
+	let mut buffer = DataBuf::zeroed(buf_len);
+	debug_assert_eq!(buf_len, buffer.len());
+	let buf_ptr: *mut c_char = buffer.as_c_buf_mut();
+
+	let fd = pty_fd.as_raw_fd();
+	let code = retry_on_eintr(|| {
+		// SAFETY: `buf_ptr` is non-null, and has already been zeroed with `buffer.len()` characters.
+		let code = unsafe { libc::ptsname_r(fd, buf_ptr, buffer.len()) };
+		// glibc returns the error number itself instead of `-1`.
+		if code > 0 {
+			Errno::set_raw(code);
+			return -1;
+		}
+		code
+	})?;
+	// Per POSIX, `ptsname_r` may only return `0` or `-1`.
+	debug_assert!(code == 0, "ptsname_r() returned invalid code {code}");
+
+	Ok(buffer)
+}
+
+/// The NUL-terminated name of the terminal child for `pty_fd`, from `TIOCPTYGNAME`.
+#[cfg(target_vendor = "apple")]
+fn ptsname_bytes(pty_fd: BorrowedFd) -> Result<DataBuf, Errno>
+{
+	// Per `<sys/ttycom.h>`, `TIOCPTYGNAME` takes a `char[128]`.
+	const PTY_NAME_MAX: usize = 128;
+	let mut buffer = DataBuf::zeroed(PTY_NAME_MAX);
+	let buf_ptr: *mut c_char = buffer.as_c_buf_mut();
+
+	let fd = pty_fd.as_raw_fd();
+	// SAFETY: `buf_ptr` is non-null, and points to the 128 characters this ioctl writes at most.
+	retry_on_eintr(|| unsafe { libc::ioctl(fd, libc::TIOCPTYGNAME, buf_ptr) })?;
+
+	Ok(buffer)
+}
+
+/// The NUL-terminated name of the terminal child for `pty_fd`, from the non-reentrant
+/// `ptsname()`.
+#[cfg(not(any(
+	target_os = "linux",
+	target_os = "android",
+	target_os = "freebsd",
+	target_vendor = "apple",
+)))]
+fn ptsname_bytes(pty_fd: BorrowedFd) -> Result<DataBuf, Errno>
+{
+	use std::ffi::CStr;
+	use std::sync::Mutex;
+
+	/// `ptsname()` returns a static buffer, so at least make sure *we* don't race on it.
+	static PTSNAME_LOCK: Mutex<()> = Mutex::new(());
+	// Nothing the lock protects can be left in a bad state, so poisoning doesn't matter.
+	let _guard = PTSNAME_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+	Errno::clear();
+	// SAFETY: we hold the lock, and copy the result out before releasing it.
+	let name_ptr: *mut c_char = unsafe { libc::ptsname(pty_fd.as_raw_fd()) };
+	if name_ptr.is_null() {
+		return Err(Errno::last());
+	}
+
+	// SAFETY: `ptsname()` returned non-null, so this is a valid NUL-terminated string.
+	let name = unsafe { CStr::from_ptr(name_ptr) };
+
+	Ok(name.to_bytes_with_nul().to_vec())
+}
+
+/// Rust wrapper for `ioctl(TIOCGPTPEER)`, which opens the other side of the pseudo-terminal
+/// controlled by `master`, without going through its path in `/dev/pts`.
+///
+/// Kernels older than Linux 4.13 don't support this, and fail with [`PtyPeerError::Unsupported`],
+/// in which case [`ptsname()`] and opening that path is the way to go. Other systems don't have
+/// `TIOCGPTPEER` at all, so there this always fails that way.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn pty_peer(master: BorrowedFd, flags: OFlag) -> Result<OwnedFd, PtyPeerError>
+{
+	let flags: c_int = flags.bits();
+	let result = retry_on_eintr(|| {
+		unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCGPTPEER, flags) }
+	});
+	trace!("ioctl(TIOCGPTPEER) returned {result:?}");
+	let peer_fd: RawFd = match result {
+		Ok(peer_fd) => peer_fd,
+		Err(errno) => {
+			let err = PtyPeerError::from_errno(errno);
+			return Err(err);
+		},
+	};
+
+	// SAFETY: `TIOCGPTPEER` returns a newly opened file descriptor, which is now ours.
+	let peer = unsafe { OwnedFd::from_raw_fd(peer_fd) };
+
+	Ok(peer)
+}
+
+/// Without `TIOCGPTPEER`, [`open_pty_pair()`] opens the terminal child by its path instead.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn pty_peer(_master: BorrowedFd, _flags: OFlag) -> Result<OwnedFd, PtyPeerError>
+{
+	Err(PtyPeerError::Unsupported)
+}
+
+/// Open the terminal child at `path`, like from [`ptsname()`], with `open()`.
+///
+/// This is the racy fallback for [`pty_peer()`], since something else could open the same path
+/// first, or the controller could be closed and its number reused in between.
+pub fn open_peer(path: &Path, flags: OFlag) -> Result<OwnedFd, OpenPeerError>
+{
+	let result = path
+		.with_nix_path(|path| {
+			retry_on_eintr(|| unsafe { libc::open(path.as_ptr(), flags.bits()) })
+		})
+		.and_then(|result| result);
+	trace!("open({}) returned {result:?}", path.display());
+	let peer_fd: RawFd = match result {
+		Ok(peer_fd) => peer_fd,
+		Err(errno) => {
+			let err = OpenPeerError::from_errno(errno);
+			return Err(err);
+		},
+	};
+
+	// SAFETY: `open()` returns a newly opened file descriptor, which is now ours.
+	let peer = unsafe { OwnedFd::from_raw_fd(peer_fd) };
+
+	Ok(peer)
+}
+
+/// Open a new pseudo-terminal, returning its controller and the terminal child, in that order.
+/// Both are close-on-exec.
+///
+/// This does the whole [`openpt()`], [`grantpt()`], [`unlockpt()`] dance, and then opens the
+/// child with [`pty_peer()`], falling back to its path from [`ptsname()`] on kernels without
+/// `TIOCGPTPEER`. The child is opened with `O_NOCTTY`, so that it only becomes a controlling
+/// terminal if you ask for that explicitly, like with [`csctty()`].
+///
+/// Neither file descriptor is `O_NONBLOCK`, and they don't share their file status flags,
+/// so it's fine to make the controller nonblocking while the child stays blocking.
+pub fn open_pty_pair(control: OpenptControl) -> Result<(OwnedFd, OwnedFd), PtyPairError>
+{
+	let controller: OwnedFd = openpt(control)?;
+
+	grantpt(controller.as_fd())?;
+	unlockpt(controller.as_fd())?;
+
+	// Close-on-exec too, like the controller; a child that wants it as stdio can `dup2()` it.
+	let peer_flags = OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_CLOEXEC;
+	let child: OwnedFd = match pty_peer(controller.as_fd(), peer_flags) {
+		Ok(peer) => peer,
+		Err(PtyPeerError::Unsupported) => {
+			// Older kernel; do it the racy way instead.
+			debug!("TIOCGPTPEER unsupported; opening terminal child by path");
+			open_peer_by_path(controller.as_fd(), peer_flags)?
+		},
+		Err(e) => {
+			return Err(PtyPairError::from(e));
+		},
+	};
+
+	Ok((controller, child))
+}
+
+/// Fallback for [`pty_peer()`], which opens the terminal child by its path from [`ptsname()`].
+fn open_peer_by_path(pty_fd: BorrowedFd, flags: OFlag) -> Result<OwnedFd, PtyPairError>
+{
+	let term_name = ptsname(pty_fd)?;
+	info!("Our terminal is {}", term_name.display());
+
+	let other_side = open_peer(&term_name, flags)?;
+
+	Ok(other_side)
+}
+
+/// The traditional 80x24 terminal size, for when there's no better size to use.
+pub const DEFAULT_WINSIZE: libc::winsize = libc::winsize {
+	ws_row: 24,
+	ws_col: 80,
+	ws_xpixel: 0,
+	ws_ypixel: 0,
+};
+
+/// A terminal size, like [`libc::winsize`] but with friendlier field names, and
+/// `serde` support behind the `serde` feature, for storing sizes in config files.
+///
+/// The pixel dimensions are unused by most programs, and default to 0 when deserializing.
+/// Things that draw images in the terminal, like sixel or kitty graphics, do rely on them though.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WinSize
+{
+	pub cols: u16,
+	pub rows: u16,
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub xpixel: u16,
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub ypixel: u16,
+}
+
+impl WinSize
+{
+	/// A window `cols` cells wide and `rows` cells tall, where each cell is `cell_width` by
+	/// `cell_height` pixels.
+	///
+	/// The pixel dimensions saturate, rather than overflow, for absurdly large windows.
+	pub const fn from_cells(cols: u16, rows: u16, cell_width: u16, cell_height: u16) -> Self
+	{
+		Self {
+			cols,
+			rows,
+			xpixel: cols.saturating_mul(cell_width),
+			ypixel: rows.saturating_mul(cell_height),
+		}
+	}
+
+	/// The width and height of one cell in pixels, or `None` if the pixel dimensions aren't known.
+	pub const fn cell_pixels(&self) -> Option<(u16, u16)>
+	{
+		if self.cols == 0 || self.rows == 0 || self.xpixel == 0 || self.ypixel == 0 {
+			return None;
+		}
+
+		Some((self.xpixel / self.cols, self.ypixel / self.rows))
+	}
+
+	/// Whether this size says anything about pixels at all.
+	pub const fn has_pixels(&self) -> bool
+	{
+		self.xpixel != 0 || self.ypixel != 0
+	}
+}
+
+impl From<libc::winsize> for WinSize
+{
+	fn from(size: libc::winsize) -> Self
+	{
+		Self {
+			cols: size.ws_col,
+			rows: size.ws_row,
+			xpixel: size.ws_xpixel,
+			ypixel: size.ws_ypixel,
+		}
+	}
+}
+
+impl From<WinSize> for libc::winsize
+{
+	fn from(size: WinSize) -> Self
+	{
+		Self {
+			ws_row: size.rows,
+			ws_col: size.cols,
+			ws_xpixel: size.xpixel,
+			ws_ypixel: size.ypixel,
+		}
+	}
+}
+
+/// Rust wrapper for `ioctl(TIOCGWINSZ)`.
+///
+/// This fails with [`WinsizeError::NotATty`] if `fd` isn't a terminal, which is completely
+/// normal for things like stdin when it's a pipe.
+pub fn getwinsz(fd: BorrowedFd) -> Result<libc::winsize, WinsizeError>
 {
 	let mut winsize = libc::winsize {
 		ws_row: 0,
@@ -151,35 +539,215 @@ pub fn getwinsz(fd: BorrowedFd) -> libc::winsize
 		ws_ypixel: 0,
 	};
 
-	let code = unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCGWINSZ, &raw mut winsize) };
-	trace!("ioctl(TIOCGWINSZ) returned {code}");
-	if code < 0 {
-		let errno = Errno::last();
-		panic!("ioctl(TIOCGWINSZ) returned supposedly imposssible errno {errno}");
+	let result = retry_on_eintr(|| {
+		unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCGWINSZ, &raw mut winsize) }
+	});
+	trace!("ioctl(TIOCGWINSZ) returned {result:?}");
+	if let Err(errno) = result {
+		let err = WinsizeError::from_errno(errno);
+		return Err(err);
+	}
+
+	Ok(winsize)
+}
+
+/// Rust wrapper for `ioctl(TIOCSWINSZ)`.
+pub fn setwinsz(fd: BorrowedFd, size: libc::winsize) -> Result<(), WinsizeError>
+{
+	let result = retry_on_eintr(|| {
+		unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCSWINSZ, &raw const size) }
+	});
+	trace!("ioctl(TIOCSWINSZ) returned {result:?}");
+	if let Err(errno) = result {
+		let err = WinsizeError::from_errno(errno);
+		return Err(err);
+	}
+
+	Ok(())
+}
+
+/// Give `to` the same window size as `from`, pixel dimensions and all, sending whatever's
+/// running on `to` a `SIGWINCH`, like a terminal emulator does for its children when resized.
+pub fn sync_winsize(from: BorrowedFd, to: BorrowedFd) -> Result<(), WinsizeError>
+{
+	let size = getwinsz(from)?;
+	trace!("syncing window size {}x{} to fd {}", size.ws_col, size.ws_row, to.as_raw_fd());
+
+	setwinsz(to, size)
+}
+
+/// Rust wrapper for `tcgetattr(3p)`, implemented with [`libc::tcgetattr()`].
+pub fn get_termios(fd: BorrowedFd) -> Result<libc::termios, TermiosError>
+{
+	let mut termios = MaybeUninit::<libc::termios>::uninit();
+
+	let result = retry_on_eintr(|| {
+		unsafe { libc::tcgetattr(fd.as_raw_fd(), termios.as_mut_ptr()) }
+	});
+	let code = match result {
+		Ok(code) => code,
+		Err(errno) => {
+			let err = TermiosError::from_errno(errno);
+			return Err(err);
+		},
+	};
+	// Per POSIX, `tcgetattr()` may only return `0`, or `-1`.
+	debug_assert!(code == 0, "tcgetattr() returned invalid code {code}");
+
+	// SAFETY: `tcgetattr()` succeeded, so it has filled in the whole struct.
+	let termios = unsafe { termios.assume_init() };
+
+	Ok(termios)
+}
+
+/// Rust wrapper for `tcsetattr(3p)`, implemented with [`libc::tcsetattr()`].
+///
+/// `when` specifies if the change happens immediately (`TCSANOW`), after all output has been
+/// written (`TCSADRAIN`), or after all output has been written and pending input is discarded
+/// (`TCSAFLUSH`).
+pub fn set_termios(fd: BorrowedFd, when: SetArg, termios: &libc::termios) -> Result<(), TermiosError>
+{
+	let result = retry_on_eintr(|| {
+		unsafe { libc::tcsetattr(fd.as_raw_fd(), when as c_int, termios) }
+	});
+	let code = match result {
+		Ok(code) => code,
+		Err(errno) => {
+			let err = TermiosError::from_errno(errno);
+			return Err(err);
+		},
+	};
+	// Per POSIX, `tcsetattr()` may only return `0`, or `-1`.
+	debug_assert!(code == 0, "tcsetattr() returned invalid code {code}");
+
+	Ok(())
+}
+
+/// Turn the terminal's echoing of input back to its output on or off.
+///
+/// This only changes `ECHO`, so canonical mode and the rest of the line discipline stay as they
+/// were. For a PTY, either side works, since they share the one line discipline.
+pub fn set_echo(fd: BorrowedFd, echo: bool) -> Result<(), TermiosError>
+{
+	let mut termios = get_termios(fd)?;
+	if echo {
+		termios.c_lflag |= libc::ECHO;
+	} else {
+		termios.c_lflag &= !libc::ECHO;
 	}
 
-	winsize
+	set_termios(fd, SetArg::TCSANOW, &termios)
 }
 
-pub fn setwinsz(fd: BorrowedFd, size: libc::winsize)
+/// Turn the terminal's software flow control on or off.
+///
+/// With it on, `^S` and `^Q` pause and resume output (`IXON`), rather than reaching whatever's
+/// reading the terminal, and the terminal may send them itself when its input fills up (`IXOFF`).
+/// Like [`set_echo()`], this changes the line discipline, so for a PTY, either side works.
+pub fn set_flow_control(fd: BorrowedFd, flow_control: bool) -> Result<(), TermiosError>
 {
-	let code = unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCSWINSZ, &raw const size) };
-	trace!("ioctl(TIOCSWINSZ) returned {code}");
-	if code < 0 {
-		let errno = Errno::last();
-		panic!("ioctl(TIOCSWINSZ) returned supposedly imposssible errno {errno}");
+	let mut termios = get_termios(fd)?;
+	if flow_control {
+		termios.c_iflag |= libc::IXON | libc::IXOFF;
+	} else {
+		termios.c_iflag &= !(libc::IXON | libc::IXOFF);
 	}
+
+	set_termios(fd, SetArg::TCSANOW, &termios)
 }
 
 pub fn csctty(fd: BorrowedFd) -> Result<(), CscttyError>
 {
-	let code = unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCSCTTY) };
-	let errno = Errno::last();
-	trace!("ioctl(TIOCSCTTY) returned {code}");
-	if code < 0 {
+	let result = retry_on_eintr(|| unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCSCTTY) });
+	trace!("ioctl(TIOCSCTTY) returned {result:?}");
+	if let Err(errno) = result {
 		let err = CscttyError::from_errno(errno);
 		return Err(err);
 	}
 
 	Ok(())
 }
+
+/// Rust wrapper for `ioctl(TIOCGPGRP)`, which gets the foreground process group of the terminal.
+pub fn get_foreground_pgrp(fd: BorrowedFd) -> Result<Pid, GetForegroundPgrpError>
+{
+	let mut pgid: libc::pid_t = 0;
+
+	let result = retry_on_eintr(|| {
+		unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCGPGRP, &raw mut pgid) }
+	});
+	trace!("ioctl(TIOCGPGRP) returned {result:?}");
+	if let Err(errno) = result {
+		let err = GetForegroundPgrpError::from_errno(errno);
+		return Err(err);
+	}
+
+	Ok(Pid::from_raw(pgid))
+}
+
+/// Rust wrapper for `ioctl(TIOCSIG)`, which sends `signal` to the foreground process group of
+/// the terminal child of `master`, as if its user had typed the character for it, like `^C` for
+/// `SIGINT`.
+///
+/// Unlike [`killpg()`](nix::sys::signal::killpg), this reaches whichever job is in the
+/// foreground right now, like a program a shell in the PTY is running, rather than one process
+/// group we picked. If the terminal child has no foreground process group, because it isn't
+/// anyone's controlling terminal, this silently does nothing, so see [`get_foreground_pgrp()`].
+pub fn send_tty_signal(master: BorrowedFd, signal: Signal) -> Result<(), SendTtySignalError>
+{
+	// Solaris and illumos call it something else.
+	#[cfg(not(any(target_os = "solaris", target_os = "illumos")))]
+	let request = libc::TIOCSIG;
+	#[cfg(any(target_os = "solaris", target_os = "illumos"))]
+	let request = libc::TIOCSIGNAL;
+
+	let signal: c_int = signal as c_int;
+	let result = retry_on_eintr(|| {
+		unsafe { libc::ioctl(master.as_raw_fd(), request, signal) }
+	});
+	trace!("ioctl(TIOCSIG, {signal}) returned {result:?}");
+	if let Err(errno) = result {
+		let err = SendTtySignalError::from_errno(errno);
+		return Err(err);
+	}
+
+	Ok(())
+}
+
+/// Rust wrapper for `ioctl(TIOCPKT)`, which turns packet mode on or off for the PTY controller
+/// `master`.
+///
+/// In packet mode, each read from `master` is either the child's output after a zero byte, or a
+/// single byte reporting a change in the terminal child's state, like its queues being flushed
+/// or flow control stopping output. See [`Packet::parse()`].
+pub fn set_packet_mode(master: BorrowedFd, enabled: bool) -> Result<(), PacketModeError>
+{
+	let enabled: c_int = enabled.into();
+	let result = retry_on_eintr(|| {
+		unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCPKT, &raw const enabled) }
+	});
+	trace!("ioctl(TIOCPKT, {enabled}) returned {result:?}");
+	if let Err(errno) = result {
+		let err = PacketModeError::from_errno(errno);
+		return Err(err);
+	}
+
+	Ok(())
+}
+
+/// Rust wrapper for `ioctl(TIOCSPGRP)`, which sets the foreground process group of the terminal.
+pub fn set_foreground_pgrp(fd: BorrowedFd, pgid: Pid) -> Result<(), SetForegroundPgrpError>
+{
+	let pgid: libc::pid_t = pgid.as_raw();
+
+	let result = retry_on_eintr(|| {
+		unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCSPGRP, &raw const pgid) }
+	});
+	trace!("ioctl(TIOCSPGRP) returned {result:?}");
+	if let Err(errno) = result {
+		let err = SetForegroundPgrpError::from_errno(errno);
+		return Err(err);
+	}
+
+	Ok(())
+}
@@ -1,6 +1,3 @@
-use std::error::Error as StdError;
-use std::fmt::{Display, Result as FmtResult, Formatter};
-
 #[allow(unused_imports)]
 use {
 	log::{trace, debug, info, warn, error},
@@ -11,13 +8,23 @@ use {
 	nix::errno::Errno,
 };
 
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
 /// The error type for [`csctty()`], which contains variants for all error codes that can
 /// be returned by `ioctl(CSCTTY)`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CscttyError
 {
 	/// Insufficient permissions to become the controlling terminal.
+	#[diagnostic(
+		code(floatty::csctty::permission_denied),
+		help("only a session leader can acquire a terminal; call `setsid()` to start a new session first"),
+	)]
 	PermissionDenied,
+	/// An error code that `ioctl(TIOCSCTTY)` isn't documented to return.
+	#[diagnostic(code(floatty::csctty::other))]
+	Other(OtherErrno),
 }
 
 impl CscttyError
@@ -36,81 +43,26 @@ impl CscttyError
 		Some(csctty_error)
 	}
 
-	pub fn from_errno(raw: Errno) -> Self
-	{
-		match Self::try_from_raw(raw) {
-			Some(err) => err,
-			None => {
-				panic!("ioctl(TIOCSCTTY) gave supposedly impossible error code {raw}");
-			},
-		}
-	}
-
 	pub const fn to_errno(self) -> Errno
 	{
 		use Errno::*;
 		use CscttyError::*;
 		match self {
 			PermissionDenied => EPERM,
-		}
-	}
-
-	pub const fn as_errno(self) -> &'static Errno
-	{
-		use Errno::*;
-		use CscttyError::*;
-		match self {
-			PermissionDenied => &EPERM,
+			Other(other) => other.errno(),
 		}
 	}
 
 	/// Not to be confused with [`std::error::Error::description()`].
-	pub const fn desc(self) -> &'static str
+	pub fn desc(self) -> &'static str
 	{
 		// Descriptions from `TIOCSCTTY(2const)`.
 		use CscttyError::*;
 		match self {
 			PermissionDenied => "Insufficient permissions",
+			Other(other) => other.errno().desc(),
 		}
 	}
 }
 
-impl Display for CscttyError
-{
-	fn fmt(&self, f: &mut Formatter) -> FmtResult
-	{
-		let description: &'static str = self.desc();
-		f.write_str(description)?;
-
-		Ok(())
-	}
-}
-
-/// [`std::error::Error::source()`] returns the [`nix::Error`] that caused this error.
-impl StdError for CscttyError
-{
-	fn source(&self) -> Option<&(dyn StdError + 'static)>
-	{
-		// We can actually reconstruct the source error trivially,
-		// so we don't even need to store it.
-		let nix_error: &'static Errno = self.as_errno();
-
-		Some(nix_error)
-	}
-}
-
-impl From<Errno> for CscttyError
-{
-	fn from(other: Errno) -> Self
-	{
-		Self::from_errno(other)
-	}
-}
-
-impl From<CscttyError> for Errno
-{
-	fn from(other: CscttyError) -> Self
-	{
-		CscttyError::to_errno(other)
-	}
-}
+impl_errno_error!(CscttyError, "ioctl(TIOCSCTTY)");
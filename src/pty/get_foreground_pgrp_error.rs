@@ -0,0 +1,72 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`get_foreground_pgrp()`], which contains variants for all error codes that
+/// can be returned by `ioctl(TIOCGPGRP)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GetForegroundPgrpError
+{
+	/// The file descriptor is not a terminal, or is not the controlling terminal of the
+	/// calling process.
+	#[diagnostic(
+		code(floatty::get_foreground_pgrp::not_a_tty),
+		help("this only works on the controlling terminal of the calling process"),
+	)]
+	NotATty,
+	/// An error code that `ioctl(TIOCGPGRP)` isn't documented to return.
+	#[diagnostic(code(floatty::get_foreground_pgrp::other))]
+	Other(OtherErrno),
+}
+
+impl GetForegroundPgrpError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use GetForegroundPgrpError::*;
+		let get_pgrp_error = match raw {
+			ENOTTY => NotATty,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(get_pgrp_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use GetForegroundPgrpError::*;
+		match self {
+			NotATty => ENOTTY,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions from `tcgetpgrp(3p)`.
+		use GetForegroundPgrpError::*;
+		match self {
+			NotATty => {
+				"The calling process does not have a controlling terminal, \
+				or the file is not the controlling terminal"
+			},
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(GetForegroundPgrpError, "ioctl(TIOCGPGRP)");
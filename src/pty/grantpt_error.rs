@@ -0,0 +1,77 @@
+#[allow(unused_imports)]
+use {
+    log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type returned for [`grantpt()`], which contains variants for all error codes that
+/// can be returned by `grantpt(3p)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GrantptError
+{
+	/// The corresponding pseudo-terminal "slave" could not be accessed.
+	#[diagnostic(
+		code(floatty::grantpt::inaccessible_slave),
+		help("check the permissions of /dev/pts, and that devpts is mounted"),
+	)]
+	InaccessibleSlave,
+	/// The file descriptor is not a pseudo-terminal "master".
+	#[diagnostic(
+		code(floatty::grantpt::not_a_pty),
+		help("this should be a file descriptor from `posix_openpt()`"),
+	)]
+	NotAPty,
+	/// An error code that `grantpt()` isn't documented to return.
+	#[diagnostic(code(floatty::grantpt::other))]
+	Other(OtherErrno),
+}
+
+impl GrantptError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use GrantptError::*;
+		let grantpt_error = match raw {
+			EACCES => InaccessibleSlave,
+			EINVAL => NotAPty,
+			_ => {
+				return None;
+			}
+		};
+
+		Some(grantpt_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use GrantptError::*;
+		match self {
+			InaccessibleSlave => EACCES,
+			NotAPty => EINVAL,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions from `grantpt(3p)`.
+		use GrantptError::*;
+		match self {
+			InaccessibleSlave => "The corresponding slave pseudo-terminal device could not be accessed",
+			NotAPty => "The filedes argument is not associated with a master pseudo-terminal device",
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(GrantptError, "grantpt()");
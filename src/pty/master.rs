@@ -0,0 +1,239 @@
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd};
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use nix::errno::Errno;
+use nix::unistd::Pid;
+
+use crate::poller::DEFAULT_BUFFER_SIZE;
+use crate::{DataBuf, DataBufExt};
+use super::{
+	get_foreground_pgrp,
+	get_termios,
+	getwinsz,
+	set_echo,
+	set_flow_control,
+	set_foreground_pgrp,
+	set_packet_mode,
+	setwinsz,
+	GetForegroundPgrpError,
+	Packet,
+	PacketModeError,
+	SetForegroundPgrpError,
+	TermiosError,
+	WinsizeError,
+};
+#[cfg(target_os = "linux")]
+use super::{pty_number, PtyNumberError};
+
+/// Owned handle to the controller side of a PTY, as from [`openpt()`](super::openpt).
+///
+/// Reading gives you what the child wrote to its terminal, and writing is as if the
+/// child's user typed it. Whether those block depends on `O_NONBLOCK`, like any other fd;
+/// see [`FdOps`](crate::FdOps).
+#[derive(Debug)]
+pub struct PtyMaster(OwnedFd);
+
+impl PtyMaster
+{
+	/// The number of the terminal child, with [`pty_number()`](super::pty_number).
+	#[cfg(target_os = "linux")]
+	pub fn number(&self) -> Result<u32, PtyNumberError>
+	{
+		pty_number(self.0.as_fd())
+	}
+
+	/// Get the window size the child sees.
+	pub fn winsize(&self) -> Result<libc::winsize, WinsizeError>
+	{
+		getwinsz(self.0.as_fd())
+	}
+
+	/// Set the window size the child sees, sending it `SIGWINCH`.
+	pub fn set_winsize(&self, size: libc::winsize) -> Result<(), WinsizeError>
+	{
+		setwinsz(self.0.as_fd(), size)
+	}
+
+	/// Like [`PtyMaster::set_winsize()`], keeping the current pixel dimensions.
+	pub fn resize(&self, cols: u16, rows: u16) -> Result<(), WinsizeError>
+	{
+		let current = self.winsize()?;
+		self.set_winsize(libc::winsize {
+			ws_col: cols,
+			ws_row: rows,
+			..current
+		})
+	}
+
+	/// Whether the line discipline echoes what's written here back to us, like a terminal
+	/// showing what its user types.
+	pub fn echo(&self) -> Result<bool, TermiosError>
+	{
+		let termios = get_termios(self.0.as_fd())?;
+		Ok(termios.c_lflag & libc::ECHO != 0)
+	}
+
+	/// Turn echoing on or off, with [`set_echo()`](super::set_echo).
+	pub fn set_echo(&self, echo: bool) -> Result<(), TermiosError>
+	{
+		set_echo(self.0.as_fd(), echo)
+	}
+
+	/// Whether `^S` and `^Q` written here pause and resume output, instead of reaching the child.
+	pub fn flow_control(&self) -> Result<bool, TermiosError>
+	{
+		let termios = get_termios(self.0.as_fd())?;
+		Ok(termios.c_iflag & libc::IXON != 0)
+	}
+
+	/// Turn software flow control on or off, with
+	/// [`set_flow_control()`](super::set_flow_control).
+	pub fn set_flow_control(&self, flow_control: bool) -> Result<(), TermiosError>
+	{
+		set_flow_control(self.0.as_fd(), flow_control)
+	}
+
+	/// The process group of the job in the foreground of the terminal child, with
+	/// [`get_foreground_pgrp()`](super::get_foreground_pgrp).
+	pub fn foreground_pgrp(&self) -> Result<Pid, GetForegroundPgrpError>
+	{
+		get_foreground_pgrp(self.0.as_fd())
+	}
+
+	/// Put the job `pgid` in the foreground of the terminal child, with
+	/// [`set_foreground_pgrp()`](super::set_foreground_pgrp).
+	///
+	/// Like through the child side, this only works for a process in the terminal's session.
+	pub fn set_foreground_pgrp(&self, pgid: Pid) -> Result<(), SetForegroundPgrpError>
+	{
+		set_foreground_pgrp(self.0.as_fd(), pgid)
+	}
+
+	/// Turn packet mode on or off, with [`set_packet_mode()`](super::set_packet_mode).
+	///
+	/// While it's on, read with [`PtyMaster::read_packets_until_block()`] instead, since plain
+	/// reads get the control bytes mixed in with the data.
+	pub fn set_packet_mode(&self, enabled: bool) -> Result<(), PacketModeError>
+	{
+		set_packet_mode(self.0.as_fd(), enabled)
+	}
+
+	/// Read one packet at a time until reading would block, for when packet mode is on and this
+	/// is `O_NONBLOCK`.
+	///
+	/// Like our [`Read`] impl, the child side having been closed counts as end-of-file, so this
+	/// returns whatever it got before that.
+	pub fn read_packets_until_block(&self) -> io::Result<Vec<Packet>>
+	{
+		let mut buffer = DataBuf::zeroed(DEFAULT_BUFFER_SIZE);
+		let mut packets = Vec::new();
+		loop {
+			match (&*self).read(&mut buffer) {
+				Ok(0) => break,
+				Ok(count) => packets.extend(Packet::parse(&buffer[..count])),
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e),
+			}
+		}
+
+		Ok(packets)
+	}
+}
+
+/// On Linux, reading from a PTY whose child side has been closed by everyone fails with
+/// `EIO`. We report that as EOF instead, so things like [`Read::read_to_end()`] just work.
+impl Read for &PtyMaster
+{
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+	{
+		match nix::unistd::read(self.0.as_raw_fd(), buf) {
+			Ok(count) => Ok(count),
+			Err(Errno::EIO) => {
+				trace!("read() on PTY {} returned EIO; treating as EOF", self.0.as_raw_fd());
+				Ok(0)
+			},
+			Err(errno) => Err(io::Error::from(errno)),
+		}
+	}
+}
+
+impl Read for PtyMaster
+{
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+	{
+		(&*self).read(buf)
+	}
+}
+
+impl Write for &PtyMaster
+{
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+	{
+		nix::unistd::write(self.0.as_fd(), buf).map_err(io::Error::from)
+	}
+
+	/// Writes to a PTY aren't buffered on our side, so this does nothing.
+	fn flush(&mut self) -> io::Result<()>
+	{
+		Ok(())
+	}
+}
+
+impl Write for PtyMaster
+{
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+	{
+		(&*self).write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()>
+	{
+		(&*self).flush()
+	}
+}
+
+impl From<OwnedFd> for PtyMaster
+{
+	fn from(fd: OwnedFd) -> Self
+	{
+		Self(fd)
+	}
+}
+
+impl From<PtyMaster> for OwnedFd
+{
+	fn from(master: PtyMaster) -> Self
+	{
+		master.0
+	}
+}
+
+impl AsFd for PtyMaster
+{
+	fn as_fd(&self) -> BorrowedFd<'_>
+	{
+		self.0.as_fd()
+	}
+}
+
+impl AsRawFd for PtyMaster
+{
+	fn as_raw_fd(&self) -> RawFd
+	{
+		self.0.as_raw_fd()
+	}
+}
+
+impl IntoRawFd for PtyMaster
+{
+	fn into_raw_fd(self) -> RawFd
+	{
+		self.0.into_raw_fd()
+	}
+}
@@ -0,0 +1,128 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`open_peer()`](super::open_peer), which contains variants for the error
+/// codes that `open()` can return for the path of a terminal child.
+///
+/// `open()` can fail in far too many ways to list them all here, so the rest are
+/// [`OpenPeerError::Other`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OpenPeerError
+{
+	/// We aren't allowed to open the terminal child.
+	#[diagnostic(
+		code(floatty::open_peer::access_denied),
+		help("call `grantpt()` on the pseudo-terminal first"),
+	)]
+	AccessDenied,
+	/// The terminal child doesn't exist, like because its controller was already closed.
+	#[diagnostic(
+		code(floatty::open_peer::not_found),
+		help("keep the controller open until the terminal child has been opened"),
+	)]
+	NotFound,
+	/// The terminal child's device doesn't exist, like because its controller was already closed.
+	#[diagnostic(
+		code(floatty::open_peer::no_device),
+		help("keep the controller open until the terminal child has been opened"),
+	)]
+	NoDevice,
+	/// The pseudo-terminal has not been unlocked with [`unlockpt()`](crate::pty::unlockpt).
+	#[diagnostic(
+		code(floatty::open_peer::locked),
+		help("call `unlockpt()` on the pseudo-terminal first"),
+	)]
+	Locked,
+	/// All file descriptors available to the process are currently open.
+	#[diagnostic(
+		code(floatty::open_peer::exhausted_file_descriptors),
+		help("close some file descriptors, or raise the limit with `ulimit -n`"),
+	)]
+	ExhaustedFileDescriptors,
+	/// The maximum allowable number of files is currently open in the system.
+	#[diagnostic(
+		code(floatty::open_peer::exhausted_files),
+		help("close some files, or raise the system limit in /proc/sys/fs/file-max"),
+	)]
+	ExhaustedFiles,
+	/// An error code that isn't one of the above.
+	#[diagnostic(code(floatty::open_peer::other))]
+	Other(OtherErrno),
+}
+
+impl OpenPeerError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use OpenPeerError::*;
+		let open_peer_error = match raw {
+			EACCES => AccessDenied,
+			ENOENT => NotFound,
+			ENXIO => NoDevice,
+			EIO => Locked,
+			EMFILE => ExhaustedFileDescriptors,
+			ENFILE => ExhaustedFiles,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(open_peer_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use OpenPeerError::*;
+		match self {
+			AccessDenied => EACCES,
+			NotFound => ENOENT,
+			NoDevice => ENXIO,
+			Locked => EIO,
+			ExhaustedFileDescriptors => EMFILE,
+			ExhaustedFiles => ENFILE,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions from `open(2)` and `pts(4)`.
+		use OpenPeerError::*;
+		match self {
+			AccessDenied => {
+				"Search or read and write permission was denied for the terminal child"
+			},
+			NotFound => {
+				"The terminal child does not exist"
+			},
+			NoDevice => {
+				"No device corresponds to the terminal child"
+			},
+			Locked => {
+				"The pseudo-terminal is still locked"
+			},
+			ExhaustedFileDescriptors => {
+				"The per-process limit on the number of open file descriptors has been reached"
+			},
+			ExhaustedFiles => {
+				"The system-wide limit on the total number of open files has been reached"
+			},
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(OpenPeerError, "open()");
@@ -1,6 +1,3 @@
-use std::error::Error as StdError;
-use std::fmt::{Display, Result as FmtResult, Formatter};
-
 #[allow(unused_imports)]
 use {
     log::{trace, debug, info, warn, error},
@@ -11,22 +8,47 @@ use {
 	nix::errno::Errno,
 };
 
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
 /// The error type for [`openpt()`], which contains variants for all error codes that can be
 /// returned by `posix_openpt(3p)`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
-// We're not repeating the enum name;
-// it just so happens that all possible errors are an "exhausted" variant.
-#[allow(clippy::enum_variant_names)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OpenptError
 {
     /// All file descriptors available to the process are currently open.
+	#[diagnostic(
+		code(floatty::openpt::exhausted_file_descriptors),
+		help("close some file descriptors, or raise the limit with `ulimit -n`"),
+	)]
 	ExhaustedFileDescriptors,
     /// The maximum allowable number of file sis openly open in the system.
+	#[diagnostic(
+		code(floatty::openpt::exhausted_files),
+		help("close some files, or raise the system limit in /proc/sys/fs/file-max"),
+	)]
 	ExhaustedFiles,
     /// Out of pseudo-terminal resources.
+	#[diagnostic(
+		code(floatty::openpt::exhausted_ptys),
+		help("increase the system pty limit in /proc/sys/kernel/pty/max, or close other terminals"),
+	)]
 	ExhaustedPtys,
     /// Out of STREAMS resources.
+	#[diagnostic(
+		code(floatty::openpt::exhausted_streams),
+		help("close other STREAMS devices, or increase the system's STREAMS resources"),
+	)]
 	ExhaustedStreams,
+	/// The value of `oflag` is not valid.
+	#[diagnostic(
+		code(floatty::openpt::invalid_flags),
+		help("only pass `posix_openpt()` flags this system supports, like `O_CLOEXEC` on Linux"),
+	)]
+	InvalidFlags,
+	/// An error code that `posix_openpt()` isn't documented to return.
+	#[diagnostic(code(floatty::openpt::other))]
+	Other(OtherErrno),
 }
 
 impl OpenptError
@@ -40,10 +62,8 @@ impl OpenptError
 			ENFILE => ExhaustedFiles,
 			EAGAIN => ExhaustedPtys,
 			ENOSR => ExhaustedStreams,
-			EINVAL => {
-				// Only reachable if `oflag` is invalid. Should be impossible in our code.
-				unreachable!();
-			},
+			// Only reachable with bad flags to `openpt_with_flags()`.
+			EINVAL => InvalidFlags,
 
 			_ => {
 				return None;
@@ -53,16 +73,6 @@ impl OpenptError
 		Some(openpt_error)
 	}
 
-	pub fn from_errno(raw: Errno) -> Self
-	{
-		match Self::try_from_raw(raw) {
-			Some(err) => err,
-			None => {
-				panic!("posix_openpt() gave supposedly impossible error code {raw}");
-			},
-		}
-	}
-
 	pub const fn to_errno(self) -> Errno
 	{
 		use Errno::*;
@@ -72,23 +82,13 @@ impl OpenptError
 			ExhaustedFiles => ENFILE,
 			ExhaustedPtys => EAGAIN,
 			ExhaustedStreams => ENOSR,
-		}
-	}
-
-	pub const fn as_errno(self) -> &'static Errno
-	{
-		use Errno::*;
-		use OpenptError::*;
-		match self {
-			ExhaustedFileDescriptors => &EMFILE,
-			ExhaustedFiles => &ENFILE,
-			ExhaustedPtys => &EAGAIN,
-			ExhaustedStreams => &ENOSR,
+			InvalidFlags => EINVAL,
+			Other(other) => other.errno(),
 		}
 	}
 
 	/// Not to be confused with [`std::error::Error::description()`].
-	pub const fn desc(self) -> &'static str
+	pub fn desc(self) -> &'static str
 	{
 		// Descriptions from `posix_openpt(3p)`.
 		use OpenptError::*;
@@ -105,46 +105,12 @@ impl OpenptError
 			ExhaustedStreams => {
 				"Out of STREAMS resources"
 			},
+			InvalidFlags => {
+				"The value of oflag is not valid"
+			},
+			Other(other) => other.errno().desc(),
 		}
 	}
 }
 
-impl Display for OpenptError
-{
-	fn fmt(&self, f: &mut Formatter) -> FmtResult
-	{
-		let description: &'static str = self.desc();
-		f.write_str(description)?;
-
-		Ok(())
-	}
-}
-
-/// [`std::error::Error::source()`] returns the [`nix::Error`] that caused this error.
-impl StdError for OpenptError
-{
-	fn source(&self) -> Option<&(dyn StdError + 'static)>
-	{
-		// We can actually reconstruct the source error trivially,
-		// so we don't even need to store it.
-		let nix_error: &'static Errno = self.as_errno();
-
-		Some(nix_error)
-	}
-}
-
-impl From<Errno> for OpenptError
-{
-	fn from(other: Errno) -> OpenptError
-	{
-		OpenptError::from_errno(other)
-	}
-}
-
-impl From<OpenptError> for Errno
-{
-	fn from(other: OpenptError) -> Errno
-	{
-		OpenptError::to_errno(other)
-	}
-}
+impl_errno_error!(OpenptError, "posix_openpt()");
@@ -0,0 +1,174 @@
+//! Parsing what a PTY controller reads in packet mode, from
+//! [`set_packet_mode()`](super::set_packet_mode).
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+
+use crate::{Data, DataBuf};
+
+// From `ioctl_tty(2)`. libc doesn't have these for every platform.
+const TIOCPKT_DATA: u8 = 0x00;
+const TIOCPKT_FLUSHREAD: u8 = 0x01;
+const TIOCPKT_FLUSHWRITE: u8 = 0x02;
+const TIOCPKT_STOP: u8 = 0x04;
+const TIOCPKT_START: u8 = 0x08;
+const TIOCPKT_NOSTOP: u8 = 0x10;
+const TIOCPKT_DOSTOP: u8 = 0x20;
+
+/// The state changes a control packet reports, which can be several at once.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketControl(u8);
+
+impl PacketControl
+{
+	pub const fn from_bits(bits: u8) -> Self
+	{
+		Self(bits)
+	}
+
+	pub const fn bits(self) -> u8
+	{
+		self.0
+	}
+
+	/// The terminal child's input queue was flushed, like by `tcflush(TCIFLUSH)`.
+	pub const fn flushed_read(self) -> bool
+	{
+		self.0 & TIOCPKT_FLUSHREAD != 0
+	}
+
+	/// The terminal child's output queue was flushed, like by `tcflush(TCOFLUSH)`.
+	pub const fn flushed_write(self) -> bool
+	{
+		self.0 & TIOCPKT_FLUSHWRITE != 0
+	}
+
+	/// Output was stopped, like by `^S`.
+	pub const fn stopped(self) -> bool
+	{
+		self.0 & TIOCPKT_STOP != 0
+	}
+
+	/// Output was started again, like by `^Q`.
+	pub const fn started(self) -> bool
+	{
+		self.0 & TIOCPKT_START != 0
+	}
+
+	/// `Some(true)` if software flow control was just turned on, with `^S` and `^Q` as its
+	/// characters, `Some(false)` if it was turned off or they were changed, and `None` if this
+	/// isn't about flow control.
+	///
+	/// This is what `rlogin` uses to decide whether it can handle `^S` and `^Q` locally.
+	pub const fn flow_control(self) -> Option<bool>
+	{
+		if self.0 & TIOCPKT_DOSTOP != 0 {
+			Some(true)
+		} else if self.0 & TIOCPKT_NOSTOP != 0 {
+			Some(false)
+		} else {
+			None
+		}
+	}
+}
+
+/// One read from a PTY controller in packet mode.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Packet
+{
+	/// Output from the child, like a read in normal mode would have gotten.
+	Data(DataBuf),
+	/// A change in the terminal child's state.
+	Control(PacketControl),
+}
+
+impl Packet
+{
+	/// Parse what one `read()` from a PTY controller in packet mode got, which starts with a
+	/// control byte.
+	///
+	/// That's one packet, or none if the read got nothing at all. A control byte shouldn't come
+	/// with any data, but if it does, that data follows the control packet as a data packet of
+	/// its own, rather than getting lost.
+	///
+	/// Each read starts with its own control byte, so this must not be given several reads' worth
+	/// at once, like from [`NonblockingRead::read_until_block()`](crate::poller::NonblockingRead).
+	pub fn parse(read: &Data) -> Vec<Packet>
+	{
+		let Some((&control, data)) = read.split_first() else {
+			return Vec::new();
+		};
+		if control == TIOCPKT_DATA {
+			return vec![Packet::Data(data.to_vec())];
+		}
+
+		let mut packets = vec![Packet::Control(PacketControl::from_bits(control))];
+		if !data.is_empty() {
+			warn!("control packet {control:#04x} came with {} bytes of data", data.len());
+			packets.push(Packet::Data(data.to_vec()));
+		}
+
+		packets
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn empty_read()
+	{
+		assert_eq!(Packet::parse(b""), []);
+	}
+
+	#[test]
+	fn data()
+	{
+		assert_eq!(Packet::parse(b"\0data"), [Packet::Data(b"data".to_vec())]);
+		// Even an empty one.
+		assert_eq!(Packet::parse(b"\0"), [Packet::Data(DataBuf::new())]);
+	}
+
+	#[test]
+	fn control()
+	{
+		let packets = Packet::parse(&[TIOCPKT_FLUSHREAD | TIOCPKT_DOSTOP]);
+		let [Packet::Control(control)] = packets[..] else {
+			panic!("expected one control packet, got {packets:?}");
+		};
+		assert!(control.flushed_read());
+		assert!(!control.flushed_write());
+		assert!(!control.stopped() && !control.started());
+		assert_eq!(control.flow_control(), Some(true));
+		assert_eq!(control.bits(), TIOCPKT_FLUSHREAD | TIOCPKT_DOSTOP);
+	}
+
+	#[test]
+	fn control_flags()
+	{
+		let control = PacketControl::from_bits(TIOCPKT_FLUSHWRITE | TIOCPKT_STOP);
+		assert!(control.flushed_write() && control.stopped());
+		assert!(!control.flushed_read() && !control.started());
+		assert_eq!(control.flow_control(), None);
+
+		let control = PacketControl::from_bits(TIOCPKT_START | TIOCPKT_NOSTOP);
+		assert!(control.started() && !control.stopped());
+		assert_eq!(control.flow_control(), Some(false));
+	}
+
+	#[test]
+	fn control_with_data()
+	{
+		let packets = Packet::parse(b"\x04stray");
+		assert_eq!(packets, [
+			Packet::Control(PacketControl::from_bits(TIOCPKT_STOP)),
+			Packet::Data(b"stray".to_vec()),
+		]);
+	}
+}
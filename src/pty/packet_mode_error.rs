@@ -0,0 +1,67 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`set_packet_mode()`](super::set_packet_mode), which contains variants for
+/// all error codes that can be returned by `ioctl(TIOCPKT)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PacketModeError
+{
+	/// The file descriptor is not a pseudo-terminal controller.
+	#[diagnostic(
+		code(floatty::packet_mode::not_a_pty),
+		help("this should be a file descriptor from `posix_openpt()`"),
+	)]
+	NotAPty,
+	/// An error code that `ioctl(TIOCPKT)` isn't documented to return.
+	#[diagnostic(code(floatty::packet_mode::other))]
+	Other(OtherErrno),
+}
+
+impl PacketModeError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use PacketModeError::*;
+		let packet_error = match raw {
+			ENOTTY => NotAPty,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(packet_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use PacketModeError::*;
+		match self {
+			NotAPty => ENOTTY,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		use PacketModeError::*;
+		match self {
+			NotAPty => "The file descriptor is not a pseudoterminal master",
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(PacketModeError, "ioctl(TIOCPKT)");
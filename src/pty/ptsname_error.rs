@@ -1,6 +1,3 @@
-use std::error::Error as StdError;
-use std::fmt::{Display, Result as FmtResult, Formatter};
-
 #[allow(unused_imports)]
 use {
     log::{trace, debug, info, warn, error},
@@ -11,16 +8,26 @@ use {
 	nix::errno::Errno,
 };
 
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
 /// The error type for [`openpt()`], which contains variants for all error codes that can be
 /// returned by `posix_openpt(3p)`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 // We're not repeating the enum name;
 // it just so happens that all possible errors are an "exhausted" variant.
 #[allow(clippy::enum_variant_names)]
 pub enum PtsnameError
 {
 	/// The file descriptor is not a pseudo-terminal "master".
+	#[diagnostic(
+		code(floatty::ptsname::not_a_pty),
+		help("this should be a file descriptor from `posix_openpt()`"),
+	)]
 	NotAPty,
+	/// An error code that `ptsname()` isn't documented to return.
+	#[diagnostic(code(floatty::ptsname::other))]
+	Other(OtherErrno),
 }
 
 impl PtsnameError
@@ -39,81 +46,26 @@ impl PtsnameError
 		Some(ptsname_error)
 	}
 
-	pub fn from_errno(raw: Errno) -> Self
-	{
-		match Self::try_from_raw(raw) {
-			Some(err) => err,
-			None => {
-				panic!("ptsname() gave supposedly impossible error code {raw}");
-			},
-		}
-	}
-
 	pub const fn to_errno(self) -> Errno
 	{
 		use Errno::*;
 		use PtsnameError::*;
 		match self {
 			NotAPty => ENOTTY,
-		}
-	}
-
-	pub const fn as_errno(self) -> &'static Errno
-	{
-		use Errno::*;
-		use PtsnameError::*;
-		match self {
-			NotAPty => &ENOTTY,
+			Other(other) => other.errno(),
 		}
 	}
 
 	/// Not to be confused with [`std::error::Error::description()`].
-	pub const fn desc(self) -> &'static str
+	pub fn desc(self) -> &'static str
 	{
 		// Descriptions from `posix_openpt(3p)`.
 		use PtsnameError::*;
 		match self {
 			NotAPty => "The filedes argument is not associated with a master pseudo-terminal device",
+			Other(other) => other.errno().desc(),
 		}
 	}
 }
 
-impl Display for PtsnameError
-{
-	fn fmt(&self, f: &mut Formatter) -> FmtResult
-	{
-		let description: &'static str = self.desc();
-		f.write_str(description)?;
-
-		Ok(())
-	}
-}
-
-/// [`std::error::Error::source()`] returns the [`nix::Error`] that caused this error.
-impl StdError for PtsnameError
-{
-	fn source(&self) -> Option<&(dyn StdError + 'static)>
-	{
-		// We can actually reconstruct the source error trivially,
-		// so we don't even need to store it.
-		let nix_error: &'static Errno = self.as_errno();
-
-		Some(nix_error)
-	}
-}
-
-impl From<Errno> for PtsnameError
-{
-	fn from(other: Errno) -> PtsnameError
-	{
-		PtsnameError::from_errno(other)
-	}
-}
-
-impl From<PtsnameError> for Errno
-{
-	fn from(other: PtsnameError) -> Errno
-	{
-		PtsnameError::to_errno(other)
-	}
-}
+impl_errno_error!(PtsnameError, "ptsname()");
@@ -0,0 +1,67 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`pty_number()`](super::pty_number), which contains variants for all error
+/// codes that can be returned by `ioctl(TIOCGPTN)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PtyNumberError
+{
+	/// The file descriptor is not a pseudo-terminal controller.
+	#[diagnostic(
+		code(floatty::pty_number::not_a_pty),
+		help("this should be a file descriptor from `posix_openpt()`"),
+	)]
+	NotAPty,
+	/// An error code that `ioctl(TIOCGPTN)` isn't documented to return.
+	#[diagnostic(code(floatty::pty_number::other))]
+	Other(OtherErrno),
+}
+
+impl PtyNumberError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use PtyNumberError::*;
+		let pty_number_error = match raw {
+			ENOTTY => NotAPty,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(pty_number_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use PtyNumberError::*;
+		match self {
+			NotAPty => ENOTTY,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		use PtyNumberError::*;
+		match self {
+			NotAPty => "The file descriptor is not a pseudoterminal master",
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(PtyNumberError, "ioctl(TIOCGPTN)");
@@ -0,0 +1,114 @@
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use miette::Diagnostic;
+
+use super::{GrantptError, OpenPeerError, OpenptError, PtsnameError, PtyPeerError, UnlockptError};
+
+/// The error type for [`open_pty_pair()`](super::open_pty_pair), which is whichever step of
+/// opening the pseudo-terminal failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PtyPairError
+{
+	#[diagnostic(transparent)]
+	Openpt(OpenptError),
+	#[diagnostic(transparent)]
+	Grantpt(GrantptError),
+	#[diagnostic(transparent)]
+	Unlockpt(UnlockptError),
+	#[diagnostic(transparent)]
+	PtyPeer(PtyPeerError),
+	/// Only on the fallback path for when `TIOCGPTPEER` is unsupported.
+	#[diagnostic(transparent)]
+	Ptsname(PtsnameError),
+	/// Opening the terminal child by its path, when `TIOCGPTPEER` is unsupported.
+	#[diagnostic(transparent)]
+	OpenPeer(OpenPeerError),
+}
+
+impl Display for PtyPairError
+{
+	fn fmt(&self, f: &mut Formatter) -> FmtResult
+	{
+		use PtyPairError::*;
+		let step = match self {
+			Openpt(_) => "opening pseudo-terminal controller",
+			Grantpt(_) => "granting access to terminal child",
+			Unlockpt(_) => "unlocking terminal child",
+			PtyPeer(_) => "opening terminal child with TIOCGPTPEER",
+			Ptsname(_) => "getting path of terminal child",
+			OpenPeer(_) => "opening terminal child by path",
+		};
+		f.write_str(step)
+	}
+}
+
+impl StdError for PtyPairError
+{
+	fn source(&self) -> Option<&(dyn StdError + 'static)>
+	{
+		use PtyPairError::*;
+		match self {
+			Openpt(e) => Some(e),
+			Grantpt(e) => Some(e),
+			Unlockpt(e) => Some(e),
+			PtyPeer(e) => Some(e),
+			Ptsname(e) => Some(e),
+			OpenPeer(e) => Some(e),
+		}
+	}
+}
+
+impl From<OpenptError> for PtyPairError
+{
+	fn from(other: OpenptError) -> Self
+	{
+		PtyPairError::Openpt(other)
+	}
+}
+
+impl From<GrantptError> for PtyPairError
+{
+	fn from(other: GrantptError) -> Self
+	{
+		PtyPairError::Grantpt(other)
+	}
+}
+
+impl From<UnlockptError> for PtyPairError
+{
+	fn from(other: UnlockptError) -> Self
+	{
+		PtyPairError::Unlockpt(other)
+	}
+}
+
+impl From<PtyPeerError> for PtyPairError
+{
+	fn from(other: PtyPeerError) -> Self
+	{
+		PtyPairError::PtyPeer(other)
+	}
+}
+
+impl From<PtsnameError> for PtyPairError
+{
+	fn from(other: PtsnameError) -> Self
+	{
+		PtyPairError::Ptsname(other)
+	}
+}
+
+impl From<OpenPeerError> for PtyPairError
+{
+	fn from(other: OpenPeerError) -> Self
+	{
+		PtyPairError::OpenPeer(other)
+	}
+}
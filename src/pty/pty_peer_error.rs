@@ -0,0 +1,114 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`pty_peer()`], which contains variants for all error codes that can be
+/// returned by `ioctl(TIOCGPTPEER)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PtyPeerError
+{
+	/// The kernel does not support `TIOCGPTPEER`, or the flags are invalid.
+	#[diagnostic(
+		code(floatty::pty_peer::unsupported),
+		help("TIOCGPTPEER needs Linux 4.13 or newer; open the path from `ptsname()` instead"),
+	)]
+	Unsupported,
+	/// The file descriptor is not a pseudo-terminal controller.
+	#[diagnostic(
+		code(floatty::pty_peer::not_a_pty),
+		help("this should be a file descriptor from `posix_openpt()`"),
+	)]
+	NotAPty,
+	/// The pseudo-terminal has not been unlocked with [`unlockpt()`](crate::pty::unlockpt).
+	#[diagnostic(
+		code(floatty::pty_peer::locked),
+		help("call `unlockpt()` on the pseudo-terminal first"),
+	)]
+	Locked,
+	/// All file descriptors available to the process are currently open.
+	#[diagnostic(
+		code(floatty::pty_peer::exhausted_file_descriptors),
+		help("close some file descriptors, or raise the limit with `ulimit -n`"),
+	)]
+	ExhaustedFileDescriptors,
+	/// The maximum allowable number of files is currently open in the system.
+	#[diagnostic(
+		code(floatty::pty_peer::exhausted_files),
+		help("close some files, or raise the system limit in /proc/sys/fs/file-max"),
+	)]
+	ExhaustedFiles,
+	/// An error code that `ioctl(TIOCGPTPEER)` isn't documented to return.
+	#[diagnostic(code(floatty::pty_peer::other))]
+	Other(OtherErrno),
+}
+
+impl PtyPeerError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use PtyPeerError::*;
+		let pty_peer_error = match raw {
+			EINVAL => Unsupported,
+			ENOTTY => NotAPty,
+			EIO => Locked,
+			EMFILE => ExhaustedFileDescriptors,
+			ENFILE => ExhaustedFiles,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(pty_peer_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use PtyPeerError::*;
+		match self {
+			Unsupported => EINVAL,
+			NotAPty => ENOTTY,
+			Locked => EIO,
+			ExhaustedFileDescriptors => EMFILE,
+			ExhaustedFiles => ENFILE,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions from `TIOCGPTPEER(2const)` and `open(2)`.
+		use PtyPeerError::*;
+		match self {
+			Unsupported => {
+				"`TIOCGPTPEER` is not supported, or `flags` is invalid"
+			},
+			NotAPty => {
+				"`fd` is not a pseudo-terminal controller"
+			},
+			Locked => {
+				"The pseudo-terminal is still locked"
+			},
+			ExhaustedFileDescriptors => {
+				"The per-process limit on the number of open file descriptors has been reached"
+			},
+			ExhaustedFiles => {
+				"The system-wide limit on the total number of open files has been reached"
+			},
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(PtyPeerError, "ioctl(TIOCGPTPEER)");
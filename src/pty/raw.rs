@@ -0,0 +1,60 @@
+use std::os::fd::{AsRawFd, BorrowedFd};
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use nix::sys::termios::SetArg;
+
+use super::{get_termios, set_termios, TermiosError};
+
+/// Guard that keeps a terminal in raw mode, restoring its original attributes when dropped.
+///
+/// Since restoring happens in [`Drop`], the terminal is restored even if we unwind from a panic.
+#[derive(Debug)]
+pub struct RawMode<'fd>
+{
+	fd: BorrowedFd<'fd>,
+	original: libc::termios,
+}
+
+impl<'fd> RawMode<'fd>
+{
+	/// Save the current attributes of `fd`, and then apply `cfmakeraw(3)` to it.
+	pub fn enable(fd: BorrowedFd<'fd>) -> Result<Self, TermiosError>
+	{
+		let original: libc::termios = get_termios(fd)?;
+
+		let mut raw = original;
+		// SAFETY: `raw` is a valid, initialized termios struct.
+		unsafe { libc::cfmakeraw(&raw mut raw) };
+
+		set_termios(fd, SetArg::TCSANOW, &raw)?;
+		debug!("put fd {} into raw mode", fd.as_raw_fd());
+
+		Ok(Self { fd, original })
+	}
+
+	/// The terminal attributes from before raw mode was enabled.
+	pub fn original(&self) -> &libc::termios
+	{
+		&self.original
+	}
+}
+
+impl Drop for RawMode<'_>
+{
+	fn drop(&mut self)
+	{
+		match set_termios(self.fd, SetArg::TCSADRAIN, &self.original) {
+			Ok(()) => {
+				debug!("restored fd {} from raw mode", self.fd.as_raw_fd());
+			},
+			Err(e) => {
+				// Not much else we can do in a destructor.
+				error!("error restoring fd {} from raw mode: {e}", self.fd.as_raw_fd());
+			},
+		}
+	}
+}
@@ -0,0 +1,77 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`send_tty_signal()`](super::send_tty_signal), which contains variants for
+/// all error codes that can be returned by `ioctl(TIOCSIG)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SendTtySignalError
+{
+	/// The signal number is not a valid signal.
+	#[diagnostic(
+		code(floatty::send_tty_signal::invalid_signal),
+		help("signal numbers must be between 1 and the highest signal the system supports"),
+	)]
+	InvalidSignal,
+	/// The file descriptor is not a pseudo-terminal controller.
+	#[diagnostic(
+		code(floatty::send_tty_signal::not_a_pty),
+		help("this should be a file descriptor from `posix_openpt()`"),
+	)]
+	NotAPty,
+	/// An error code that `ioctl(TIOCSIG)` isn't documented to return.
+	#[diagnostic(code(floatty::send_tty_signal::other))]
+	Other(OtherErrno),
+}
+
+impl SendTtySignalError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use SendTtySignalError::*;
+		let signal_error = match raw {
+			EINVAL => InvalidSignal,
+			ENOTTY => NotAPty,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(signal_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use SendTtySignalError::*;
+		match self {
+			InvalidSignal => EINVAL,
+			NotAPty => ENOTTY,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions adapted from `ioctl_tty(2)`.
+		use SendTtySignalError::*;
+		match self {
+			InvalidSignal => "The signal number is not a valid signal",
+			NotAPty => "The file descriptor is not a pseudoterminal master",
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(SendTtySignalError, "ioctl(TIOCSIG)");
@@ -0,0 +1,97 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`set_foreground_pgrp()`], which contains variants for all error codes that
+/// can be returned by `ioctl(TIOCSPGRP)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SetForegroundPgrpError
+{
+	/// The process group ID is not a supported value.
+	#[diagnostic(
+		code(floatty::set_foreground_pgrp::invalid_process_group),
+		help("process group IDs must be positive"),
+	)]
+	InvalidProcessGroup,
+	/// The file descriptor is not a terminal, or is not the controlling terminal of the
+	/// calling process.
+	#[diagnostic(
+		code(floatty::set_foreground_pgrp::not_a_tty),
+		help("this only works on the controlling terminal of the calling process"),
+	)]
+	NotATty,
+	/// The process group ID is valid, but does not match the process group of a process in the
+	/// same session as the calling process.
+	#[diagnostic(
+		code(floatty::set_foreground_pgrp::permission_denied),
+		help("the process group must be in the same session as the calling process"),
+	)]
+	PermissionDenied,
+	/// An error code that `ioctl(TIOCSPGRP)` isn't documented to return.
+	#[diagnostic(code(floatty::set_foreground_pgrp::other))]
+	Other(OtherErrno),
+}
+
+impl SetForegroundPgrpError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use SetForegroundPgrpError::*;
+		let set_pgrp_error = match raw {
+			EINVAL => InvalidProcessGroup,
+			ENOTTY => NotATty,
+			EPERM => PermissionDenied,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(set_pgrp_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use SetForegroundPgrpError::*;
+		match self {
+			InvalidProcessGroup => EINVAL,
+			NotATty => ENOTTY,
+			PermissionDenied => EPERM,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions from `tcsetpgrp(3p)`.
+		use SetForegroundPgrpError::*;
+		match self {
+			InvalidProcessGroup => {
+				"This implementation does not support the value in the argument `pgid_id`"
+			},
+			NotATty => {
+				"The calling process does not have a controlling terminal, \
+				or the file is not the controlling terminal"
+			},
+			PermissionDenied => {
+				"The value of `pgid_id` is a value supported by the implementation, \
+				but does not match the process group ID of a process in the same session \
+				as the calling process"
+			},
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(SetForegroundPgrpError, "ioctl(TIOCSPGRP)");
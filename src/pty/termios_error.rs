@@ -0,0 +1,117 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`get_termios()`] and [`set_termios()`], which contains variants for all
+/// error codes that can be returned by `tcgetattr(3p)` and `tcsetattr(3p)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TermiosError
+{
+	/// The file descriptor is not valid.
+	#[diagnostic(
+		code(floatty::termios::bad_file_descriptor),
+		help("the file descriptor may have already been closed"),
+	)]
+	BadFileDescriptor,
+	/// A signal interrupted `tcsetattr()`.
+	#[diagnostic(
+		code(floatty::termios::interrupted),
+		help("this is usually safe to retry"),
+	)]
+	Interrupted,
+	/// The optional actions or the attributes themselves are invalid.
+	#[diagnostic(
+		code(floatty::termios::invalid_argument),
+		help("this is probably a bug in floatty"),
+	)]
+	InvalidArgument,
+	/// The calling process is in a background process group that is ignoring `SIGTTOU`.
+	#[diagnostic(
+		code(floatty::termios::background_process_group),
+		help("bring this process to the foreground, with `fg` in most shells"),
+	)]
+	BackgroundProcessGroup,
+	/// The file descriptor is not a terminal.
+	#[diagnostic(
+		code(floatty::termios::not_a_tty),
+		help("this only works on terminals, not pipes or regular files"),
+	)]
+	NotATty,
+	/// An error code that `tcgetattr() or tcsetattr()` isn't documented to return.
+	#[diagnostic(code(floatty::termios::other))]
+	Other(OtherErrno),
+}
+
+impl TermiosError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use TermiosError::*;
+		let termios_error = match raw {
+			EBADF => BadFileDescriptor,
+			EINTR => Interrupted,
+			EINVAL => InvalidArgument,
+			EIO => BackgroundProcessGroup,
+			ENOTTY => NotATty,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(termios_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use TermiosError::*;
+		match self {
+			BadFileDescriptor => EBADF,
+			Interrupted => EINTR,
+			InvalidArgument => EINVAL,
+			BackgroundProcessGroup => EIO,
+			NotATty => ENOTTY,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions from `tcgetattr(3p)` and `tcsetattr(3p)`.
+		use TermiosError::*;
+		match self {
+			BadFileDescriptor => {
+				"The fildes argument is not a valid file descriptor"
+			},
+			Interrupted => {
+				"A signal interrupted tcsetattr()"
+			},
+			InvalidArgument => {
+				"The optional_actions argument is not a supported value, \
+				or an attempt was made to change an attribute represented in the termios \
+				structure to an unsupported value"
+			},
+			BackgroundProcessGroup => {
+				"The process group of the writing process is orphaned, the calling thread \
+				is not blocking SIGTTOU, and the process is not ignoring SIGTTOU"
+			},
+			NotATty => {
+				"The file associated with fildes is not a terminal"
+			},
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(TermiosError, "tcgetattr() or tcsetattr()");
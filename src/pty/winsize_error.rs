@@ -0,0 +1,92 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for [`getwinsz()`] and [`setwinsz()`], which contains variants for all error
+/// codes that can be returned by `ioctl(TIOCGWINSZ)` and `ioctl(TIOCSWINSZ)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WinsizeError
+{
+	/// The file descriptor is not valid.
+	#[diagnostic(
+		code(floatty::winsize::bad_file_descriptor),
+		help("the file descriptor may have already been closed"),
+	)]
+	BadFileDescriptor,
+	/// The window size request is not valid for this file descriptor.
+	#[diagnostic(
+		code(floatty::winsize::invalid_request),
+		help("this is probably a bug in floatty"),
+	)]
+	InvalidRequest,
+	/// The file descriptor is not a terminal.
+	#[diagnostic(
+		code(floatty::winsize::not_a_tty),
+		help("this only works on terminals, not pipes or regular files"),
+	)]
+	NotATty,
+	/// An error code that `ioctl(TIOCGWINSZ or TIOCSWINSZ)` isn't documented to return.
+	#[diagnostic(code(floatty::winsize::other))]
+	Other(OtherErrno),
+}
+
+impl WinsizeError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use WinsizeError::*;
+		let winsize_error = match raw {
+			EBADF => BadFileDescriptor,
+			EINVAL => InvalidRequest,
+			ENOTTY => NotATty,
+			_ => {
+				return None;
+			},
+		};
+
+		Some(winsize_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use WinsizeError::*;
+		match self {
+			BadFileDescriptor => EBADF,
+			InvalidRequest => EINVAL,
+			NotATty => ENOTTY,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions from `ioctl(2)`.
+		use WinsizeError::*;
+		match self {
+			BadFileDescriptor => {
+				"`fd` is not a valid file descriptor"
+			},
+			InvalidRequest => {
+				"`op` or `argp` is not valid"
+			},
+			NotATty => {
+				"`fd` is not associated with a character special device"
+			},
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(WinsizeError, "ioctl(TIOCGWINSZ or TIOCSWINSZ)");
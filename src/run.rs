@@ -0,0 +1,60 @@
+//! Running a program under a PTY from start to finish.
+
+use std::ffi::OsStr;
+use std::os::fd::OwnedFd;
+use std::path::Path;
+
+use nix::unistd::Pid;
+
+use crate::parent::{ChildOutcome, LoopOptions, Recording};
+use crate::pty::OpenptControl;
+use crate::session::Session;
+
+/// Run `prog` with `args` in a new PTY, forwarding our stdio to and from it, and return
+/// how it terminated once it's done.
+///
+/// [`ChildOutcome`] converts into an [`ExitStatus`](std::process::ExitStatus) or an
+/// [`ExitCode`](std::process::ExitCode), for callers who only need those.
+///
+/// This forks, so like [`fork()`](nix::unistd::fork) it must not be called while
+/// this process has other threads running. See [`Session`] for more control.
+pub fn run(prog: &Path, args: &[impl AsRef<OsStr>]) -> miette::Result<ChildOutcome>
+{
+	run_recorded(prog, args, None)
+}
+
+/// Like [`run()`], but also records the child's output to `log`, if given.
+pub fn run_recorded(
+	prog: &Path,
+	args: &[impl AsRef<OsStr>],
+	log: Option<Recording>,
+) -> miette::Result<ChildOutcome>
+{
+	let (child, pty) = spawn(prog, args, OpenptControl::BecomeControllingTerminal)?;
+
+	crate::parent::parent_process(child, pty, log, None, None, LoopOptions::default())
+}
+
+/// Start `prog` with `args` in a new PTY, and return the child and the controller side of its
+/// PTY, without forwarding anything, for callers who want to run their own loop.
+///
+/// The PTY controller is `O_NONBLOCK`. The caller is responsible for reaping the child with
+/// `waitpid()` once it exits, or it will linger as a zombie until we exit. Like
+/// [`Session::spawn()`], this forks, and so must not be called while this process has other
+/// threads running.
+pub fn spawn(
+	prog: &Path,
+	args: &[impl AsRef<OsStr>],
+	control: OpenptControl,
+) -> miette::Result<(Pid, OwnedFd)>
+{
+	let controlling = control == OpenptControl::BecomeControllingTerminal;
+	let running = Session::new(prog)
+		.args(args)
+		.controlling(controlling)
+		.spawn()?;
+
+	let (pty, child) = running.into_parts();
+
+	Ok((child, OwnedFd::from(pty)))
+}
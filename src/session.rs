@@ -0,0 +1,525 @@
+//! A [`Command`]-like builder for running programs under a PTY.
+
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+#[allow(unused_imports)]
+use {
+	bstr::{BStr, BString, ByteSlice, ByteVec},
+	bytes::{BufMut, Bytes},
+	log::{trace, debug, info, warn, error},
+	miette::{Context as _, Diagnostic, Error, IntoDiagnostic},
+	nix::{errno::Errno, fcntl::OFlag, unistd::{ForkResult, Pid}},
+	tap::prelude::*,
+};
+
+use crate::child::{ExecError, StdioConfig, SETUP_FAILED_CODE};
+use crate::fdops::FdOps;
+use crate::parent::{
+	CaptureLimit,
+	Captured,
+	ChildOutcome,
+	FlushPolicy,
+	LoopOptions,
+	OutputFilter,
+	Recording,
+	ResizeHook,
+};
+use crate::pty::{
+	open_pty_pair, getwinsz, set_echo, set_flow_control, setwinsz,
+	OpenptControl, PtyMaster, WinSize, WinsizeError, DEFAULT_WINSIZE,
+};
+
+mod fork_error;
+pub use fork_error::ForkError;
+
+/// Our window size according to `COLUMNS` and `LINES`, the way shells export it.
+/// Whichever of those is missing or invalid comes from `fallback` instead.
+fn size_from_env(fallback: libc::winsize) -> libc::winsize
+{
+	let dimension = |var: &str| -> Option<u16> {
+		let value = env::var_os(var)?;
+		let parsed = value.to_str()
+			.and_then(|value| value.parse::<u16>().ok())
+			.filter(|&value| value != 0);
+
+		if parsed.is_none() {
+			debug!("ignoring invalid {var}={}", value.display());
+		}
+
+		parsed
+	};
+
+	libc::winsize {
+		ws_col: dimension("COLUMNS").unwrap_or(fallback.ws_col),
+		ws_row: dimension("LINES").unwrap_or(fallback.ws_row),
+		..fallback
+	}
+}
+
+/// Builder for a program to run under a new PTY, in the style of [`Command`].
+///
+/// The program, its arguments, environment, and working directory are handled by
+/// an inner [`Command`], so they behave exactly as they do there, except that a child
+/// which would have no `TERM` gets [`DEFAULT_TERM`](crate::child::DEFAULT_TERM).
+#[derive(Debug)]
+pub struct Session
+{
+	command: Command,
+	/// `None` means "whatever size our stdin is".
+	window_size: Option<libc::winsize>,
+	/// For when `window_size` is `None` and our stdin isn't a terminal.
+	fallback_size: libc::winsize,
+	/// Overrides the pixel dimensions of whichever size the PTY starts with.
+	pixel_size: Option<(u16, u16)>,
+	controlling: bool,
+	/// `None` means the line discipline's default, which echoes.
+	echo: Option<bool>,
+	/// `None` means the line discipline's default, which has flow control on.
+	flow_control: Option<bool>,
+	stdio: StdioConfig,
+	/// Cleared by [`Session::env_clear()`].
+	inherits_env: bool,
+	timeout: Option<Duration>,
+	idle_timeout: Option<Duration>,
+	flush: Option<FlushPolicy>,
+	stream: bool,
+	capture_limit: Option<CaptureLimit>,
+	/// Taken by the next [`Session::spawn()`].
+	filter: Option<OutputFilter>,
+}
+
+impl Session
+{
+	pub fn new(prog: impl AsRef<OsStr>) -> Self
+	{
+		Self {
+			command: Command::new(prog),
+			window_size: None,
+			fallback_size: DEFAULT_WINSIZE,
+			pixel_size: None,
+			controlling: true,
+			echo: None,
+			flow_control: None,
+			stdio: StdioConfig::default(),
+			inherits_env: true,
+			timeout: None,
+			idle_timeout: None,
+			flush: None,
+			stream: true,
+			capture_limit: None,
+			filter: None,
+		}
+	}
+
+	pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self
+	{
+		self.command.arg(arg);
+		self
+	}
+
+	pub fn args<I, S>(&mut self, args: I) -> &mut Self
+	where
+		I: IntoIterator<Item = S>,
+		S: AsRef<OsStr>,
+	{
+		self.command.args(args);
+		self
+	}
+
+	pub fn env(&mut self, key: impl AsRef<OsStr>, val: impl AsRef<OsStr>) -> &mut Self
+	{
+		self.command.env(key, val);
+		self
+	}
+
+	pub fn env_remove(&mut self, key: impl AsRef<OsStr>) -> &mut Self
+	{
+		self.command.env_remove(key);
+		self
+	}
+
+	/// Don't inherit our environment. `TERM` will still be set to
+	/// [`DEFAULT_TERM`](crate::child::DEFAULT_TERM) unless specified with [`Session::env()`].
+	pub fn env_clear(&mut self) -> &mut Self
+	{
+		self.command.env_clear();
+		self.inherits_env = false;
+		self
+	}
+
+	pub fn current_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self
+	{
+		self.command.current_dir(dir);
+		self
+	}
+
+	/// Run `hook` in the child right before it execs the program, after floatty's own setup,
+	/// like becoming a session leader and connecting stdio to the PTY, is done. Hooks run in
+	/// the order they were added, and an error from any of them is reported like the program
+	/// failing to exec.
+	///
+	/// This is just [`CommandExt::pre_exec()`](std::os::unix::process::CommandExt::pre_exec),
+	/// for things like setting resource limits.
+	///
+	/// # Safety
+	///
+	/// The same as for `pre_exec()`: `hook` runs in a forked child, so it should stick to
+	/// async-signal-safe functions, and in particular not allocate, take locks, or touch anything
+	/// shared with other threads, even though floatty already requires that there are none
+	/// when spawning.
+	pub unsafe fn pre_exec<F>(&mut self, hook: F) -> &mut Self
+	where
+		F: FnMut() -> io::Result<()> + Send + Sync + 'static,
+	{
+		// SAFETY: upheld by our caller.
+		unsafe { self.command.pre_exec(hook) };
+		self
+	}
+
+	/// Set the window size of the PTY, rather than copying it from our stdin.
+	///
+	/// The size then stays fixed, and changes to our own window size are ignored.
+	pub fn window_size(&mut self, size: impl Into<libc::winsize>) -> &mut Self
+	{
+		self.window_size = Some(size.into());
+		self
+	}
+
+	/// Set the initial window size of the PTY for when our stdin isn't a terminal, or is one
+	/// with no size, and `COLUMNS` and `LINES` don't say otherwise. Defaults to [`DEFAULT_WINSIZE`].
+	pub fn default_window_size(&mut self, size: impl Into<libc::winsize>) -> &mut Self
+	{
+		self.fallback_size = size.into();
+		self
+	}
+
+	/// Set the size of the PTY's window in pixels, for programs that draw images in the terminal,
+	/// overriding whatever pixel size it would have had.
+	///
+	/// If the window size follows ours, this is scaled along with it, unless our own terminal
+	/// reports a pixel size, which then takes over.
+	pub fn pixel_size(&mut self, width: u16, height: u16) -> &mut Self
+	{
+		self.pixel_size = Some((width, height));
+		self
+	}
+
+	/// Whether the PTY should become the child's controlling terminal. Defaults to `true`.
+	///
+	/// Without a controlling terminal, the line discipline won't send the child job control
+	/// signals like `SIGINT` from `^C` or `SIGTSTP` from `^Z`, the child won't get `SIGHUP`
+	/// when the PTY is closed, and it can't open `/dev/tty`. Signals *we* receive are
+	/// still forwarded to it either way.
+	pub fn controlling(&mut self, controlling: bool) -> &mut Self
+	{
+		self.controlling = controlling;
+		self
+	}
+
+	/// Whether the PTY echoes input back, before the child even starts. Defaults to whatever
+	/// the system's default is, which is to echo.
+	///
+	/// This is the PTY's line discipline, not our own terminal's, which is in raw mode anyway
+	/// while we forward input. The child can still change it back itself.
+	pub fn echo(&mut self, echo: bool) -> &mut Self
+	{
+		self.echo = Some(echo);
+		self
+	}
+
+	/// Whether `^S` and `^Q` pause and resume the child's output, instead of reaching it as
+	/// input. Defaults to whatever the system's default is, which is to pause and resume.
+	///
+	/// Turning this off is what editors and REPLs that bind `^S` want. Like [`Session::echo()`],
+	/// this is the PTY's line discipline, which the child can still change itself.
+	pub fn flow_control(&mut self, flow_control: bool) -> &mut Self
+	{
+		self.flow_control = Some(flow_control);
+		self
+	}
+
+	/// Whether the child's stdin should be the PTY. Defaults to `true`.
+	///
+	/// Otherwise, the child reads our stdin directly, and we neither forward it nor put our
+	/// terminal into raw mode, while its stdout and stderr are still the PTY.
+	pub fn pty_stdin(&mut self, pty_stdin: bool) -> &mut Self
+	{
+		self.stdio.stdin = pty_stdin;
+		self
+	}
+
+	/// Whether the child's stderr should be the PTY. Defaults to `true`.
+	///
+	/// Otherwise, the child writes to our stderr directly. See
+	/// [`StdioConfig::stderr`] for what that means for the child.
+	pub fn pty_stderr(&mut self, pty_stderr: bool) -> &mut Self
+	{
+		self.stdio.stderr = pty_stderr;
+		self
+	}
+
+	/// Kill the child if it's still running after `timeout`, first with `SIGTERM`, and then with
+	/// `SIGKILL` if it's still running [`KILL_GRACE_PERIOD`](crate::parent::KILL_GRACE_PERIOD)
+	/// after that.
+	pub fn timeout(&mut self, timeout: Duration) -> &mut Self
+	{
+		self.timeout = Some(timeout);
+		self
+	}
+
+	/// Like [`Session::timeout()`], but only counting time since the child last wrote anything.
+	pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self
+	{
+		self.idle_timeout = Some(timeout);
+		self
+	}
+
+	/// When to flush the child's output to our stdout, instead of picking based on whether it's
+	/// a terminal.
+	///
+	/// See [`LoopOptions::flush`].
+	pub fn flush_policy(&mut self, policy: FlushPolicy) -> &mut Self
+	{
+		self.flush = Some(policy);
+		self
+	}
+
+	/// Whether to write the child's output to our stdout, which is on by default.
+	///
+	/// Turning this off is for when the output is only wanted from
+	/// [`RunningSession::wait_and_capture()`], or a recording.
+	pub fn stream(&mut self, stream: bool) -> &mut Self
+	{
+		self.stream = stream;
+		self
+	}
+
+	/// Keep at most `limit` of the child's output in memory for
+	/// [`RunningSession::wait_and_capture()`].
+	///
+	/// See [`LoopOptions::capture_limit`].
+	pub fn capture_limit(&mut self, limit: CaptureLimit) -> &mut Self
+	{
+		self.capture_limit = Some(limit);
+		self
+	}
+
+	/// Pass the child's output through `filter` before it's written to stdout or recorded.
+	///
+	/// Filters are usually stateful, so this only applies to the next [`Session::spawn()`].
+	pub fn output_filter(&mut self, filter: OutputFilter) -> &mut Self
+	{
+		self.filter = Some(filter);
+		self
+	}
+
+	pub fn get_program(&self) -> &OsStr
+	{
+		self.command.get_program()
+	}
+
+	/// Start the program in a new PTY.
+	///
+	/// This forks, so like [`fork()`](nix::unistd::fork) it must not be called while
+	/// this process has other threads running.
+	pub fn spawn(&mut self) -> miette::Result<RunningSession>
+	{
+		let control = OpenptControl::from_noctty(!self.controlling);
+		let (pty_fd, other_side): (OwnedFd, OwnedFd) = open_pty_pair(control)?;
+
+		// Only ours; the child gets `other_side` as its stdio, which must stay blocking, or
+		// programs reading their stdin will get EAGAIN instead of waiting for input.
+		pty_fd.set_nonblocking();
+
+		// Surprisingly, `pty_fd` is NOT a terminal, but this definitely should be.
+		debug_assert!(other_side.is_tty());
+
+		debug!("Got file descriptors {} and {}", pty_fd.as_raw_fd(), other_side.as_raw_fd());
+
+		let mut current_size = match self.window_size {
+			Some(size) => size,
+			None => match getwinsz(io::stdin().as_fd()) {
+				// Some terminals, like ones containers are given, never get a size at all.
+				Ok(size) if size.ws_col == 0 || size.ws_row == 0 => {
+					debug!("stdin is a terminal without a window size; using fallback window size");
+					size_from_env(self.fallback_size)
+				},
+				Ok(size) => size,
+				Err(WinsizeError::NotATty) => {
+					// Perfectly normal if we're being run non-interactively.
+					debug!("stdin is not a terminal; using fallback window size");
+					size_from_env(self.fallback_size)
+				},
+				Err(e) => {
+					return Err(e).context("getting window size of stdin");
+				},
+			},
+		};
+		if let Some((width, height)) = self.pixel_size {
+			current_size.ws_xpixel = width;
+			current_size.ws_ypixel = height;
+		}
+		if let Err(e) = setwinsz(pty_fd.as_fd(), current_size) {
+			warn!("couldn't set initial window size of child PTY: {e}");
+		}
+		if let Some(echo) = self.echo {
+			set_echo(other_side.as_fd(), echo)
+				.context("setting echo of child PTY")?;
+		}
+		if let Some(flow_control) = self.flow_control {
+			set_flow_control(other_side.as_fd(), flow_control)
+				.context("setting flow control of child PTY")?;
+		}
+
+		// Spawn a new process, and then use setsid() and TIOCSCTTY to make this terminal
+		// the controlling terminal for that process, and then spawn the requested command.
+		use ForkResult::*;
+		match unsafe { nix::unistd::fork() } {
+			Ok(Child) => {
+				drop(pty_fd);
+
+				info!("command: {:?}", self.command);
+				let result = crate::child::child_process(
+					&mut self.command,
+					other_side,
+					self.controlling,
+					self.inherits_env,
+					self.stdio,
+				);
+				let code = match result {
+					Ok(()) => SETUP_FAILED_CODE,
+					Err(e) => {
+						eprintln!("floatty: {e:?}");
+						// Let the parent tell a missing program from one it just couldn't run.
+						e.downcast_ref::<ExecError>().map_or(SETUP_FAILED_CODE, ExecError::exit_code)
+					},
+				};
+				// We're a forked copy of the caller; don't return into their code, or run their
+				// exit handlers and flush their copy of stdout's buffer a second time.
+				let _ = io::stderr().flush();
+				unsafe { libc::_exit(code) };
+			},
+			Ok(Parent { child }) => {
+				// Only the child should hold this open, so we can tell when it's been closed.
+				drop(other_side);
+
+				Ok(RunningSession {
+					pty: PtyMaster::from(pty_fd),
+					child,
+					logs: Vec::new(),
+					filter: self.filter.take(),
+					on_resize: None,
+					options: LoopOptions {
+						follow_resize: self.window_size.is_none(),
+						timeout: self.timeout,
+						idle_timeout: self.idle_timeout,
+						flush: self.flush,
+						cell_pixels: self.pixel_size
+							.and_then(|_| WinSize::from(current_size).cell_pixels()),
+						forward_stdin: self.stdio.stdin,
+						stream: self.stream,
+						capture_limit: self.capture_limit,
+					},
+				})
+			},
+			Err(errno) => {
+				Err(ForkError::from_errno(errno))
+					.context("forking a process for the child")
+			},
+		}
+	}
+}
+
+/// A program started by [`Session::spawn()`].
+#[derive(Debug)]
+pub struct RunningSession
+{
+	pty: PtyMaster,
+	child: Pid,
+	logs: Vec<Recording>,
+	filter: Option<OutputFilter>,
+	on_resize: Option<ResizeHook>,
+	/// For [`parent_process()`](crate::parent::parent_process).
+	options: LoopOptions,
+}
+
+impl RunningSession
+{
+	pub fn pid(&self) -> Pid
+	{
+		self.child
+	}
+
+	/// The controller side of the child's PTY.
+	pub fn pty(&self) -> &PtyMaster
+	{
+		&self.pty
+	}
+
+	/// Also record the child's output to `log` once we start forwarding it.
+	///
+	/// This can be called more than once, like for both a plain log and an asciicast.
+	pub fn record(&mut self, log: Recording) -> &mut Self
+	{
+		self.logs.push(log);
+		self
+	}
+
+	/// Call `hook` with the child's new window size whenever we pass a change to ours along to
+	/// it, after its PTY has been resized.
+	///
+	/// It's set here rather than on the [`Session`], so it can hold onto the PTY, like to write a
+	/// redraw key to programs that don't redraw on their own. This only happens when the child
+	/// follows our size, so not with [`Session::window_size()`].
+	pub fn on_resize<F>(&mut self, hook: F) -> &mut Self
+	where
+		F: FnMut(libc::winsize) + 'static,
+	{
+		self.on_resize = Some(ResizeHook::new(hook));
+		self
+	}
+
+	/// Take the PTY and child back, to drive them some other way,
+	/// like [`parent_loop_async()`](crate::parent::parent_loop_async).
+	pub fn into_parts(self) -> (PtyMaster, Pid)
+	{
+		(self.pty, self.child)
+	}
+
+	/// Forward our stdio to and from the child until it exits, and return how it terminated.
+	pub fn wait(self) -> miette::Result<ChildOutcome>
+	{
+		crate::parent::parent_process(
+			self.child,
+			OwnedFd::from(self.pty),
+			self.logs,
+			self.filter,
+			self.on_resize,
+			self.options,
+		)
+	}
+
+	/// Like [`RunningSession::wait()`], but also return everything the child wrote, after any
+	/// [output filter](Session::output_filter).
+	///
+	/// The output still goes to stdout too, unless that's turned off with [`Session::stream()`],
+	/// and the whole thing is kept unless it's limited with [`Session::capture_limit()`].
+	pub fn wait_and_capture(self) -> miette::Result<(ChildOutcome, Captured)>
+	{
+		crate::parent::parent_process_capturing(
+			self.child,
+			OwnedFd::from(self.pty),
+			self.logs,
+			self.filter,
+			self.on_resize,
+			self.options,
+		)
+	}
+}
@@ -0,0 +1,81 @@
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use {
+	miette::Diagnostic,
+	nix::errno::Errno,
+};
+
+use crate::errno_error::{impl_errno_error, OtherErrno};
+
+/// The error type for `fork()` in [`Session::spawn()`](crate::Session::spawn), which contains
+/// variants for all error codes that can be returned by `fork(3p)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Diagnostic)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ForkError
+{
+	/// The system lacked the necessary resources to create another process, or the limit on
+	/// the number of processes for this user would be exceeded.
+	#[diagnostic(
+		code(floatty::fork::exhausted_processes),
+		help("wait for some processes to exit, or raise the limit with `ulimit -u`"),
+	)]
+	ExhaustedProcesses,
+	/// Insufficient storage space is available.
+	#[diagnostic(
+		code(floatty::fork::out_of_memory),
+		help("free up some memory, or check the memory limits on this process"),
+	)]
+	OutOfMemory,
+	/// An error code that `fork()` isn't documented to return.
+	#[diagnostic(code(floatty::fork::other))]
+	Other(OtherErrno),
+}
+
+impl ForkError
+{
+	pub const fn try_from_raw(raw: Errno) -> Option<Self>
+	{
+		use Errno::*;
+		use ForkError::*;
+		let fork_error = match raw {
+			EAGAIN => ExhaustedProcesses,
+			ENOMEM => OutOfMemory,
+			_ => {
+				return None;
+			}
+		};
+
+		Some(fork_error)
+	}
+
+	pub const fn to_errno(self) -> Errno
+	{
+		use Errno::*;
+		use ForkError::*;
+		match self {
+			ExhaustedProcesses => EAGAIN,
+			OutOfMemory => ENOMEM,
+			Other(other) => other.errno(),
+		}
+	}
+
+	/// Not to be confused with [`std::error::Error::description()`].
+	pub fn desc(self) -> &'static str
+	{
+		// Descriptions from `fork(3p)`.
+		use ForkError::*;
+		match self {
+			ExhaustedProcesses => {
+				"The system lacked the necessary resources to create another process, or the \
+				system-imposed limit on the total number of processes would be exceeded"
+			},
+			OutOfMemory => "Insufficient storage space is available",
+			Other(other) => other.errno().desc(),
+		}
+	}
+}
+
+impl_errno_error!(ForkError, "fork()");
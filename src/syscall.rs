@@ -0,0 +1,130 @@
+//! Helpers for calling raw syscalls.
+
+use std::ffi::c_int;
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+use nix::errno::Errno;
+
+/// Call `f`, a raw syscall which returns a negative value and sets `errno` when it fails,
+/// until it doesn't fail with `EINTR`.
+///
+/// Signals are a normal part of a PTY tool's life, and per POSIX, a call interrupted by one
+/// can just be made again.
+pub fn retry_on_eintr<F>(mut f: F) -> Result<c_int, Errno>
+where
+	F: FnMut() -> c_int,
+{
+	loop {
+		let code = f();
+		if code >= 0 {
+			return Ok(code);
+		}
+
+		match Errno::last() {
+			Errno::EINTR => {
+				trace!("syscall interrupted; retrying");
+			},
+			errno => {
+				return Err(errno);
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use std::io::{self, Write};
+	use std::os::fd::AsRawFd;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::thread;
+	use std::time::Duration;
+
+	use nix::errno::Errno;
+	use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+	use super::retry_on_eintr;
+
+	#[test]
+	fn retries_eintr()
+	{
+		let mut calls = 0;
+		let result = retry_on_eintr(|| {
+			calls += 1;
+			if calls <= 3 {
+				Errno::EINTR.set();
+				return -1;
+			}
+			42
+		});
+		assert_eq!(result, Ok(42));
+		assert_eq!(calls, 4);
+	}
+
+	#[test]
+	fn other_errors_returned()
+	{
+		let mut calls = 0;
+		let result = retry_on_eintr(|| {
+			calls += 1;
+			Errno::EBADF.set();
+			-1
+		});
+		assert_eq!(result, Err(Errno::EBADF));
+		assert_eq!(calls, 1);
+	}
+
+	static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+	extern "C" fn note_interrupted(_signal: libc::c_int)
+	{
+		INTERRUPTED.store(true, Ordering::SeqCst);
+	}
+
+	#[test]
+	fn blocking_read_interrupted()
+	{
+		// Without `SA_RESTART`, so the kernel fails the read with `EINTR` instead of restarting
+		// it for us.
+		let action = SigAction::new(
+			SigHandler::Handler(note_interrupted),
+			SaFlags::empty(),
+			SigSet::empty(),
+		);
+		// SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+		unsafe { sigaction(Signal::SIGALRM, &action) }.expect("installing SIGALRM handler");
+
+		let (reader, mut writer) = io::pipe().expect("opening pipe");
+		// SAFETY: `pthread_self()` is always safe to call.
+		let reading_thread = unsafe { libc::pthread_self() };
+		let signaller = thread::spawn(move || {
+			// Give the read time to block, then interrupt it, and only then give it data.
+			thread::sleep(Duration::from_millis(100));
+			// SAFETY: the reading thread outlives this one, since it joins us.
+			unsafe { libc::pthread_kill(reading_thread, libc::SIGALRM) };
+			thread::sleep(Duration::from_millis(100));
+			writer.write_all(b"x").unwrap();
+		});
+
+		let mut byte = [0u8; 1];
+		let mut calls = 0;
+		let result = retry_on_eintr(|| {
+			calls += 1;
+			// SAFETY: `byte` is valid for writes of its length for the whole call.
+			let count = unsafe {
+				libc::read(reader.as_raw_fd(), byte.as_mut_ptr().cast(), byte.len())
+			};
+			count as libc::c_int
+		});
+		signaller.join().unwrap();
+
+		assert_eq!(result, Ok(1));
+		assert_eq!(&byte, b"x");
+		assert!(INTERRUPTED.load(Ordering::SeqCst), "SIGALRM handler never ran");
+		assert!(calls >= 2, "read wasn't interrupted and retried");
+	}
+}
@@ -1,4 +1,8 @@
 use std::ffi::c_char;
+use std::io::{ErrorKind as IoErrorKind, Read, Result as IoResult};
+
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn, error};
 
 /// Like `Path`, but for data!
 pub type Data = [u8];
@@ -9,6 +13,27 @@ pub type DataBuf = Vec<u8>;
 pub trait DataBufExt
 {
     fn zeroed(len: usize) -> Self;
+
+    /// Make this `len` zeroes, like [`DataBufExt::zeroed()`], but reusing its allocation.
+    ///
+    /// If this is already longer than `len`, it's truncated, and never grows its allocation.
+    fn resize_zeroed(&mut self, len: usize);
+
+    /// Read from `reader` until `std::io::ErrorKind::WouldBlock` is returned, `buf_size` bytes
+    /// at a time, appending all data read to this buffer.
+    ///
+    /// Returns how many bytes were appended. If some other error occurs after some data has
+    /// already been read, then that data is kept instead, and the error is left for the next
+    /// read to find.
+    fn fill_until_block<R>(&mut self, reader: &mut R, buf_size: usize) -> IoResult<usize>
+    where
+        R: Read + ?Sized;
+
+    /// Same as [`DataBufExt::fill_until_block()`], but allocation-free: `buffer` is used as
+    /// scratch space for each `read()`.
+    fn fill_until_block_with<R>(&mut self, reader: &mut R, buffer: &mut Data) -> IoResult<usize>
+    where
+        R: Read + ?Sized;
 }
 
 impl DataBufExt for DataBuf
@@ -17,6 +42,60 @@ impl DataBufExt for DataBuf
     {
         vec![0u8; len]
     }
+
+    fn resize_zeroed(&mut self, len: usize)
+    {
+        self.truncate(len);
+        self.fill(0);
+        self.resize(len, 0);
+    }
+
+    fn fill_until_block<R>(&mut self, reader: &mut R, buf_size: usize) -> IoResult<usize>
+    where
+        R: Read + ?Sized,
+    {
+        let mut buffer = DataBuf::zeroed(buf_size);
+        self.fill_until_block_with(reader, &mut buffer)
+    }
+
+    fn fill_until_block_with<R>(&mut self, reader: &mut R, buffer: &mut Data) -> IoResult<usize>
+    where
+        R: Read + ?Sized,
+    {
+        let start_len = self.len();
+
+        loop {
+            match reader.read(buffer) {
+                Ok(0) => {
+                    // No more data at all I guess? Is this necessary?
+                    debug!("nonblocking reader returned 0 bytes; treating it as having blocked");
+                    break;
+                },
+                Ok(count) => {
+                    let read_data = &buffer[0..count];
+                    self.extend_from_slice(read_data);
+                },
+                Err(e) => {
+                    if e.kind() == IoErrorKind::WouldBlock {
+                        // No more data ready right now. We're done here.
+                        break;
+                    }
+                    let read_len = self.len() - start_len;
+                    if read_len > 0 {
+                        // Don't lose what we already have.
+                        debug!("deferring error after {read_len} bytes of non-blocking read: {e}");
+                        break;
+                    }
+                    // Not necessarily an error to the caller (e.g. `EIO` on a PTY), so let them
+                    // decide how loud to be about it.
+                    debug!("error while doing non-blocking read: {e:?}");
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(self.len() - start_len)
+    }
 }
 
 pub trait DataExt
@@ -45,6 +124,11 @@ pub trait VecExt<T>
 
 	/// Same as [`Vec::push()`], but also returns an exclusive reference to the new item.
 	fn push_get_mut(&mut self, item: T) -> &mut T;
+
+	/// Same as [`Vec::extend()`], but also returns a shared reference to the newly added items.
+	fn extend_get<I>(&mut self, items: I) -> &[T]
+	where
+		I: IntoIterator<Item = T>;
 }
 
 impl<T> VecExt<T> for Vec<T>
@@ -60,5 +144,111 @@ impl<T> VecExt<T> for Vec<T>
 		self.push(item);
 		self.last_mut().unwrap()
 	}
+
+	fn extend_get<I>(&mut self, items: I) -> &[T]
+	where
+		I: IntoIterator<Item = T>,
+	{
+		let start = self.len();
+		self.extend(items);
+		&self[start..]
+	}
 }
 
+
+#[cfg(test)]
+mod tests
+{
+	use std::collections::VecDeque;
+	use std::io;
+
+	use super::*;
+	use crate::poller::NonblockingRead;
+
+	/// Hands out each of its results in turn, and then blocks, like a nonblocking fd would.
+	struct Scripted(VecDeque<IoResult<&'static [u8]>>);
+
+	impl Scripted
+	{
+		fn new(results: impl IntoIterator<Item = IoResult<&'static [u8]>>) -> Self
+		{
+			Self(results.into_iter().collect())
+		}
+	}
+
+	impl Read for Scripted
+	{
+		fn read(&mut self, buf: &mut [u8]) -> IoResult<usize>
+		{
+			let data = self.0.pop_front().unwrap_or(Err(IoErrorKind::WouldBlock.into()))?;
+			buf[..data.len()].copy_from_slice(data);
+			Ok(data.len())
+		}
+	}
+
+	#[test]
+	fn resize_zeroed()
+	{
+		let mut buffer: DataBuf = b"leftover data".to_vec();
+		let capacity = buffer.capacity();
+
+		buffer.resize_zeroed(4);
+		assert_eq!(buffer, [0; 4]);
+		assert_eq!(buffer.capacity(), capacity, "shrinking shouldn't reallocate");
+
+		buffer.extend_from_slice(b"more");
+		buffer.resize_zeroed(10);
+		assert_eq!(buffer, [0; 10]);
+	}
+
+	#[test]
+	fn extend_get()
+	{
+		let mut items = vec![1, 2];
+		assert_eq!(items.extend_get([3, 4]), [3, 4]);
+		assert_eq!(items.extend_get([]), [] as [i32; 0]);
+		assert_eq!(items, [1, 2, 3, 4]);
+	}
+
+	#[test]
+	fn fill_until_block()
+	{
+		let mut reader = Scripted::new([Ok(&b"some "[..]), Ok(b"data")]);
+		let mut buffer: DataBuf = b"already ".to_vec();
+		assert_eq!(buffer.fill_until_block(&mut reader, 8).unwrap(), 9);
+		assert_eq!(buffer, b"already some data");
+
+		// Nothing ready at all is just nothing.
+		assert_eq!(buffer.fill_until_block(&mut reader, 8).unwrap(), 0);
+		assert_eq!(buffer, b"already some data");
+	}
+
+	#[test]
+	fn fill_until_block_eof()
+	{
+		// End-of-file counts as blocking, so whatever's after it waits for the next call.
+		let mut reader = Scripted::new([Ok(&b"before"[..]), Ok(b""), Ok(b"after")]);
+		let mut buffer = DataBuf::new();
+		assert_eq!(buffer.fill_until_block(&mut reader, 8).unwrap(), 6);
+		assert_eq!(buffer.fill_until_block(&mut reader, 8).unwrap(), 5);
+		assert_eq!(buffer, b"beforeafter");
+	}
+
+	#[test]
+	fn fill_until_block_error()
+	{
+		let broken = || io::Error::from(IoErrorKind::BrokenPipe);
+
+		// An error with nothing read yet is returned right away.
+		let mut reader = Scripted::new([Err(broken())]);
+		let mut buffer = DataBuf::new();
+		let err = buffer.fill_until_block(&mut reader, 8).unwrap_err();
+		assert_eq!(err.kind(), IoErrorKind::BrokenPipe);
+
+		// But after some data, that data comes first, and the error is left for the next read.
+		let mut reader = Scripted::new([Ok(&b"partial"[..]), Err(broken()), Err(broken())]);
+		assert_eq!(reader.read_until_block().unwrap(), b"partial");
+		let err = reader.read_until_block().unwrap_err();
+		assert_eq!(err.kind(), IoErrorKind::BrokenPipe);
+	}
+}
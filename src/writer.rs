@@ -0,0 +1,131 @@
+//! Writing to `O_NONBLOCK` files without losing data.
+
+use std::fs::File;
+use std::io::{ErrorKind as IoErrorKind, Result as IoResult, Write};
+
+#[allow(unused_imports)]
+use {
+	log::{trace, debug, info, warn, error},
+	tap::prelude::*,
+};
+
+use crate::poller::InterestHandle;
+use crate::{Data, DataBuf};
+
+/// Writer for an `O_NONBLOCK` file which queues whatever can't be written yet, instead of
+/// erroring like [`Write::write_all()`] would.
+///
+/// If given an [`InterestHandle`], it registers write interest while there's queued data,
+/// so [`QueuedWriter::flush_pending()`] can be called when the file becomes writable.
+#[derive(Debug)]
+pub struct QueuedWriter
+{
+	file: File,
+	pending: DataBuf,
+	interest: Option<InterestHandle>,
+}
+
+impl QueuedWriter
+{
+	pub fn new(file: File) -> Self
+	{
+		Self {
+			file,
+			pending: DataBuf::new(),
+			interest: None,
+		}
+	}
+
+	pub fn with_interest(file: File, interest: InterestHandle) -> Self
+	{
+		Self {
+			interest: Some(interest),
+			..Self::new(file)
+		}
+	}
+
+	pub fn file(&self) -> &File
+	{
+		&self.file
+	}
+
+	/// How many bytes are waiting for the file to become writable.
+	pub fn pending_len(&self) -> usize
+	{
+		self.pending.len()
+	}
+
+	pub fn has_pending(&self) -> bool
+	{
+		!self.pending.is_empty()
+	}
+
+	/// Write as much of `data` as we can right now, and queue the rest.
+	pub fn write(&mut self, data: &Data) -> IoResult<()>
+	{
+		if self.has_pending() {
+			// Gotta keep things in order.
+			self.pending.extend_from_slice(data);
+			return Ok(());
+		}
+
+		let written = write_until_block(&mut self.file, data)?;
+		if written < data.len() {
+			trace!("queueing {} bytes that would block", data.len() - written);
+			self.pending.extend_from_slice(&data[written..]);
+			self.update_interest();
+		}
+
+		Ok(())
+	}
+
+	/// Write as much queued data as we can right now.
+	pub fn flush_pending(&mut self) -> IoResult<()>
+	{
+		let written = write_until_block(&mut self.file, &self.pending)?;
+		self.pending.drain(..written);
+		self.update_interest();
+
+		Ok(())
+	}
+}
+
+/// Implementation details.
+impl QueuedWriter
+{
+	fn update_interest(&self)
+	{
+		if let Some(interest) = &self.interest {
+			interest.set_write(self.has_pending());
+		}
+	}
+}
+
+/// Write until everything has been written or `std::io::ErrorKind::WouldBlock` is returned,
+/// and return how many bytes were written.
+fn write_until_block(file: &mut File, mut data: &Data) -> IoResult<usize>
+{
+	let total = data.len();
+
+	while !data.is_empty() {
+		match file.write(data) {
+			Ok(0) => {
+				return Err(IoErrorKind::WriteZero.into());
+			},
+			Ok(count) => {
+				data = &data[count..];
+			},
+			Err(e) if e.kind() == IoErrorKind::WouldBlock => {
+				break;
+			},
+			Err(e) if e.kind() == IoErrorKind::Interrupted => {
+				continue;
+			},
+			Err(e) => {
+				return Err(e);
+			},
+		}
+	}
+
+	Ok(total - data.len())
+}
@@ -0,0 +1,179 @@
+//! Runs the floatty binary on small commands and checks how it reports what they did.
+
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Output, Stdio};
+
+mod common;
+use common::floatty;
+
+fn run(args: &[&str]) -> Output
+{
+	floatty()
+		.args(args)
+		.stdin(Stdio::null())
+		.output()
+		.expect("running floatty")
+}
+
+/// What the child printed for `echo $TERM`, as floatty set it up with `args` and `term` as our own.
+fn child_term(args: &[&str], term: Option<&str>) -> String
+{
+	let mut command = floatty();
+	match term {
+		Some(term) => command.env("TERM", term),
+		None => command.env_remove("TERM"),
+	};
+	let output = command
+		.args(args)
+		.args(["--", "sh", "-c", "echo $TERM"])
+		.stdin(Stdio::null())
+		.output()
+		.expect("running floatty");
+	assert!(output.status.success());
+
+	String::from_utf8(output.stdout).unwrap().trim_end().to_owned()
+}
+
+#[test]
+fn missing_program()
+{
+	let output = run(&["--", "/nonexistent/program"]);
+	assert_eq!(output.status.code(), Some(127));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert_eq!(stderr.matches("command not found").count(), 1, "stderr: {stderr}");
+}
+
+#[test]
+fn not_executable()
+{
+	let output = run(&["--", "/dev/null"]);
+	assert_eq!(output.status.code(), Some(126));
+}
+
+#[test]
+fn exit_code()
+{
+	assert_eq!(run(&["--", "sh", "-c", "exit 7"]).status.code(), Some(7));
+	assert_eq!(run(&["--", "true"]).status.code(), Some(0));
+}
+
+#[test]
+fn killed_by_signal()
+{
+	// Like a shell, a child killed by a signal is 128 plus its number.
+	let output = run(&["--", "sh", "-c", "kill -TERM $$"]);
+	assert_eq!(output.status.code(), Some(128 + libc::SIGTERM));
+}
+
+#[test]
+fn term()
+{
+	assert_eq!(child_term(&[], None), "xterm-256color");
+	assert_eq!(child_term(&[], Some("vt100")), "vt100");
+	assert_eq!(child_term(&["--term", "dumb"], Some("vt100")), "dumb");
+	// --term wins over --env, wherever they are.
+	assert_eq!(child_term(&["--term", "dumb", "--env", "TERM=vt100"], None), "dumb");
+}
+
+#[test]
+fn output_before_exit()
+{
+	// The child is often gone before we've read everything it wrote, so try a few times.
+	for _ in 0..20 {
+		let output = run(&["--", "echo", "hello"]);
+		assert!(output.status.success());
+		assert_eq!(output.stdout, b"hello\r\n");
+	}
+}
+
+/// The hex signal mask on the `field` line of the child's `/proc/self/status`.
+fn child_sigmask(output: &Output, field: &str) -> u64
+{
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let mask = stdout
+		.lines()
+		.find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+		.unwrap_or_else(|| panic!("no {field} in child's status: {stdout}"));
+
+	u64::from_str_radix(mask.trim(), 16).unwrap()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn child_signals_reset()
+{
+	let mut command = floatty();
+	command
+		.args(["--", "grep", "-E", "^Sig(Blk|Ign):", "/proc/self/status"])
+		.stdin(Stdio::null());
+	// Like a library caller that has signals of its own blocked.
+	unsafe {
+		command.pre_exec(|| {
+			let mut set: libc::sigset_t = std::mem::zeroed();
+			libc::sigemptyset(&mut set);
+			libc::sigaddset(&mut set, libc::SIGUSR1);
+			libc::sigprocmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+			Ok(())
+		});
+	}
+	let output = command.output().expect("running floatty");
+	assert!(output.status.success());
+
+	assert_eq!(child_sigmask(&output, "SigBlk"), 0, "child inherited blocked signals");
+	let sigpipe = 1 << (libc::SIGPIPE - 1);
+	assert_eq!(child_sigmask(&output, "SigIgn") & sigpipe, 0, "child inherited ignored SIGPIPE");
+}
+
+/// A path for `name` in the temporary directory that no other test process is using.
+fn temp_path(name: &str) -> PathBuf
+{
+	std::env::temp_dir().join(format!("floatty-test-{}-{name}", std::process::id()))
+}
+
+/// A megabyte of `a`s, with nothing the terminal would translate.
+const LARGE_OUTPUT: &str = "head -c 1000000 /dev/zero | tr '\\0' a";
+
+#[test]
+fn large_output()
+{
+	let log = temp_path("large-output.log");
+	let output = run(&["--log", log.to_str().unwrap(), "--", "sh", "-c", LARGE_OUTPUT]);
+	let logged = fs::read(&log).expect("reading log");
+	fs::remove_file(&log).unwrap();
+
+	assert!(output.status.success());
+	assert_eq!(output.stdout.len(), 1_000_000);
+	assert!(output.stdout.iter().all(|&byte| byte == b'a'));
+	assert_eq!(logged, output.stdout);
+}
+
+#[test]
+fn max_output()
+{
+	let log = temp_path("max-output.log");
+	let output = run(&[
+		"--log", log.to_str().unwrap(), "--max-output", "1000", "--", "sh", "-c", LARGE_OUTPUT,
+	]);
+	let logged = fs::read(&log).expect("reading log");
+	fs::remove_file(&log).unwrap();
+
+	// Only the log is cut short.
+	assert!(output.status.success());
+	assert_eq!(output.stdout.len(), 1_000_000);
+	assert_eq!(logged, output.stdout[..1000]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn child_fds()
+{
+	// Nothing of ours, like the poller's sources or the PTY controller, should leak into the
+	// program; only its stdio.
+	let output = run(&["--", "sh", "-c", "ls /proc/$$/fd"]);
+	assert!(output.status.success());
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	let fds: Vec<&str> = stdout.split_whitespace().collect();
+	assert_eq!(fds, ["0", "1", "2"]);
+}
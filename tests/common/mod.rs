@@ -0,0 +1,69 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate only uses some of these.
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::mem;
+use std::os::fd::AsFd;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use floatty::fdops::FdOps;
+use floatty::poller::NonblockingRead;
+use floatty::pty::RawMode;
+use floatty::{open_pty_pair, DataBuf, OpenptControl};
+
+/// How long to wait for data to make it through a PTY before giving up.
+pub const PTY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A fresh PTY pair as files: the controller, which is `O_NONBLOCK`, and the terminal child,
+/// which is left blocking.
+pub fn open_pair() -> (File, File)
+{
+	let (controller, child) = open_pty_pair(OpenptControl::BecomeNonControllingTerminal)
+		.expect("opening PTY pair");
+	controller.set_nonblocking();
+
+	(File::from(controller), File::from(child))
+}
+
+/// Same as [`open_pair()`], but with the terminal child in raw mode, so the line discipline
+/// passes bytes through as they are.
+pub fn open_raw_pair() -> (File, File)
+{
+	let (controller, child) = open_pair();
+	let raw = RawMode::enable(child.as_fd()).expect("putting terminal child into raw mode");
+	// Nothing else uses this PTY, so there's nothing to restore it for.
+	mem::forget(raw);
+
+	(controller, child)
+}
+
+/// Read from the nonblocking `reader` until it's given us at least `len` bytes, or
+/// [`PTY_TIMEOUT`] passes, and return everything it gave us.
+pub fn read_at_least(reader: &mut File, len: usize) -> DataBuf
+{
+	let deadline = Instant::now() + PTY_TIMEOUT;
+	let mut data = DataBuf::new();
+	while data.len() < len && Instant::now() < deadline {
+		data.extend(reader.read_until_block().expect("reading until block"));
+		thread::sleep(Duration::from_millis(10));
+	}
+
+	data
+}
+
+/// Give the line discipline a moment to move data between the two sides, for checking that
+/// something *doesn't* come through.
+pub fn settle()
+{
+	thread::sleep(Duration::from_millis(50));
+}
+
+/// A command for running the floatty binary itself.
+pub fn floatty() -> Command
+{
+	Command::new(env!("CARGO_BIN_EXE_floatty"))
+}
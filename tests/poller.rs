@@ -0,0 +1,147 @@
+//! [`Poller`] on plain pipes, which don't need a terminal or a child.
+
+use std::io::{self, PipeReader, PipeWriter, Write};
+use std::ops::ControlFlow;
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::time::Duration;
+
+use floatty::fdops::FdOps;
+use floatty::poller::{PollInterest, Poller};
+use floatty::DataBuf;
+
+/// A pipe whose reading end is `O_NONBLOCK`, ready to go in a [`Poller`].
+fn nonblocking_pipe() -> (PipeReader, PipeWriter)
+{
+	let (reader, writer) = io::pipe().expect("opening pipe");
+	reader.as_fd().set_nonblocking();
+
+	(reader, writer)
+}
+
+#[test]
+fn data_left_after_break()
+{
+	let (first, mut first_writer) = nonblocking_pipe();
+	let (second, mut second_writer) = nonblocking_pipe();
+	let fds = [first.as_raw_fd(), second.as_raw_fd()];
+	let mut poller = Poller::with_sources([PollInterest::read(first), PollInterest::read(second)])
+		.expect("creating poller");
+
+	first_writer.write_all(b"first").unwrap();
+	second_writer.write_all(b"second").unwrap();
+
+	// Both are ready by now, so whichever comes first, the other is left until the next call.
+	// Give up rather than hang if it doesn't come.
+	poller.set_timeout(Some(Duration::from_secs(1)));
+	let mut got: Vec<(RawFd, DataBuf)> = Vec::new();
+	for _ in fds {
+		poller.each_with_idle(
+			&mut got,
+			|got, event, data| {
+				got.push((event.key, data.to_vec()));
+				ControlFlow::Break(())
+			},
+			|_got| ControlFlow::Break(()),
+		).expect("polling");
+	}
+
+	got.sort();
+	let mut expected = vec![(fds[0], b"first".to_vec()), (fds[1], b"second".to_vec())];
+	expected.sort();
+	assert_eq!(got, expected);
+}
+
+#[test]
+fn many_sources()
+{
+	const COUNT: usize = 12;
+	let (readers, mut writers): (Vec<_>, Vec<_>) = (0..COUNT).map(|_| nonblocking_pipe()).unzip();
+	let interests = readers
+		.into_iter()
+		.enumerate()
+		.map(|(index, reader)| PollInterest::read(reader).with_key(index));
+	let mut poller = Poller::with_sources(interests).expect("creating poller");
+
+	// Backwards, so the order they're ready in isn't the order they were added in.
+	for (index, writer) in writers.iter_mut().enumerate().rev() {
+		write!(writer, "pipe {index}").unwrap();
+	}
+
+	poller.set_timeout(Some(Duration::from_secs(1)));
+	let mut got: Vec<DataBuf> = vec![DataBuf::new(); COUNT];
+	poller.each_with_idle(
+		&mut got,
+		|got, event, data| {
+			got[event.key].extend_from_slice(data);
+			let done = got
+				.iter()
+				.enumerate()
+				.all(|(index, data)| *data == format!("pipe {index}").as_bytes());
+			if done {
+				ControlFlow::Break(())
+			} else {
+				ControlFlow::Continue(())
+			}
+		},
+		|_got| ControlFlow::Break(()),
+	).expect("polling");
+
+	for (index, data) in got.iter().enumerate() {
+		assert_eq!(data, format!("pipe {index}").as_bytes(), "from pipe {index}");
+	}
+}
+
+#[test]
+fn drop_closes_sources()
+{
+	let (reader, mut writer) = nonblocking_pipe();
+	let mut poller = Poller::with_sources([PollInterest::read(reader)]).expect("creating poller");
+	writer.write_all(b"data").unwrap();
+	poller.each(|_event, _data| ControlFlow::Break(())).expect("polling");
+
+	// Still ours until the poller goes away.
+	writer.write_all(b"more").expect("writing while the poller still has the reader");
+	drop(poller);
+	let err = writer.write_all(b"after").expect_err("reader should be closed with the poller");
+	assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn blocking_eof_keeps_polling()
+{
+	let (closed, closed_writer) = io::pipe().expect("opening pipe");
+	let (open, mut open_writer) = nonblocking_pipe();
+	let closed_fd = closed.as_raw_fd();
+	let interests = [PollInterest::read_blocking(closed), PollInterest::read(open)];
+	let mut poller = Poller::with_sources(interests).expect("creating poller");
+
+	drop(closed_writer);
+	open_writer.write_all(b"still here").unwrap();
+
+	// Only a PTY's end should end the loop, so the other pipe still gets read after the closed
+	// one's end-of-file has been handed out.
+	poller.set_timeout(Some(Duration::from_secs(1)));
+	let mut closed_eof = false;
+	let mut got = DataBuf::new();
+	poller.each_with_idle(
+		&mut (),
+		|_, event, data| {
+			if event.key == closed_fd {
+				assert!(data.is_empty(), "read {data:?} from a pipe with no writer");
+				closed_eof |= event.eof;
+			} else {
+				assert!(!event.eof, "nonblocking pipe reported end-of-file");
+				got.extend_from_slice(data);
+			}
+			if closed_eof && got == b"still here" {
+				ControlFlow::Break(())
+			} else {
+				ControlFlow::Continue(())
+			}
+		},
+		|_| ControlFlow::Break(()),
+	).expect("polling");
+
+	assert!(closed_eof, "no end-of-file from the closed pipe");
+	assert_eq!(got, b"still here");
+}
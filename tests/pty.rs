@@ -0,0 +1,328 @@
+//! The PTY lifecycle, through real PTY pairs from [`open_pty_pair()`] and [`openpt()`], which
+//! don't need us to be in a terminal ourselves.
+
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::path::Path;
+
+use nix::fcntl::OFlag;
+use nix::unistd::Pid;
+
+use floatty::fdops::FdOps;
+use floatty::poller::NonblockingRead;
+use floatty::pty::{
+	get_termios,
+	getwinsz,
+	grantpt,
+	open_peer,
+	ptsname,
+	set_echo,
+	setwinsz,
+	sync_winsize,
+	unlockpt,
+	OpenPeerError,
+	Packet,
+	SetForegroundPgrpError,
+	WinSize,
+};
+use floatty::{open_pty_pair, openpt, openpt_with_flags, OpenptControl, PtyMaster};
+
+mod common;
+use common::{open_pair, open_raw_pair, read_at_least, settle, PTY_TIMEOUT};
+
+#[test]
+fn child_to_controller()
+{
+	let (mut controller, child) = open_raw_pair();
+
+	(&child).write_all(b"from the child").unwrap();
+	let data = read_at_least(&mut controller, b"from the child".len());
+	assert_eq!(data, b"from the child");
+
+	// Nothing left, so this should come back empty rather than block.
+	assert!(controller.read_until_block().unwrap().is_empty());
+}
+
+#[test]
+fn controller_to_child()
+{
+	let (mut controller, child) = open_raw_pair();
+
+	controller.write_all(b"to the child").unwrap();
+	let mut buffer = [0u8; 64];
+	let count = (&child).read(&mut buffer).expect("reading from terminal child");
+	assert_eq!(&buffer[..count], b"to the child");
+}
+
+#[test]
+fn nothing_to_read()
+{
+	let (mut controller, _child) = open_pair();
+
+	settle();
+	assert!(controller.read_until_block().unwrap().is_empty());
+}
+
+#[test]
+fn winsize_round_trip()
+{
+	let (controller, child) = open_pair();
+
+	let size = WinSize::from_cells(100, 40, 0, 0);
+	setwinsz(controller.as_fd(), size.into()).expect("setting window size");
+	let got = WinSize::from(getwinsz(child.as_fd()).expect("getting window size from child"));
+	assert_eq!(got, size);
+	let got = WinSize::from(getwinsz(controller.as_fd()).expect("getting window size back"));
+	assert_eq!(got, size);
+	assert!(!got.has_pixels());
+	assert_eq!(got.cell_pixels(), None);
+}
+
+#[test]
+fn pixel_size_round_trip()
+{
+	let (controller, child) = open_pair();
+
+	let size = WinSize::from_cells(100, 40, 9, 18);
+	assert_eq!((size.xpixel, size.ypixel), (900, 720));
+	setwinsz(controller.as_fd(), size.into()).expect("setting window size");
+	let got = WinSize::from(getwinsz(child.as_fd()).expect("getting window size from child"));
+	assert_eq!(got, size);
+	assert!(got.has_pixels());
+	assert_eq!(got.cell_pixels(), Some((9, 18)));
+}
+
+#[test]
+fn sync_winsize_between_pairs()
+{
+	let (from_controller, from_child) = open_pair();
+	let (to_controller, to_child) = open_pair();
+
+	let size = WinSize::from_cells(120, 50, 8, 16);
+	setwinsz(from_controller.as_fd(), size.into()).expect("setting window size");
+	// From one's terminal child to the other's controller, like following our own terminal.
+	sync_winsize(from_child.as_fd(), to_controller.as_fd()).expect("syncing window size");
+	let synced = WinSize::from(getwinsz(to_child.as_fd()).expect("getting synced window size"));
+	assert_eq!(synced, size);
+}
+
+#[test]
+fn ptsname_opens()
+{
+	let controller = openpt(OpenptControl::BecomeNonControllingTerminal).expect("opening PTY");
+	grantpt(controller.as_fd()).expect("granting PTY");
+	unlockpt(controller.as_fd()).expect("unlocking PTY");
+
+	let path = ptsname(controller.as_fd()).expect("getting path of terminal child");
+	let child = open_peer(&path, OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_CLOEXEC)
+		.expect("opening terminal child by path");
+	assert!(child.is_terminal());
+	assert_eq!(child.tty_name().as_deref(), Some(&*path));
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn pty_peer_opens()
+{
+	use floatty::pty::pty_peer;
+
+	let controller = openpt(OpenptControl::BecomeNonControllingTerminal).expect("opening PTY");
+	grantpt(controller.as_fd()).expect("granting PTY");
+	unlockpt(controller.as_fd()).expect("unlocking PTY");
+
+	let path = ptsname(controller.as_fd()).expect("getting path of terminal child");
+	let child = pty_peer(controller.as_fd(), OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_CLOEXEC)
+		.expect("opening terminal child with TIOCGPTPEER");
+	assert!(child.is_terminal());
+	assert_eq!(child.tty_name().as_deref(), Some(&*path));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pty_number_matches_ptsname()
+{
+	use floatty::pty::pty_number;
+
+	let (controller, _child) = open_pair();
+	let path = ptsname(controller.as_fd()).expect("getting path of terminal child");
+	let number = pty_number(controller.as_fd()).expect("getting number of terminal child");
+	assert_eq!(path, Path::new("/dev/pts").join(number.to_string()).into_boxed_path());
+
+	let controller = PtyMaster::from(OwnedFd::from(controller));
+	assert_eq!(controller.number().unwrap(), number);
+
+	// Not a PTY controller at all.
+	let (reader, _writer) = io::pipe().expect("opening pipe");
+	assert!(pty_number(reader.as_fd()).is_err());
+}
+
+#[test]
+fn open_peer_missing()
+{
+	let missing = open_peer(Path::new("/dev/pts/no-such-terminal"), OFlag::O_RDWR);
+	assert_eq!(missing.unwrap_err(), OpenPeerError::NotFound);
+}
+
+#[test]
+fn tty_name()
+{
+	let (controller, child) = open_pair();
+
+	assert!(child.is_tty());
+	let path = ptsname(controller.as_fd()).expect("getting path of terminal child");
+	assert_eq!(child.tty_name().as_deref(), Some(&*path));
+
+	let (reader, _writer) = io::pipe().expect("opening pipe");
+	assert!(!reader.is_tty());
+	assert_eq!(reader.tty_name(), None);
+}
+
+/// Read packets from `pty` until one of them matches `want`, or [`PTY_TIMEOUT`] passes.
+fn wait_for_packet(pty: &PtyMaster, want: impl Fn(&Packet) -> bool) -> Vec<Packet>
+{
+	let deadline = Instant::now() + PTY_TIMEOUT;
+	let mut packets = Vec::new();
+	while !packets.iter().any(&want) && Instant::now() < deadline {
+		packets.extend(pty.read_packets_until_block().expect("reading packets"));
+		thread::sleep(Duration::from_millis(10));
+	}
+
+	packets
+}
+
+#[test]
+fn packet_mode()
+{
+	let (controller, child) = open_raw_pair();
+	let controller = PtyMaster::from(OwnedFd::from(controller));
+	controller.set_packet_mode(true).expect("turning packet mode on");
+
+	(&child).write_all(b"packet").unwrap();
+	let got = wait_for_packet(&controller, |packet| matches!(packet, Packet::Data(_)));
+	assert!(got.contains(&Packet::Data(b"packet".to_vec())), "no data packet in {got:?}");
+
+	// Raw mode turned flow control off, so turning it back on gets reported.
+	controller.set_flow_control(true).expect("turning flow control on");
+	let flow_control_on = |packet: &Packet| {
+		matches!(packet, Packet::Control(control) if control.flow_control() == Some(true))
+	};
+	let got = wait_for_packet(&controller, flow_control_on);
+	assert!(got.iter().any(flow_control_on), "no flow control packet in {got:?}");
+}
+
+#[test]
+fn echo()
+{
+	let (controller, child) = open_pair();
+	let controller = PtyMaster::from(OwnedFd::from(controller));
+	controller.set_echo(true).expect("turning echo on");
+	assert!(controller.echo().unwrap());
+	let mut controller = File::from(OwnedFd::from(controller));
+
+	controller.write_all(b"shown\n").unwrap();
+	let echoed = read_at_least(&mut controller, b"shown\r\n".len());
+	assert_eq!(echoed, b"shown\r\n");
+	let mut buffer = [0u8; 64];
+	let count = (&child).read(&mut buffer).expect("reading echoed input");
+	assert_eq!(&buffer[..count], b"shown\n");
+}
+
+#[test]
+fn no_echo()
+{
+	let (mut controller, child) = open_pair();
+	set_echo(controller.as_fd(), false).expect("turning echo off");
+	let lflag = get_termios(child.as_fd()).expect("getting termios").c_lflag;
+	assert_eq!(lflag & libc::ECHO, 0, "ECHO still set");
+
+	controller.write_all(b"hidden\n").unwrap();
+	// Once the child has it, the line discipline is done with it, echo and all.
+	let mut buffer = [0u8; 64];
+	let count = (&child).read(&mut buffer).expect("reading unechoed input");
+	assert_eq!(&buffer[..count], b"hidden\n");
+	settle();
+	assert!(controller.read_until_block().unwrap().is_empty(), "input was echoed with echo off");
+}
+
+#[test]
+fn flow_control()
+{
+	let (controller, child) = open_pair();
+	let controller = PtyMaster::from(OwnedFd::from(controller));
+	let flow_bits = libc::IXON | libc::IXOFF;
+
+	controller.set_flow_control(false).expect("turning flow control off");
+	assert!(!controller.flow_control().unwrap());
+	let iflag = get_termios(child.as_fd()).expect("getting termios").c_iflag;
+	assert_eq!(iflag & flow_bits, 0, "flow control bits still set");
+
+	controller.set_flow_control(true).expect("turning flow control on");
+	assert!(controller.flow_control().unwrap());
+	let iflag = get_termios(child.as_fd()).expect("getting termios").c_iflag;
+	assert_eq!(iflag & flow_bits, flow_bits, "flow control bits not set");
+}
+
+#[test]
+fn foreground_pgrp()
+{
+	let (controller, child) = open_pair();
+	let controller = PtyMaster::from(OwnedFd::from(controller));
+	let mut command = Command::new("sleep");
+	command.arg("5").stdin(Stdio::from(child)).stdout(Stdio::null());
+	// Make the terminal child the controlling terminal of a new session, like Session does.
+	unsafe {
+		command.pre_exec(|| {
+			if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+				return Err(io::Error::last_os_error());
+			}
+			Ok(())
+		});
+	}
+	let mut sleeper = command.spawn().expect("spawning sleep");
+	let sleeper_pid = Pid::from_raw(sleeper.id() as i32);
+
+	// As the session leader, it's in the foreground until it says otherwise.
+	assert_eq!(controller.foreground_pgrp().expect("getting foreground pgrp"), sleeper_pid);
+	// And we're not in its session, so we can't put ourselves there.
+	assert_eq!(
+		controller.set_foreground_pgrp(Pid::this()),
+		Err(SetForegroundPgrpError::NotATty),
+	);
+	assert_eq!(controller.foreground_pgrp().unwrap(), sleeper_pid);
+
+	sleeper.kill().unwrap();
+	sleeper.wait().unwrap();
+}
+
+/// Whether a program we spawn can see `fd`.
+fn inherited(fd: BorrowedFd) -> bool
+{
+	Command::new("sh")
+		.arg("-c")
+		.arg(format!("test -e /dev/fd/{}", fd.as_raw_fd()))
+		.status()
+		.expect("running sh")
+		.success()
+}
+
+#[test]
+fn close_on_exec()
+{
+	let (controller, child) = open_pty_pair(OpenptControl::BecomeNonControllingTerminal)
+		.expect("opening PTY pair");
+	assert!(!inherited(controller.as_fd()), "PTY controller was inherited");
+	assert!(!inherited(child.as_fd()), "terminal child was inherited");
+
+	let controller = openpt(OpenptControl::BecomeNonControllingTerminal).expect("opening PTY");
+	assert!(!inherited(controller.as_fd()), "PTY controller was inherited");
+
+	// Unless we ask for it.
+	let inheritable = openpt_with_flags(OpenptControl::BecomeNonControllingTerminal, OFlag::empty())
+		.expect("opening inheritable PTY");
+	assert!(inherited(inheritable.as_fd()), "PTY controller without O_CLOEXEC wasn't inherited");
+}
@@ -0,0 +1,76 @@
+//! Many short [`Session`]s in this process, checking that none of them leave file descriptors
+//! behind, however they end.
+//!
+//! This is `harness = false`, so everything runs on the main thread, with no test threads around
+//! to receive the signals the parent loop is waiting for.
+
+use std::fs;
+use std::time::Duration;
+
+use floatty::parent::{CaptureLimit, ChildOutcome, OverLimit, Timeout};
+use floatty::Session;
+
+const ROUNDS: usize = 50;
+
+fn open_fds() -> usize
+{
+	fs::read_dir("/proc/self/fd").expect("listing our fds").count()
+}
+
+/// One of each way a session can end, from a clean exit to the parent loop bailing out with `?`.
+fn run_sessions()
+{
+	let outcome = Session::new("true").stream(false).spawn().unwrap().wait().unwrap();
+	assert_eq!(outcome, ChildOutcome::Exited(0));
+
+	let outcome = Session::new("sh")
+		.args(["-c", "echo out; exit 3"])
+		.stream(false)
+		.spawn()
+		.unwrap()
+		.wait()
+		.unwrap();
+	assert_eq!(outcome, ChildOutcome::Exited(3));
+
+	let outcome = Session::new("/nonexistent/program")
+		.stream(false)
+		.spawn()
+		.unwrap()
+		.wait()
+		.unwrap();
+	assert_eq!(outcome, ChildOutcome::Exited(127));
+
+	let outcome = Session::new("sleep")
+		.arg("10")
+		.stream(false)
+		.timeout(Duration::from_millis(20))
+		.spawn()
+		.unwrap()
+		.wait()
+		.unwrap();
+	assert_eq!(outcome, ChildOutcome::TimedOut(Timeout::Total));
+
+	let result = Session::new("sh")
+		.args(["-c", "yes | head -c 100000"])
+		.stream(false)
+		.capture_limit(CaptureLimit { bytes: 100, over_limit: OverLimit::Fail })
+		.spawn()
+		.unwrap()
+		.wait_and_capture();
+	assert!(result.is_err(), "capture over its limit didn't fail");
+}
+
+fn main()
+{
+	// The first round can open things that stay open for good, like the logger's, so only
+	// count from after it.
+	run_sessions();
+	let before = open_fds();
+	for _ in 0..ROUNDS {
+		run_sessions();
+	}
+	let after = open_fds();
+
+	assert_eq!(after, before, "fds open before and after {ROUNDS} rounds of sessions");
+	println!("sessions: {ROUNDS} rounds, {before} fds before and after");
+}
@@ -0,0 +1,57 @@
+//! [`terminate_child()`] on children that do and don't listen to `SIGTERM`.
+
+use std::io::{BufRead, BufReader};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+
+use floatty::parent::terminate_child;
+
+const GRACE: Duration = Duration::from_millis(300);
+
+/// Start `script` in its own process group, like a session leader would be, and wait for it to
+/// print its first line, so we know it's done setting up.
+///
+/// The [`Child`](std::process::Child) itself is dropped without waiting, since reaping it is
+/// [`terminate_child()`]'s job.
+#[allow(clippy::zombie_processes)]
+fn spawn_ready(script: &str) -> Pid
+{
+	let mut child = Command::new("sh")
+		.args(["-c", script])
+		.process_group(0)
+		.stdout(Stdio::piped())
+		.spawn()
+		.expect("spawning sh");
+	let mut line = String::new();
+	BufReader::new(child.stdout.take().unwrap())
+		.read_line(&mut line)
+		.expect("waiting for child to be ready");
+	assert_eq!(line, "ready\n");
+
+	Pid::from_raw(child.id() as i32)
+}
+
+#[test]
+fn exits_on_sigterm()
+{
+	let child = spawn_ready("echo ready; exec sleep 10");
+	let started = Instant::now();
+	let status = terminate_child(child, Duration::from_secs(5)).expect("terminating child");
+	assert_eq!(status, WaitStatus::Signaled(child, Signal::SIGTERM, false));
+	assert!(started.elapsed() < Duration::from_secs(5), "waited out the grace period anyway");
+}
+
+#[test]
+fn ignores_sigterm()
+{
+	let child = spawn_ready("trap '' TERM; echo ready; while :; do sleep 0.05; done");
+	let started = Instant::now();
+	let status = terminate_child(child, GRACE).expect("terminating child");
+	assert_eq!(status, WaitStatus::Signaled(child, Signal::SIGKILL, false));
+	assert!(started.elapsed() >= GRACE, "SIGKILLed before the grace period was up");
+}